@@ -6,15 +6,20 @@
 use clap::Parser;
 use opentelemetry_sdk::trace::SdkTracerProvider;
 use opentelemetry::trace::TracerProvider;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::io::{stdin, stdout, BufReader, BufWriter};
+use tokio::io::{stdin, stdout, BufReader};
 
 use cli::{
     info,
     json_rpc::{new_json_rpc, start_json_rpc},
     log::{self, Level},
-    services::fileio::{start_fileio_service, handle_fileio_request},
+    rpc::RequestParams,
+    services::fileio::{
+        handle_fileio_request,
+        sandbox::{AccessMode, Sandbox, SandboxRoot},
+        start_fileio_service, FileIORequestMessage, FileIOServiceConfig,
+    },
 };
 
 #[derive(Parser)]
@@ -23,6 +28,15 @@ use cli::{
 struct Args {
     #[arg(long, default_value = "info")]
     log_level: Level,
+
+    /// Root the service may read and write under. Repeatable; when no roots
+    /// are given, any path is allowed.
+    #[arg(long = "allow-read-write", value_name = "PATH")]
+    read_write_roots: Vec<PathBuf>,
+
+    /// Root the service may only read under. Repeatable.
+    #[arg(long = "allow-read-only", value_name = "PATH")]
+    read_only_roots: Vec<PathBuf>,
 }
 
 #[tokio::main]
@@ -38,28 +52,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     info!(logger, "Starting FileIO service");
 
-    // Create the IPC sink for sending responses
+    // Create the IPC sink for sending responses. Messages are queued synchronously
+    // so that streamed chunks reach the client in the order they were read,
+    // one per line like the JSON-RPC replies they are interleaved with.
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
     let ipc_sink = Arc::new(move |message: String| {
-        let tx = tx.clone();
-        tokio::spawn(async move {
-            let _ = tx.send(message.into_bytes());
-        });
+        let mut line = message.into_bytes();
+        line.push(b'\n');
+        let _ = tx.send(line);
     });
 
+    let roots: Vec<SandboxRoot> = args
+        .read_write_roots
+        .into_iter()
+        .map(|path| SandboxRoot { path, mode: AccessMode::ReadWrite })
+        .chain(args.read_only_roots.into_iter().map(|path| SandboxRoot { path, mode: AccessMode::ReadOnly }))
+        .collect();
+    let sandbox = if roots.is_empty() {
+        None
+    } else {
+        info!(logger, "Restricting FileIO access to {} root(s)", roots.len());
+        Some(Sandbox::new(roots)?)
+    };
+
     // Start the FileIO service
-    let service = start_fileio_service(ipc_sink).await;
-    let service = Arc::new(Mutex::new(service));
+    let service = start_fileio_service(ipc_sink, FileIOServiceConfig { sandbox, ..Default::default() }).await;
 
-    // Set up JSON-RPC
+    // Set up JSON-RPC. The builder shares the service with every handler as
+    // an Arc, so requests are not serialized behind a single lock.
     let builder = new_json_rpc();
-    let mut methods = builder.methods(service.clone());
+    let mut methods = builder.methods(service);
 
-    // Register the FileIO request handler
-    methods.register_async("onFileIORequest", |req, service| {
-        let service = service.clone();
+    // Register the FileIO request handler. Clients send the FileIO request
+    // itself as the call's params.
+    methods.register_async("onFileIORequest", |message: FileIORequestMessage, service| {
         async move {
-            handle_fileio_request(&service, &req).await.map_err(|e| cli::util::errors::AnyError::WrappedError(cli::util::errors::wrapdbg(e, "fileio request failed")))
+            handle_fileio_request(&service, &RequestParams { params: message }).await.map_err(|e| cli::util::errors::AnyError::WrappedError(cli::util::errors::wrapdbg(e, "fileio request failed")))
         }
     });
 
@@ -68,9 +96,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Set up IPC channels
     let (shutdown_rx, shutdown_tx) = cli::util::sync::new_barrier::<()>();
 
-    // Start the JSON-RPC loop with stdin/stdout
+    // Start the JSON-RPC loop with stdin/stdout. Writes are not buffered,
+    // since the loop never flushes and clients wait on every line.
     let read = BufReader::new(stdin());
-    let write = BufWriter::new(stdout());
+    let write = stdout();
 
     let join_handle = tokio::spawn(async move {
         start_json_rpc(dispatcher, read, write, rx, shutdown_rx).await
@@ -7,7 +7,7 @@ use cli::log::{Level, Logger};
 use cli::services::ipc::{start_node_ipc_server_with, Base64Serialization};
 use cli::services::lifecycle::ParentMonitor;
 use cli::services::watcher::service::create_watcher_service;
-use cli::services::watcher::types::WatchRequest;
+use cli::services::watcher::types::{UnwatchRequest, WatchRequest};
 use cli::json_rpc::JsonRpcSerializer;
 use cli::rpc::RpcBuilder;
 use opentelemetry::trace::TracerProvider;
@@ -52,6 +52,11 @@ async fn start_ipc_server() -> Result<(), Box<dyn std::error::Error + Send + Syn
         let mut service = context.lock().await;
         service.watch(requests).await.map_err(|e| cli::util::errors::AnyError::WrappedError(cli::util::errors::wrapdbg(e, "watch failed")))
     });
+    // unwatch method - stops the watcher registered for a single path
+    method_builder.register_async("unwatch", |request: UnwatchRequest, context| async move {
+        let service = context.lock().await;
+        service.unwatch(&request.id).await.map_err(|e| cli::util::errors::AnyError::WrappedError(cli::util::errors::wrapdbg(e, "unwatch failed")))
+    });
     // setVerboseLogging method
     method_builder.register_async("setVerboseLogging", |enabled: bool, context| async move {
         let service = context;
@@ -53,7 +53,9 @@ pub async fn start_json_rpc<C: Send + Sync + 'static, S: Clone>(
 	let (write_tx, mut write_rx) = mpsc::channel::<Vec<u8>>(8);
 	let mut read = BufReader::new(read);
 
-	let mut read_buf = String::new();
+	// Bytes of the current line. `read_until` keeps what it has read in the
+	// buffer when a write wins the select, where `read_line` would drop it.
+	let mut read_buf = Vec::new();
 	let shutdown_fut = shutdown_rx.wait();
 	pin!(shutdown_fut);
 
@@ -66,10 +68,10 @@ pub async fn start_json_rpc<C: Send + Sync + 'static, S: Clone>(
 			Some(w) = msg_rx.recv_msg() => {
 				write.write_all(&w).await?;
 			},
-			n = read.read_line(&mut read_buf) => {
+			n = read.read_until(b'\n', &mut read_buf) => {
 				let r = match n {
 					Ok(0) => return Ok(None),
-					Ok(n) => dispatcher.dispatch(read_buf[..n].as_bytes()),
+					Ok(_) => dispatcher.dispatch(&read_buf),
 					Err(e) => return Err(e)
 				};
 
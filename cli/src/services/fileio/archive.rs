@@ -0,0 +1,452 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! Read-only browsing of zip archives, including `.vsix` files, and of plain
+//! or gzipped tar archives. Entries are addressed as
+//! `archive:/path/to/file.vsix!/extension/package.json`, and the archive
+//! itself as `archive:/path/to/file.vsix!/`. Each archive is indexed once,
+//! from the central directory of a zip or a pass over a tar, and the index is
+//! kept until the archive's mtime or size changes.
+
+use crate::services::fileio::backend::{path_components, ChunkSink, FileSystemBackend};
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::operations::{self, to_millis};
+use crate::services::fileio::types::{
+    CopyRequest, DeleteRequest, DirEntry, FileStat, MkDirRequest, ReadDirRequest, ReadFileRequest, ReadFileResponse,
+    ReadFileStreamRequest, RealPathRequest, RenameRequest, StatRequest, WriteFileRequest,
+};
+use async_trait::async_trait;
+use flate2::read::GzDecoder;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio_util::sync::CancellationToken;
+use zip::ZipArchive;
+
+pub const ARCHIVE_SCHEME: &str = "archive";
+
+/// Archive indexes kept before the least recently used is evicted.
+pub(crate) const MAX_CACHED_ARCHIVES: usize = 64;
+
+/// Entries of a gzipped tar up to this size are kept in its index.
+const MAX_KEPT_ENTRY_SIZE: u64 = 64 * 1024;
+
+/// Bytes of entries kept in the index of one gzipped tar.
+const MAX_KEPT_BYTES: u64 = 1024 * 1024;
+
+/// Entries larger than this can only be streamed, not read whole.
+pub(crate) const MAX_READ_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Where an entry's data is found.
+enum Location {
+    /// A directory that only exists as the parent of other entries.
+    Implied,
+    /// Index of the entry in the zip's central directory.
+    Zip(usize),
+    /// Offset of the entry's data in an uncompressed tar.
+    Tar { offset: u64 },
+    /// Offset of the entry's data in the decompressed stream of a gzipped
+    /// tar, and the data itself if it was small enough to keep. Other
+    /// entries have to be reached by decompressing from the start.
+    TarGz { offset: u64, content: Option<Vec<u8>> },
+}
+
+struct IndexEntry {
+    is_directory: bool,
+    size: u64,
+    mtime: i64,
+    location: Location,
+}
+
+/// The entries of one archive, keyed by their `/`-separated path inside it
+/// with the root as `""`.
+struct ArchiveIndex {
+    archive: PathBuf,
+    archive_mtime: Option<SystemTime>,
+    archive_size: u64,
+    entries: HashMap<String, IndexEntry>,
+    /// Sorted names in each directory.
+    children: HashMap<String, BTreeSet<String>>,
+    /// The parsed central directory, reused for every read from a zip.
+    zip: Option<Mutex<ZipArchive<File>>>,
+}
+
+impl ArchiveIndex {
+    fn open(archive: &Path, metadata: &std::fs::Metadata) -> FileIOResult<Self> {
+        let archive_mtime = metadata.modified().ok();
+        let mut index = ArchiveIndex {
+            archive: archive.to_path_buf(),
+            archive_mtime,
+            archive_size: metadata.len(),
+            entries: HashMap::new(),
+            children: HashMap::new(),
+            zip: None,
+        };
+        index.entries.insert(
+            String::new(),
+            IndexEntry { is_directory: true, size: 0, mtime: archive_mtime.map_or(0, to_millis), location: Location::Implied },
+        );
+
+        let mut file = File::open(archive)?;
+        let mut magic = [0u8; 4];
+        let read = file.read(&mut magic)?;
+        file.seek(SeekFrom::Start(0))?;
+        match &magic[..read] {
+            [b'P', b'K', 3, 4] | [b'P', b'K', 5, 6] => index.read_zip(file)?,
+            [0x1f, 0x8b, ..] => index.read_tar(GzDecoder::new(file), true)?,
+            _ => index.read_tar(file, false)?,
+        }
+        Ok(index)
+    }
+
+    fn read_zip(&mut self, file: File) -> FileIOResult<()> {
+        let mut zip = ZipArchive::new(file).map_err(|e| self.invalid(e))?;
+        for i in 0..zip.len() {
+            let entry = zip.by_index_raw(i).map_err(|e| self.invalid(e))?;
+            // Names that would escape the archive are skipped
+            let Some(name) = entry.enclosed_name() else {
+                continue;
+            };
+            let mtime = entry.last_modified().map_or(0, zip_time_to_millis);
+            self.insert(
+                &name.to_string_lossy(),
+                IndexEntry { is_directory: entry.is_dir(), size: entry.size(), mtime, location: Location::Zip(i) },
+            );
+        }
+        self.zip = Some(Mutex::new(zip));
+        Ok(())
+    }
+
+    fn read_tar<R: Read>(&mut self, reader: R, gzipped: bool) -> FileIOResult<()> {
+        let mut tar = tar::Archive::new(reader);
+        let mut kept = 0;
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let entry_type = entry.header().entry_type();
+            if !entry_type.is_dir() && !entry_type.is_file() {
+                continue;
+            }
+            let name = entry.path()?.to_string_lossy().to_string();
+            if name.split(['/', '\\']).any(|part| part == "..") {
+                continue;
+            }
+
+            let size = entry.size();
+            let mtime = entry.header().mtime().map_or(0, |seconds| seconds as i64 * 1000);
+            let offset = entry.raw_file_position();
+            let location = if !gzipped {
+                Location::Tar { offset }
+            } else if entry_type.is_file() && size <= MAX_KEPT_ENTRY_SIZE && kept + size <= MAX_KEPT_BYTES {
+                // Small files such as manifests are read often, and keeping
+                // them saves decompressing the archive again for each read
+                let mut content = Vec::with_capacity(size as usize);
+                entry.read_to_end(&mut content)?;
+                kept += size;
+                Location::TarGz { offset, content: Some(content) }
+            } else {
+                Location::TarGz { offset, content: None }
+            };
+            self.insert(&name, IndexEntry { is_directory: entry_type.is_dir(), size, mtime, location });
+        }
+        Ok(())
+    }
+
+    /// Adds an entry, and its parent directories unless they have entries of
+    /// their own. Later entries for the same path replace earlier ones, as
+    /// when a tar is extracted.
+    fn insert(&mut self, name: &str, entry: IndexEntry) {
+        let components = path_components(name);
+        if components.is_empty() {
+            return;
+        }
+
+        for depth in 0..components.len() {
+            let parent = components[..depth].join("/");
+            let path = components[..=depth].join("/");
+            self.children.entry(parent).or_default().insert(components[depth].clone());
+            if depth + 1 < components.len() {
+                let mtime = entry.mtime;
+                self.entries
+                    .entry(path)
+                    .or_insert(IndexEntry { is_directory: true, size: 0, mtime, location: Location::Implied });
+            }
+        }
+        self.entries.insert(components.join("/"), entry);
+    }
+
+    fn is_current(&self, metadata: &std::fs::Metadata) -> bool {
+        metadata.modified().ok() == self.archive_mtime && metadata.len() == self.archive_size
+    }
+
+    fn entry(&self, path: &str) -> FileIOResult<&IndexEntry> {
+        self.entries
+            .get(path)
+            .ok_or_else(|| FileIOError::NotFound(format!("{} does not exist", self.display(path))))
+    }
+
+    fn read(&self, path: &str) -> FileIOResult<Vec<u8>> {
+        let size = self.entry(path)?.size;
+        if size > MAX_READ_SIZE {
+            let message = format!("{} is too large to read at once ({} bytes)", self.display(path), size);
+            return Err(io::Error::new(io::ErrorKind::FileTooLarge, message).into());
+        }
+
+        self.with_reader(path, |reader| {
+            // The size comes from the archive's headers, so it is not trusted
+            // further than the archive's own length
+            let mut content = Vec::with_capacity(size.min(self.archive_size) as usize);
+            reader.read_to_end(&mut content)?;
+            Ok(content)
+        })
+    }
+
+    /// Runs `f` with a reader over the data of the file at `path`, limited to
+    /// the entry's size. A zip stays locked until `f` returns.
+    fn with_reader<T>(&self, path: &str, f: impl FnOnce(&mut dyn Read) -> FileIOResult<T>) -> FileIOResult<T> {
+        let entry = self.entry(path)?;
+        if entry.is_directory {
+            return Err(io::Error::new(io::ErrorKind::IsADirectory, format!("{} is a directory", self.display(path))).into());
+        }
+
+        match &entry.location {
+            Location::Implied => f(&mut io::empty()),
+            &Location::Zip(i) => {
+                let mut zip = self.zip.as_ref().expect("zip entries come from a zip").lock().unwrap();
+                let mut reader = zip.by_index(i).map_err(|e| self.invalid(e))?.take(entry.size);
+                f(&mut reader)
+            }
+            &Location::Tar { offset } => {
+                let mut file = File::open(&self.archive)?;
+                file.seek(SeekFrom::Start(offset))?;
+                f(&mut file.take(entry.size))
+            }
+            Location::TarGz { content: Some(kept), .. } => f(&mut kept.as_slice()),
+            &Location::TarGz { offset, content: None } => {
+                // The stream before the entry is skipped without parsing it
+                let mut decoder = GzDecoder::new(File::open(&self.archive)?);
+                io::copy(&mut (&mut decoder).take(offset), &mut io::sink())?;
+                f(&mut decoder.take(entry.size))
+            }
+        }
+    }
+
+    fn stat(&self, path: &str) -> FileIOResult<FileStat> {
+        let entry = self.entry(path)?;
+        Ok(FileStat {
+            size: entry.size,
+            mtime: entry.mtime,
+            ctime: entry.mtime,
+            is_directory: entry.is_directory,
+            is_file: !entry.is_directory,
+            is_symlink: false,
+            permissions: 0o444,
+            readonly: true,
+            mode: None,
+            uid: None,
+            gid: None,
+            ino: None,
+            dev: None,
+            nlink: None,
+            symlink_target: None,
+            dangling: false,
+        })
+    }
+
+    fn readdir(&self, path: &str) -> FileIOResult<Vec<DirEntry>> {
+        if !self.entry(path)?.is_directory {
+            return Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", self.display(path))).into());
+        }
+
+        let names = self.children.get(path).into_iter().flatten();
+        Ok(names
+            .filter_map(|name| {
+                let child = if path.is_empty() { name.clone() } else { format!("{}/{}", path, name) };
+                let entry = self.entries.get(&child)?;
+                Some(DirEntry {
+                    name: name.clone(),
+                    path: self.display(&child),
+                    is_directory: entry.is_directory,
+                    is_file: !entry.is_directory,
+                    is_symlink: false,
+                    size: Some(entry.size),
+                    mtime: Some(entry.mtime),
+                    symlink_target: None,
+                    dangling: false,
+                })
+            })
+            .collect())
+    }
+
+    /// The backend path of an entry.
+    fn display(&self, path: &str) -> String {
+        format!("{}!/{}", self.archive.display(), path)
+    }
+
+    fn invalid(&self, e: zip::result::ZipError) -> FileIOError {
+        match e {
+            zip::result::ZipError::Io(e) => e.into(),
+            e => FileIOError::InvalidArgument(format!("{} is not a valid archive: {}", self.archive.display(), e)),
+        }
+    }
+}
+
+/// Zip timestamps have no time zone; they are read as UTC.
+fn zip_time_to_millis(time: zip::DateTime) -> i64 {
+    chrono::NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())
+        .and_then(|date| date.and_hms_opt(time.hour().into(), time.minute().into(), time.second().into()))
+        .map_or(0, |time| time.and_utc().timestamp_millis())
+}
+
+/// Splits a backend path into the archive's path and the `/`-separated path
+/// of the entry inside it, `""` for the archive's root.
+fn split_path(path: &str) -> (PathBuf, String) {
+    let (archive, entry) = path
+        .split_once("!/")
+        .or_else(|| path.strip_suffix('!').map(|archive| (archive, "")))
+        .unwrap_or((path, ""));
+    (PathBuf::from(archive), path_components(entry).join("/"))
+}
+
+#[derive(Default)]
+struct IndexCache {
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// Each index with the tick at which it was last used.
+    indexes: HashMap<PathBuf, (Arc<ArchiveIndex>, u64)>,
+    tick: u64,
+}
+
+impl CacheState {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+impl IndexCache {
+    fn get(&self, archive: &Path) -> FileIOResult<Arc<ArchiveIndex>> {
+        let metadata = std::fs::metadata(archive)?;
+        {
+            let mut state = self.state.lock().unwrap();
+            let tick = state.next_tick();
+            if let Some((index, last_used)) = state.indexes.get_mut(archive) {
+                if index.is_current(&metadata) {
+                    *last_used = tick;
+                    return Ok(index.clone());
+                }
+            }
+        }
+
+        // Indexed without holding the lock, so a large archive does not
+        // hold up browsing of the others
+        let index = Arc::new(ArchiveIndex::open(archive, &metadata)?);
+        let mut state = self.state.lock().unwrap();
+        let tick = state.next_tick();
+        if !state.indexes.contains_key(archive) && state.indexes.len() >= MAX_CACHED_ARCHIVES {
+            let oldest = state.indexes.iter().min_by_key(|(_, (_, last_used))| *last_used).map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                state.indexes.remove(&oldest);
+            }
+        }
+        state.indexes.insert(archive.to_path_buf(), (index.clone(), tick));
+        Ok(index)
+    }
+}
+
+/// Serves `archive:` paths. Every modification fails with `EROFS`.
+#[derive(Default)]
+pub struct ArchiveBackend {
+    cache: Arc<IndexCache>,
+}
+
+impl ArchiveBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` on a blocking thread with the index of the archive that
+    /// `path` points into and the path of the entry inside it.
+    async fn with_entry<T, F>(&self, path: &str, f: F) -> FileIOResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ArchiveIndex, &str) -> FileIOResult<T> + Send + 'static,
+    {
+        let (archive, entry) = split_path(path);
+        let cache = self.cache.clone();
+        tokio::task::spawn_blocking(move || f(&*cache.get(&archive)?, &entry))
+            .await
+            .map_err(|e| FileIOError::Other(Box::new(e)))?
+    }
+}
+
+fn read_only(path: &str) -> FileIOError {
+    io::Error::new(io::ErrorKind::ReadOnlyFilesystem, format!("{} is inside a read-only archive", path)).into()
+}
+
+#[async_trait]
+impl FileSystemBackend for ArchiveBackend {
+    async fn read_file(&self, request: ReadFileRequest) -> FileIOResult<ReadFileResponse> {
+        let (content, stat) = self.with_entry(&request.path, |index, entry| Ok((index.read(entry)?, index.stat(entry)?))).await?;
+        let (content, encoding, lossy) = operations::encode_content(content, request.encoding.as_deref())?;
+        Ok(ReadFileResponse { content, stat, encoding, lossy })
+    }
+
+    async fn write_file(&self, request: WriteFileRequest) -> FileIOResult<()> {
+        Err(read_only(&request.path))
+    }
+
+    async fn stat(&self, request: StatRequest) -> FileIOResult<FileStat> {
+        self.with_entry(&request.path, |index, entry| index.stat(entry)).await
+    }
+
+    async fn readdir(&self, request: ReadDirRequest) -> FileIOResult<Vec<DirEntry>> {
+        self.with_entry(&request.path, |index, entry| index.readdir(entry)).await
+    }
+
+    async fn mkdir(&self, request: MkDirRequest) -> FileIOResult<()> {
+        Err(read_only(&request.path))
+    }
+
+    async fn delete(&self, request: DeleteRequest) -> FileIOResult<()> {
+        Err(read_only(&request.path))
+    }
+
+    async fn rename(&self, request: RenameRequest) -> FileIOResult<()> {
+        Err(read_only(&request.old_path))
+    }
+
+    async fn copy(&self, request: CopyRequest) -> FileIOResult<()> {
+        Err(read_only(&request.destination))
+    }
+
+    async fn realpath(&self, request: RealPathRequest) -> FileIOResult<String> {
+        self.with_entry(&request.path, |index, entry| index.entry(entry).map(|_| index.display(entry))).await
+    }
+
+    async fn read_file_stream(
+        &self,
+        request: ReadFileStreamRequest,
+        cancellation: CancellationToken,
+        on_chunk: ChunkSink,
+    ) -> FileIOResult<()> {
+        // Streamed straight from the archive, so large entries are never
+        // held in memory
+        let path = request.path.clone();
+        self.with_entry(&path, move |index, entry| {
+            index.with_reader(entry, |reader| operations::stream_sequential_reader(reader, request, cancellation, on_chunk))
+        })
+        .await
+    }
+
+    fn disk_path(&self, path: &str) -> Option<String> {
+        Some(split_path(path).0.to_string_lossy().to_string())
+    }
+}
@@ -0,0 +1,219 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! Filesystem backends behind `FileIOService`. The core file requests are
+//! served by the backend registered for the scheme of their path: plain paths
+//! and `file:` URIs go to the local disk, `untitled:` to memory, and other
+//! schemes to whatever backend the host registered for them.
+
+use crate::services::fileio::archive::{ArchiveBackend, ARCHIVE_SCHEME};
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::memory::MemoryBackend;
+use crate::services::fileio::operations;
+use crate::services::fileio::types::{
+    CopyRequest, DeleteRequest, DirEntry, FileStat, MkDirRequest, ReadDirRequest, ReadFileRequest, ReadFileResponse,
+    ReadFileStreamRequest, ReadFileStreamResponse, RealPathRequest, RenameRequest, StatRequest, WriteFileRequest,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio_util::sync::CancellationToken;
+
+pub const FILE_SCHEME: &str = "file";
+/// Unsaved editors, which never touch the disk.
+pub const UNTITLED_SCHEME: &str = "untitled";
+
+/// Receives the chunks of a streamed read.
+pub type ChunkSink = Box<dyn FnMut(ReadFileStreamResponse) + Send>;
+
+/// The requests every backend serves. Paths in requests are relative to the
+/// backend, with the scheme already removed.
+#[async_trait]
+pub trait FileSystemBackend: Send + Sync {
+    async fn read_file(&self, request: ReadFileRequest) -> FileIOResult<ReadFileResponse>;
+    async fn write_file(&self, request: WriteFileRequest) -> FileIOResult<()>;
+    async fn stat(&self, request: StatRequest) -> FileIOResult<FileStat>;
+    async fn readdir(&self, request: ReadDirRequest) -> FileIOResult<Vec<DirEntry>>;
+    async fn mkdir(&self, request: MkDirRequest) -> FileIOResult<()>;
+    async fn delete(&self, request: DeleteRequest) -> FileIOResult<()>;
+    async fn rename(&self, request: RenameRequest) -> FileIOResult<()>;
+    async fn copy(&self, request: CopyRequest) -> FileIOResult<()>;
+    async fn realpath(&self, request: RealPathRequest) -> FileIOResult<String>;
+    async fn read_file_stream(
+        &self,
+        request: ReadFileStreamRequest,
+        cancellation: CancellationToken,
+        on_chunk: ChunkSink,
+    ) -> FileIOResult<()>;
+
+    /// The local file that serving `path` reads, which the sandbox checks in
+    /// place of the path itself. `None` for backends that do not use the disk.
+    fn disk_path(&self, _path: &str) -> Option<String> {
+        None
+    }
+}
+
+/// The local disk, through `operations`.
+pub struct DiskBackend;
+
+#[async_trait]
+impl FileSystemBackend for DiskBackend {
+    async fn read_file(&self, request: ReadFileRequest) -> FileIOResult<ReadFileResponse> {
+        operations::read_file(request).await
+    }
+
+    async fn write_file(&self, request: WriteFileRequest) -> FileIOResult<()> {
+        operations::write_file(request).await
+    }
+
+    async fn stat(&self, request: StatRequest) -> FileIOResult<FileStat> {
+        operations::stat(request).await
+    }
+
+    async fn readdir(&self, request: ReadDirRequest) -> FileIOResult<Vec<DirEntry>> {
+        operations::readdir(request).await
+    }
+
+    async fn mkdir(&self, request: MkDirRequest) -> FileIOResult<()> {
+        operations::mkdir(request).await
+    }
+
+    async fn delete(&self, request: DeleteRequest) -> FileIOResult<()> {
+        operations::delete(request).await
+    }
+
+    async fn rename(&self, request: RenameRequest) -> FileIOResult<()> {
+        operations::rename(request).await
+    }
+
+    async fn copy(&self, request: CopyRequest) -> FileIOResult<()> {
+        operations::copy(request).await
+    }
+
+    async fn realpath(&self, request: RealPathRequest) -> FileIOResult<String> {
+        operations::realpath(request).await
+    }
+
+    async fn read_file_stream(
+        &self,
+        request: ReadFileStreamRequest,
+        cancellation: CancellationToken,
+        on_chunk: ChunkSink,
+    ) -> FileIOResult<()> {
+        operations::read_file_stream(request, cancellation, on_chunk).await
+    }
+
+    fn disk_path(&self, path: &str) -> Option<String> {
+        Some(path.to_string())
+    }
+}
+
+/// Splits `scheme:rest` into its lowercased scheme and the rest. Plain paths,
+/// including Windows drive paths such as `C:\foo`, have no scheme.
+pub fn split_scheme(uri: &str) -> Option<(String, &str)> {
+    let (scheme, rest) = uri.split_once(':')?;
+    let valid = scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then(|| (scheme.to_ascii_lowercase(), rest))
+}
+
+/// The local path a request path refers to: plain paths as they are and
+/// `file:` URIs decoded. `None` for paths of other schemes.
+pub fn local_path(uri: &str) -> FileIOResult<Option<String>> {
+    match split_scheme(uri) {
+        None => Ok(Some(uri.to_string())),
+        Some((scheme, _)) if scheme == FILE_SCHEME => url::Url::parse(uri)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .map(|path| Some(path.to_string_lossy().to_string()))
+            .ok_or_else(|| FileIOError::InvalidArgument(format!("Invalid file URI: {}", uri))),
+        Some(_) => Ok(None),
+    }
+}
+
+/// The names along a `/`-separated backend path, with `.` and `..` resolved.
+/// `..` at the root stays at the root.
+pub fn path_components(path: &str) -> Vec<String> {
+    let mut components = Vec::new();
+    for name in path.split(['/', '\\']) {
+        match name {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name.to_string()),
+        }
+    }
+    components
+}
+
+/// The scheme serving a request path and the path within it: the local path
+/// for the disk, and what follows the scheme and any empty `//` authority for
+/// other schemes.
+pub fn resolve_uri(uri: &str) -> FileIOResult<(String, String)> {
+    Ok(match split_scheme(uri) {
+        Some((scheme, _)) if scheme == FILE_SCHEME => (scheme, local_path(uri)?.unwrap_or_default()),
+        Some((scheme, rest)) => {
+            let path = rest.strip_prefix("//").map_or(rest, |rest| &rest[rest.find('/').unwrap_or(rest.len())..]);
+            (scheme, path.to_string())
+        }
+        None => (FILE_SCHEME.to_string(), uri.to_string()),
+    })
+}
+
+/// Turns a path returned by a backend back into a URI of the scheme the
+/// request used. Disk paths are returned as they are.
+pub fn to_request_uri(request_uri: &str, path: String) -> String {
+    match split_scheme(request_uri) {
+        Some((scheme, _)) if scheme != FILE_SCHEME => format!("{}:{}", scheme, path),
+        _ => path,
+    }
+}
+
+/// Backends by scheme. The disk is registered for `file`, which also serves
+/// plain paths, and can be replaced, e.g. by a `MemoryBackend` in tests.
+/// Untitled files live in memory and archives are browsable through
+/// `archive:` out of the box.
+pub struct BackendRegistry {
+    backends: RwLock<HashMap<String, Arc<dyn FileSystemBackend>>>,
+}
+
+impl Default for BackendRegistry {
+    fn default() -> Self {
+        let registry = BackendRegistry { backends: RwLock::new(HashMap::new()) };
+        registry.register(FILE_SCHEME, Arc::new(DiskBackend));
+        registry.register(UNTITLED_SCHEME, Arc::new(MemoryBackend::new()));
+        registry.register(ARCHIVE_SCHEME, Arc::new(ArchiveBackend::new()));
+        registry
+    }
+}
+
+impl BackendRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves paths of `scheme` with `backend`, replacing any previous one.
+    pub fn register(&self, scheme: &str, backend: Arc<dyn FileSystemBackend>) {
+        self.backends.write().unwrap().insert(scheme.to_ascii_lowercase(), backend);
+    }
+
+    pub fn unregister(&self, scheme: &str) -> bool {
+        self.backends.write().unwrap().remove(&scheme.to_ascii_lowercase()).is_some()
+    }
+
+    /// The backend for a request path and the path to give it: the local path
+    /// for the disk, and what follows the scheme and any empty `//` authority
+    /// for other schemes, e.g. `/notes.txt` for `untitled:///notes.txt`.
+    pub fn resolve(&self, uri: &str) -> FileIOResult<(Arc<dyn FileSystemBackend>, String)> {
+        let (scheme, path) = resolve_uri(uri)?;
+        let backend = self.backends.read().unwrap().get(&scheme).cloned();
+        match backend {
+            Some(backend) => Ok((backend, path)),
+            None => Err(FileIOError::Unsupported(format!("No filesystem is registered for {}: paths", scheme))),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! Transactional batches. Operations are applied in order while the batch
+//! holds locks on every path it touches, and each one records in a journal
+//! how to undo it. If any operation fails the journal is replayed backwards,
+//! so the batch is applied either completely or not at all.
+
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::locks::{LockMode, ResourceLockManager};
+use crate::services::fileio::operations;
+use crate::services::fileio::sandbox::PathAccess;
+use crate::services::fileio::types::{AtomicWriteOptions, FileIORequest, FileIOResponse};
+use crate::services::paths;
+use std::path::{Path, PathBuf};
+
+pub struct TransactionOutcome {
+    /// Results of the operations that ran, in order. Only the last can be an error.
+    pub results: Vec<FileIOResult<FileIOResponse>>,
+    pub rolled_back: bool,
+}
+
+enum Undo {
+    /// Removes a file or tree the batch created.
+    Remove(PathBuf),
+    /// Removes a directory the batch created, which is empty again once the
+    /// later steps have been undone.
+    RemoveDir(PathBuf),
+    /// Moves a backup, or an entry the batch moved, back to `path`.
+    Restore { from: PathBuf, path: PathBuf },
+}
+
+#[derive(Default)]
+struct Journal {
+    undo: Vec<Undo>,
+    /// Previous contents kept until the batch commits.
+    backups: Vec<PathBuf>,
+}
+
+/// Fails unless every operation can be journaled.
+pub fn validate_transaction(operations: &[FileIORequest]) -> FileIOResult<()> {
+    for operation in operations {
+        match operation {
+            FileIORequest::WriteFile(_)
+            | FileIORequest::Rename(_)
+            | FileIORequest::MkDir(_)
+            | FileIORequest::Copy(_)
+            | FileIORequest::ReadFile(_)
+            | FileIORequest::Stat(_)
+            | FileIORequest::ReadDir(_)
+            | FileIORequest::RealPath(_) => {}
+            FileIORequest::Delete(req) if !req.use_trash.unwrap_or(false) => {}
+            other => {
+                return Err(FileIOError::InvalidArgument(format!(
+                    "{} cannot be part of a transactional batch",
+                    other.operation()
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs `operations` as a single transaction, stopping at the first failure
+/// and rolling back everything applied before it.
+pub async fn run_transaction(lock_manager: &ResourceLockManager, operations: Vec<FileIORequest>) -> FileIOResult<TransactionOutcome> {
+    validate_transaction(&operations)?;
+
+    let resources: Vec<(&str, LockMode)> = operations
+        .iter()
+        .flat_map(|operation| operation.accessed_paths())
+        .map(|(path, access)| (path, if access == PathAccess::Write { LockMode::Exclusive } else { LockMode::Shared }))
+        .collect();
+    let _locks = lock_manager.acquire_many(&resources).await?;
+
+    let mut journal = Journal::default();
+    let mut results = Vec::with_capacity(operations.len());
+    for operation in operations {
+        let result = apply(operation, &mut journal).await;
+        let failed = result.is_err();
+        results.push(result);
+        if failed {
+            let rolled_back = rollback(journal).await;
+            return Ok(TransactionOutcome { results, rolled_back });
+        }
+    }
+
+    // Committed: the previous contents are no longer needed
+    for backup in journal.backups {
+        let _ = remove_entry(&backup).await;
+    }
+    Ok(TransactionOutcome { results, rolled_back: false })
+}
+
+async fn apply(operation: FileIORequest, journal: &mut Journal) -> FileIOResult<FileIOResponse> {
+    match operation {
+        FileIORequest::WriteFile(mut req) => {
+            let path = paths::to_path_buf(&req.path)?;
+            operations::check_preconditions(&path, &req.preconditions).await?;
+            let new_dirs = if req.create_dirs.unwrap_or(false) {
+                missing_dirs(&path.parent().map(Path::to_path_buf).unwrap_or_default(), true).await
+            } else {
+                Vec::new()
+            };
+            let backup = backup_file(&path).await?;
+
+            // Writes are staged in a temp file and renamed over the target,
+            // leaving the backup's inode untouched
+            req.atomic.get_or_insert_with(|| AtomicWriteOptions {
+                postfix: format!(".{}.tmp", uuid::Uuid::new_v4().simple()),
+                preserve_xattrs: None,
+            });
+            let written = operations::write_file(req).await;
+            journal_created_dirs(new_dirs, journal).await;
+            match backup {
+                Some(backup) => {
+                    journal.backups.push(backup.clone());
+                    journal.undo.push(Undo::Restore { from: backup, path });
+                }
+                // A failed write usually leaves nothing behind, and removing
+                // a path that is not there would fail the rollback
+                None if written.is_ok() || exists(&path).await => journal.undo.push(Undo::Remove(path)),
+                None => {}
+            }
+            written?;
+            Ok(FileIOResponse::WriteFile(()))
+        }
+        FileIORequest::Delete(req) => {
+            let path = paths::to_path_buf(&req.path)?;
+            operations::check_preconditions(&path, &req.preconditions).await?;
+            let metadata = tokio::fs::symlink_metadata(&path).await?;
+            if metadata.is_dir() && !req.recursive.unwrap_or(false) && tokio::fs::read_dir(&path).await?.next_entry().await?.is_some() {
+                return Err(FileIOError::InvalidArgument(format!("{} is not empty", path.display())));
+            }
+
+            // Deleting moves the entry aside until the batch commits
+            let backup = backup_path(&path);
+            tokio::fs::rename(&path, &backup).await?;
+            journal.backups.push(backup.clone());
+            journal.undo.push(Undo::Restore { from: backup, path });
+            Ok(FileIOResponse::Delete(()))
+        }
+        FileIORequest::Rename(req) => {
+            let old_path = paths::to_path_buf(&req.old_path)?;
+            let new_path = paths::to_path_buf(&req.new_path)?;
+            operations::check_preconditions(&old_path, &req.preconditions).await?;
+
+            if tokio::fs::symlink_metadata(&new_path).await.is_ok() && old_path != new_path {
+                let backup = backup_path(&new_path);
+                tokio::fs::rename(&new_path, &backup).await?;
+                journal.backups.push(backup.clone());
+                journal.undo.push(Undo::Restore { from: backup, path: new_path.clone() });
+            }
+            tokio::fs::rename(&old_path, &new_path).await?;
+            journal.undo.push(Undo::Restore { from: new_path, path: old_path });
+            Ok(FileIOResponse::Rename(()))
+        }
+        FileIORequest::MkDir(req) => {
+            let path = paths::to_path_buf(&req.path)?;
+            let created = missing_dirs(&path, req.recursive.unwrap_or(false)).await;
+            let made = operations::mkdir(req).await;
+            // A recursive mkdir can fail part way, after creating some parents
+            journal_created_dirs(created, journal).await;
+            made?;
+            Ok(FileIOResponse::MkDir(()))
+        }
+        FileIORequest::Copy(req) => {
+            let source = paths::to_path_buf(&req.source)?;
+            let destination = paths::to_path_buf(&req.destination)?;
+            if source == destination {
+                return Ok(FileIOResponse::Copy(()));
+            }
+
+            let replaced = tokio::fs::symlink_metadata(&destination).await.is_ok();
+            let overwrite = req.overwrite.unwrap_or(false);
+            if replaced && overwrite {
+                let backup = backup_path(&destination);
+                tokio::fs::rename(&destination, &backup).await?;
+                journal.backups.push(backup.clone());
+                journal.undo.push(Undo::Restore { from: backup, path: destination.clone() });
+            }
+            let copied = operations::copy(req).await;
+            // A failed copy can leave a partial tree behind, which is ours to
+            // remove unless the destination was kept because of no overwrite
+            if copied.is_ok() || ((!replaced || overwrite) && exists(&destination).await) {
+                journal.undo.push(Undo::Remove(destination));
+            }
+            copied?;
+            Ok(FileIOResponse::Copy(()))
+        }
+        FileIORequest::ReadFile(req) => Ok(FileIOResponse::ReadFile(operations::read_file(req).await?)),
+        FileIORequest::Stat(req) => Ok(FileIOResponse::Stat(operations::stat(req).await?)),
+        FileIORequest::ReadDir(req) => Ok(FileIOResponse::ReadDir(operations::readdir(req).await?)),
+        FileIORequest::RealPath(req) => Ok(FileIOResponse::RealPath(operations::realpath(req).await?)),
+        other => Err(FileIOError::InvalidArgument(format!("{} cannot be part of a transactional batch", other.operation()))),
+    }
+}
+
+/// Undoes the journal from the last step back, returning whether every step
+/// could be undone. Backups of steps that could not be undone are left in
+/// place so nothing is lost.
+async fn rollback(journal: Journal) -> bool {
+    let mut complete = true;
+    for step in journal.undo.into_iter().rev() {
+        let result = match &step {
+            Undo::Remove(path) => remove_entry(path).await,
+            Undo::RemoveDir(path) => tokio::fs::remove_dir(path).await,
+            Undo::Restore { from, path } => tokio::fs::rename(from, path).await,
+        };
+        if let Err(e) = result {
+            complete = false;
+            let description = match &step {
+                Undo::Remove(path) | Undo::RemoveDir(path) => format!("remove {}", path.display()),
+                Undo::Restore { from, path } => format!("move {} back to {}", from.display(), path.display()),
+            };
+            log::error!("batch rollback could not {}: {}", description, e);
+        }
+    }
+    complete
+}
+
+/// A hidden sibling of `path`, so that moving to and from it is a rename
+/// within the same filesystem.
+fn backup_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.batch", name, uuid::Uuid::new_v4().simple()))
+}
+
+/// Keeps the current contents of a file about to be overwritten, as a hard
+/// link where possible and as a copy otherwise.
+async fn backup_file(path: &Path) -> FileIOResult<Option<PathBuf>> {
+    match tokio::fs::symlink_metadata(path).await {
+        Ok(metadata) if metadata.is_file() => {
+            let backup = backup_path(path);
+            if tokio::fs::hard_link(path, &backup).await.is_err() {
+                tokio::fs::copy(path, &backup).await?;
+            }
+            Ok(Some(backup))
+        }
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The directories that creating `path` will create, outermost first.
+async fn missing_dirs(path: &Path, recursive: bool) -> Vec<PathBuf> {
+    let mut missing = Vec::new();
+    for dir in path.ancestors() {
+        if dir.as_os_str().is_empty() || tokio::fs::symlink_metadata(dir).await.is_ok() {
+            break;
+        }
+        missing.push(dir.to_path_buf());
+        if !recursive {
+            break;
+        }
+    }
+    missing.reverse();
+    missing
+}
+
+/// Journals the directories from `missing_dirs` that now exist. They are
+/// outermost first, so rolling back removes the innermost first.
+async fn journal_created_dirs(created: Vec<PathBuf>, journal: &mut Journal) {
+    for dir in created {
+        if !exists(&dir).await {
+            break;
+        }
+        journal.undo.push(Undo::RemoveDir(dir));
+    }
+}
+
+async fn exists(path: &Path) -> bool {
+    tokio::fs::symlink_metadata(path).await.is_ok()
+}
+
+async fn remove_entry(path: &Path) -> std::io::Result<()> {
+    if tokio::fs::symlink_metadata(path).await?.is_dir() {
+        tokio::fs::remove_dir_all(path).await
+    } else {
+        tokio::fs::remove_file(path).await
+    }
+}
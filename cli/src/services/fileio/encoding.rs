@@ -0,0 +1,227 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! Text encodings for `ReadFile`/`WriteFile`, named with the editor's encoding
+//! ids (`utf8`, `utf8bom`, `utf16le`, `windows1252`, ...).
+
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use encoding_rs::{Encoding, GBK, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// Requests detection from the file's BOM or, failing that, its contents.
+pub const AUTO: &str = "auto";
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// How many bytes the detector looks at in `auto` mode.
+const DETECTION_SAMPLE_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, where every byte maps to the code point of the same value.
+    Latin1,
+    Windows1252,
+    ShiftJis,
+    Gbk,
+    /// Any other encoding known to encoding_rs, by WHATWG label.
+    Other(&'static Encoding),
+}
+
+#[derive(Debug)]
+pub struct DecodedText {
+    pub content: String,
+    pub encoding: TextEncoding,
+    /// Invalid byte sequences were replaced with U+FFFD.
+    pub lossy: bool,
+}
+
+impl TextEncoding {
+    pub fn from_label(label: &str) -> FileIOResult<TextEncoding> {
+        let encoding = match label.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "utf8" => TextEncoding::Utf8,
+            "utf8bom" => TextEncoding::Utf8Bom,
+            "utf16le" => TextEncoding::Utf16Le,
+            "utf16be" => TextEncoding::Utf16Be,
+            "latin1" | "iso88591" => TextEncoding::Latin1,
+            "windows1252" | "cp1252" => TextEncoding::Windows1252,
+            "shiftjis" | "sjis" => TextEncoding::ShiftJis,
+            "gbk" => TextEncoding::Gbk,
+            _ => match Encoding::for_label(label.as_bytes()) {
+                Some(encoding) => Self::from_encoding(encoding),
+                None => return Err(FileIOError::InvalidArgument(format!("Unsupported encoding: {}", label))),
+            },
+        };
+        Ok(encoding)
+    }
+
+    fn from_encoding(encoding: &'static Encoding) -> TextEncoding {
+        if encoding == UTF_8 {
+            TextEncoding::Utf8
+        } else if encoding == UTF_16LE {
+            TextEncoding::Utf16Le
+        } else if encoding == UTF_16BE {
+            TextEncoding::Utf16Be
+        } else if encoding == WINDOWS_1252 {
+            TextEncoding::Windows1252
+        } else if encoding == SHIFT_JIS {
+            TextEncoding::ShiftJis
+        } else if encoding == GBK {
+            TextEncoding::Gbk
+        } else {
+            TextEncoding::Other(encoding)
+        }
+    }
+
+    /// The id reported back to clients.
+    pub fn id(&self) -> String {
+        match self {
+            TextEncoding::Utf8 => "utf8".to_string(),
+            TextEncoding::Utf8Bom => "utf8bom".to_string(),
+            TextEncoding::Utf16Le => "utf16le".to_string(),
+            TextEncoding::Utf16Be => "utf16be".to_string(),
+            TextEncoding::Latin1 => "latin1".to_string(),
+            TextEncoding::Windows1252 => "windows1252".to_string(),
+            TextEncoding::ShiftJis => "shiftjis".to_string(),
+            TextEncoding::Gbk => "gbk".to_string(),
+            TextEncoding::Other(encoding) => encoding.name().to_ascii_lowercase().replace(['-', '_'], ""),
+        }
+    }
+
+    fn is_unicode(&self) -> bool {
+        matches!(self, TextEncoding::Utf8 | TextEncoding::Utf8Bom | TextEncoding::Utf16Le | TextEncoding::Utf16Be)
+    }
+
+    fn bom(&self) -> &'static [u8] {
+        match self {
+            TextEncoding::Utf8Bom => UTF8_BOM,
+            TextEncoding::Utf16Le => UTF16LE_BOM,
+            TextEncoding::Utf16Be => UTF16BE_BOM,
+            _ => &[],
+        }
+    }
+}
+
+/// Identifies a Unicode BOM at the start of `bytes`.
+fn sniff_bom(bytes: &[u8]) -> Option<TextEncoding> {
+    if bytes.starts_with(UTF8_BOM) {
+        Some(TextEncoding::Utf8Bom)
+    } else if bytes.starts_with(UTF16LE_BOM) {
+        Some(TextEncoding::Utf16Le)
+    } else if bytes.starts_with(UTF16BE_BOM) {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+fn detect(bytes: &[u8]) -> TextEncoding {
+    let sample = &bytes[..bytes.len().min(DETECTION_SAMPLE_SIZE)];
+    // A sample cut in the middle of a character is still UTF-8
+    match std::str::from_utf8(sample) {
+        Ok(_) => return TextEncoding::Utf8,
+        Err(e) if e.error_len().is_none() && sample.len() < bytes.len() => return TextEncoding::Utf8,
+        Err(_) => {}
+    }
+
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(sample, sample.len() == bytes.len());
+    TextEncoding::from_encoding(detector.guess(None, false))
+}
+
+/// Decodes file contents. With `auto` or a Unicode encoding, a Unicode BOM
+/// takes precedence over the requested encoding, matching how the editor opens
+/// files, and is not part of the text. In other encodings the same bytes are
+/// ordinary characters, e.g. `ÿþ` in windows-1252.
+pub fn decode(bytes: &[u8], label: &str) -> FileIOResult<DecodedText> {
+    let encoding = if label == AUTO {
+        sniff_bom(bytes).unwrap_or_else(|| detect(bytes))
+    } else {
+        match TextEncoding::from_label(label)? {
+            encoding if encoding.is_unicode() => match sniff_bom(bytes) {
+                Some(bom) => bom,
+                // Without a BOM the file is plain UTF-8
+                None if encoding == TextEncoding::Utf8Bom => TextEncoding::Utf8,
+                None => encoding,
+            },
+            encoding => encoding,
+        }
+    };
+
+    let body = &bytes[encoding.bom().len()..];
+    let (content, lossy) = match encoding {
+        TextEncoding::Latin1 => (body.iter().map(|&b| b as char).collect(), false),
+        TextEncoding::Utf8 | TextEncoding::Utf8Bom => decode_without_bom(UTF_8, body),
+        TextEncoding::Utf16Le => decode_without_bom(UTF_16LE, body),
+        TextEncoding::Utf16Be => decode_without_bom(UTF_16BE, body),
+        TextEncoding::Windows1252 => decode_without_bom(WINDOWS_1252, body),
+        TextEncoding::ShiftJis => decode_without_bom(SHIFT_JIS, body),
+        TextEncoding::Gbk => decode_without_bom(GBK, body),
+        TextEncoding::Other(other) => decode_without_bom(other, body),
+    };
+
+    Ok(DecodedText { content, encoding, lossy })
+}
+
+fn decode_without_bom(encoding: &'static Encoding, bytes: &[u8]) -> (String, bool) {
+    let (content, lossy) = encoding.decode_without_bom_handling(bytes);
+    (content.into_owned(), lossy)
+}
+
+/// Encodes text for writing, prefixed with the encoding's BOM when
+/// `include_bom` is set. Text the encoding cannot represent is rejected rather
+/// than written with substitutes.
+pub fn encode(content: &str, label: &str, include_bom: bool) -> FileIOResult<Vec<u8>> {
+    let encoding = TextEncoding::from_label(label)?;
+    let mut bytes = Vec::with_capacity(content.len() + 3);
+    if include_bom {
+        bytes.extend_from_slice(encoding.bom());
+    }
+
+    match encoding {
+        TextEncoding::Utf8 | TextEncoding::Utf8Bom => bytes.extend_from_slice(content.as_bytes()),
+        // encoding_rs only decodes UTF-16, so encode it here
+        TextEncoding::Utf16Le => bytes.extend(content.encode_utf16().flat_map(u16::to_le_bytes)),
+        TextEncoding::Utf16Be => bytes.extend(content.encode_utf16().flat_map(u16::to_be_bytes)),
+        TextEncoding::Latin1 => {
+            for c in content.chars() {
+                let code = u8::try_from(c as u32).map_err(|_| unencodable(c, &encoding))?;
+                bytes.push(code);
+            }
+        }
+        TextEncoding::Windows1252 => encode_with(WINDOWS_1252, content, &encoding, &mut bytes)?,
+        TextEncoding::ShiftJis => encode_with(SHIFT_JIS, content, &encoding, &mut bytes)?,
+        TextEncoding::Gbk => encode_with(GBK, content, &encoding, &mut bytes)?,
+        TextEncoding::Other(other) => encode_with(other, content, &encoding, &mut bytes)?,
+    }
+
+    Ok(bytes)
+}
+
+fn encode_with(encoding: &'static Encoding, content: &str, text_encoding: &TextEncoding, bytes: &mut Vec<u8>) -> FileIOResult<()> {
+    let mut encoder = encoding.new_encoder();
+    let mut output = vec![0; encoder.max_buffer_length_from_utf8_without_replacement(content.len()).unwrap_or(content.len() * 4)];
+    let (result, read, written) = encoder.encode_from_utf8_without_replacement(content, &mut output, true);
+    match result {
+        encoding_rs::EncoderResult::InputEmpty => {
+            bytes.extend_from_slice(&output[..written]);
+            Ok(())
+        }
+        encoding_rs::EncoderResult::Unmappable(c) => Err(unencodable(c, text_encoding)),
+        encoding_rs::EncoderResult::OutputFull => Err(FileIOError::InvalidArgument(format!(
+            "Could not encode content after {} bytes as {}",
+            read,
+            text_encoding.id()
+        ))),
+    }
+}
+
+fn unencodable(c: char, encoding: &TextEncoding) -> FileIOError {
+    FileIOError::InvalidArgument(format!("Character {:?} cannot be encoded as {}", c, encoding.id()))
+}
@@ -0,0 +1,156 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use crate::services::fileio::types::FileStat;
+use serde::{Deserialize, Serialize};
+use std::io;
+use thiserror::Error;
+
+pub type FileIOResult<T> = Result<T, FileIOError>;
+
+/// Error category understood by the editor, mirroring `FileSystemProviderErrorCode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileSystemProviderErrorCode {
+    #[serde(rename = "EntryExists")]
+    FileExists,
+    #[serde(rename = "EntryNotFound")]
+    FileNotFound,
+    #[serde(rename = "EntryNotADirectory")]
+    FileNotADirectory,
+    #[serde(rename = "EntryIsADirectory")]
+    FileIsADirectory,
+    #[serde(rename = "EntryExceedsStorageQuota")]
+    FileExceedsStorageQuota,
+    #[serde(rename = "EntryTooLarge")]
+    FileTooLarge,
+    #[serde(rename = "EntryWriteLocked")]
+    FileWriteLocked,
+    NoPermissions,
+    Unavailable,
+    Unknown,
+}
+
+#[derive(Debug, Error)]
+pub enum FileIOError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    AlreadyExists(String),
+    #[error("Invalid handle: {0}")]
+    InvalidHandle(u32),
+    #[error("Too many open handles (limit {0})")]
+    TooManyOpenFiles(usize),
+    #[error("Timed out waiting for lock on {0}")]
+    LockTimeout(String),
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
+    #[error("{message}")]
+    PreconditionFailed { message: String, current: Option<Box<FileStat>> },
+    #[error("{0}")]
+    InvalidArgument(String),
+    #[error("{0}")]
+    Unsupported(String),
+    #[error(transparent)]
+    Other(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl FileIOError {
+    /// POSIX-style error code reported to clients, e.g. `ENOENT`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            FileIOError::Io(e) => io_error_code(e),
+            FileIOError::NotFound(_) => "ENOENT",
+            FileIOError::AlreadyExists(_) => "EEXIST",
+            FileIOError::InvalidHandle(_) => "EBADF",
+            FileIOError::TooManyOpenFiles(_) => "EMFILE",
+            FileIOError::LockTimeout(_) => "ETIMEDOUT",
+            FileIOError::AccessDenied(_) => "EACCES",
+            FileIOError::PreconditionFailed { .. } => "ECONFLICT",
+            FileIOError::InvalidArgument(_) => "EINVAL",
+            FileIOError::Unsupported(_) => "ENOTSUP",
+            FileIOError::Other(e) => match e.downcast_ref::<io::Error>() {
+                Some(e) => io_error_code(e),
+                None => "EIO",
+            },
+        }
+    }
+
+    pub fn category(&self) -> FileSystemProviderErrorCode {
+        match self.code() {
+            "ENOENT" => FileSystemProviderErrorCode::FileNotFound,
+            "EEXIST" => FileSystemProviderErrorCode::FileExists,
+            "ENOTDIR" => FileSystemProviderErrorCode::FileNotADirectory,
+            "EISDIR" => FileSystemProviderErrorCode::FileIsADirectory,
+            "ENOSPC" | "EDQUOT" => FileSystemProviderErrorCode::FileExceedsStorageQuota,
+            "EFBIG" => FileSystemProviderErrorCode::FileTooLarge,
+            "EBUSY" => FileSystemProviderErrorCode::FileWriteLocked,
+            "EACCES" | "EPERM" | "EROFS" => FileSystemProviderErrorCode::NoPermissions,
+            "ETIMEDOUT" | "EAGAIN" | "EMFILE" | "ENFILE" => FileSystemProviderErrorCode::Unavailable,
+            _ => FileSystemProviderErrorCode::Unknown,
+        }
+    }
+}
+
+fn io_error_code(e: &io::Error) -> &'static str {
+    // Prefer the raw errno, which is exact and independent of the OS locale
+    #[cfg(unix)]
+    {
+        if let Some(code) = e.raw_os_error().and_then(errno_code) {
+            return code;
+        }
+    }
+
+    match e.kind() {
+        io::ErrorKind::NotFound => "ENOENT",
+        io::ErrorKind::PermissionDenied => "EACCES",
+        io::ErrorKind::AlreadyExists => "EEXIST",
+        io::ErrorKind::DirectoryNotEmpty => "ENOTEMPTY",
+        io::ErrorKind::IsADirectory => "EISDIR",
+        io::ErrorKind::NotADirectory => "ENOTDIR",
+        io::ErrorKind::StorageFull => "ENOSPC",
+        io::ErrorKind::FileTooLarge => "EFBIG",
+        io::ErrorKind::ReadOnlyFilesystem => "EROFS",
+        io::ErrorKind::ResourceBusy => "EBUSY",
+        io::ErrorKind::CrossesDevices => "EXDEV",
+        io::ErrorKind::TimedOut => "ETIMEDOUT",
+        io::ErrorKind::WouldBlock => "EAGAIN",
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => "EINVAL",
+        io::ErrorKind::Unsupported => "ENOTSUP",
+        _ => "EIO",
+    }
+}
+
+#[cfg(unix)]
+fn errno_code(errno: i32) -> Option<&'static str> {
+    let code = match errno {
+        libc::ENOENT => "ENOENT",
+        libc::EEXIST => "EEXIST",
+        libc::ENOTEMPTY => "ENOTEMPTY",
+        libc::EACCES => "EACCES",
+        libc::EPERM => "EPERM",
+        libc::ELOOP => "ELOOP",
+        libc::EXDEV => "EXDEV",
+        libc::ETIMEDOUT => "ETIMEDOUT",
+        libc::ENOSPC => "ENOSPC",
+        libc::EDQUOT => "EDQUOT",
+        libc::EISDIR => "EISDIR",
+        libc::ENOTDIR => "ENOTDIR",
+        libc::EMFILE => "EMFILE",
+        libc::ENFILE => "ENFILE",
+        libc::EINVAL => "EINVAL",
+        libc::EBADF => "EBADF",
+        libc::EBUSY => "EBUSY",
+        libc::EROFS => "EROFS",
+        libc::ENAMETOOLONG => "ENAMETOOLONG",
+        libc::EFBIG => "EFBIG",
+        libc::EAGAIN => "EAGAIN",
+        libc::ENOTSUP => "ENOTSUP",
+        libc::EIO => "EIO",
+        _ => return None,
+    };
+    Some(code)
+}
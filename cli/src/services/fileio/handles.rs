@@ -0,0 +1,145 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::operations::to_millis;
+use crate::services::fileio::types::HandleInfo;
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone)]
+pub struct HandleTableConfig {
+    /// Opening more handles than this fails with EMFILE.
+    pub max_open: usize,
+    /// Handles unused for this long are closed, and write streams waiting this
+    /// long for their next chunk are discarded. `None` keeps both until closed.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for HandleTableConfig {
+    fn default() -> Self {
+        HandleTableConfig {
+            max_open: 1024,
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+        }
+    }
+}
+
+struct HandleEntry {
+    file: Arc<File>,
+    path: String,
+    writable: bool,
+    opened_at: SystemTime,
+    last_used: Instant,
+}
+
+struct HandleTableState {
+    handles: HashMap<u32, HandleEntry>,
+    next_handle: u32,
+}
+
+/// Open file handles owned by a single `FileIOService`. Every handle is closed
+/// when the table is dropped, so a disconnected client cannot leak descriptors.
+pub struct HandleTable {
+    state: Mutex<HandleTableState>,
+    config: HandleTableConfig,
+}
+
+impl HandleTable {
+    pub fn new(config: HandleTableConfig) -> Self {
+        HandleTable {
+            state: Mutex::new(HandleTableState {
+                handles: HashMap::new(),
+                next_handle: 1,
+            }),
+            config,
+        }
+    }
+
+    pub fn insert(&self, file: File, path: String, writable: bool) -> FileIOResult<u32> {
+        let mut state = self.lock_and_reap();
+        if state.handles.len() >= self.config.max_open {
+            return Err(FileIOError::TooManyOpenFiles(self.config.max_open));
+        }
+
+        // Skip ids still in use after the counter wraps around
+        let mut handle = state.next_handle;
+        while handle == 0 || state.handles.contains_key(&handle) {
+            handle = handle.wrapping_add(1);
+        }
+        state.next_handle = handle.wrapping_add(1);
+
+        state.handles.insert(
+            handle,
+            HandleEntry {
+                file: Arc::new(file),
+                path,
+                writable,
+                opened_at: SystemTime::now(),
+                last_used: Instant::now(),
+            },
+        );
+        Ok(handle)
+    }
+
+    /// Returns the file for `handle` and marks it as used. The file stays open
+    /// for the caller even if the handle is closed concurrently.
+    pub fn get(&self, handle: u32) -> FileIOResult<Arc<File>> {
+        let mut state = self.lock_and_reap();
+        let entry = state.handles.get_mut(&handle).ok_or(FileIOError::InvalidHandle(handle))?;
+        entry.last_used = Instant::now();
+        Ok(entry.file.clone())
+    }
+
+    pub fn remove(&self, handle: u32) -> FileIOResult<()> {
+        match self.lock_and_reap().handles.remove(&handle) {
+            Some(_) => Ok(()),
+            None => Err(FileIOError::InvalidHandle(handle)),
+        }
+    }
+
+    pub fn list(&self) -> Vec<HandleInfo> {
+        let state = self.lock_and_reap();
+        let mut handles: Vec<HandleInfo> = state
+            .handles
+            .iter()
+            .map(|(handle, entry)| HandleInfo {
+                handle: *handle,
+                path: entry.path.clone(),
+                writable: entry.writable,
+                opened_at: to_millis(entry.opened_at),
+                idle_ms: entry.last_used.elapsed().as_millis() as u64,
+            })
+            .collect();
+        handles.sort_by_key(|info| info.handle);
+        handles
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock_and_reap().handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Idle handles are reaped whenever the table is touched rather than by a
+    /// timer, so an idle table costs nothing.
+    fn lock_and_reap(&self) -> std::sync::MutexGuard<'_, HandleTableState> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(idle_timeout) = self.config.idle_timeout {
+            state.handles.retain(|_, entry| entry.last_used.elapsed() < idle_timeout);
+        }
+        state
+    }
+}
+
+impl Default for HandleTable {
+    fn default() -> Self {
+        HandleTable::new(HandleTableConfig::default())
+    }
+}
@@ -0,0 +1,277 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! Content digests of files and directory trees for the `Hash` request.
+
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::types::{HashAlgorithm, HashRequest, HashResponse};
+use crate::services::paths;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio_util::sync::CancellationToken;
+use xxhash_rust::xxh3::Xxh3;
+
+/// File digests kept before the cache is cleared and starts over.
+const MAX_CACHED_DIGESTS: usize = 100_000;
+
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+struct CachedDigest {
+    mtime: SystemTime,
+    size: u64,
+    digest: Vec<u8>,
+}
+
+/// File digests keyed by path and algorithm, valid while the file's mtime and
+/// size are unchanged. Directory digests are not cached since a directory's
+/// mtime does not change with the contents of its files.
+#[derive(Default)]
+pub struct HashCache {
+    entries: Mutex<HashMap<(PathBuf, HashAlgorithm), CachedDigest>>,
+}
+
+impl HashCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, path: &Path, algorithm: HashAlgorithm, metadata: &std::fs::Metadata) -> Option<Vec<u8>> {
+        let entries = self.entries.lock().unwrap();
+        let cached = entries.get(&(path.to_path_buf(), algorithm))?;
+        let unchanged = metadata.modified().ok() == Some(cached.mtime) && metadata.len() == cached.size;
+        unchanged.then(|| cached.digest.clone())
+    }
+
+    fn insert(&self, path: &Path, algorithm: HashAlgorithm, mtime: SystemTime, size: u64, digest: Vec<u8>) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_CACHED_DIGESTS {
+            entries.clear();
+        }
+        entries.insert((path.to_path_buf(), algorithm), CachedDigest { mtime, size, digest });
+    }
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Xxh3(Box<Xxh3>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::new(Xxh3::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Xxh3(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Xxh3(hasher) => hasher.digest128().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct HashCounts {
+    files: u64,
+    cached: u64,
+}
+
+/// Hashes a file, or a directory as a Merkle tree: each directory's digest
+/// covers the sorted names, kinds and digests of its entries, so equal trees
+/// have equal digests wherever they live. Symlinks contribute their target
+/// path rather than being followed, and FIFOs, sockets and devices only their
+/// kind, since reading them could block or never end. Cancelling
+/// `cancellation` stops the hash and leaves the response's digest empty.
+pub async fn hash(request: HashRequest, cache: Arc<HashCache>, cancellation: CancellationToken) -> FileIOResult<HashResponse> {
+    tokio::task::spawn_blocking(move || {
+        let path = paths::to_path_buf(&request.path)?;
+        let algorithm = request.algorithm.unwrap_or_default();
+        let metadata = std::fs::metadata(&path)?;
+        if !metadata.is_dir() && !metadata.is_file() {
+            return Err(FileIOError::InvalidArgument(format!("{} is not a file or directory", path.display())));
+        }
+
+        let mut counts = HashCounts::default();
+        // Files modified after this instant may change again within the same
+        // mtime tick, so their digests are not cached
+        let started = SystemTime::now();
+        let digest = if metadata.is_dir() {
+            hash_directory(&path, algorithm, &cache, started, &cancellation, &mut counts)
+        } else {
+            hash_file(&path, &metadata, algorithm, &cache, started, &cancellation, &mut counts)
+        };
+        let cancelled = cancellation.is_cancelled();
+        let digest = match digest {
+            Err(_) if cancelled => Vec::new(),
+            digest => digest?,
+        };
+
+        Ok(HashResponse {
+            digest: to_hex(&digest),
+            algorithm,
+            is_directory: metadata.is_dir(),
+            files: counts.files,
+            cached: counts.cached,
+            cancelled,
+        })
+    })
+    .await
+    .map_err(|e| FileIOError::Other(Box::new(e)))?
+}
+
+fn hash_file(
+    path: &Path,
+    metadata: &std::fs::Metadata,
+    algorithm: HashAlgorithm,
+    cache: &HashCache,
+    started: SystemTime,
+    cancellation: &CancellationToken,
+    counts: &mut HashCounts,
+) -> FileIOResult<Vec<u8>> {
+    counts.files += 1;
+    if let Some(digest) = cache.get(path, algorithm, metadata) {
+        counts.cached += 1;
+        return Ok(digest);
+    }
+
+    let digest = digest_file(path, algorithm, cancellation)?;
+    if let Ok(mtime) = metadata.modified() {
+        if mtime < started {
+            cache.insert(path, algorithm, mtime, metadata.len(), digest.clone());
+        }
+    }
+    Ok(digest)
+}
+
+fn digest_file(path: &Path, algorithm: HashAlgorithm, cancellation: &CancellationToken) -> FileIOResult<Vec<u8>> {
+    // Opening a FIFO would block until something writes to it
+    if !std::fs::metadata(path)?.is_file() {
+        return Err(FileIOError::InvalidArgument(format!("{} is not a file", path.display())));
+    }
+
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        if cancellation.is_cancelled() {
+            return Err(cancelled());
+        }
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// The SHA-256 of `data` in hex, the form content hash preconditions use.
+pub fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+    hasher.update(data);
+    to_hex(&hasher.finish())
+}
+
+/// Like `sha256_hex` for a file, read in chunks on a blocking thread.
+pub async fn file_sha256_hex(path: &Path) -> FileIOResult<String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        digest_file(&path, HashAlgorithm::Sha256, &CancellationToken::new()).map(|digest| to_hex(&digest))
+    })
+        .await
+        .map_err(|e| FileIOError::Other(Box::new(e)))?
+}
+
+fn hash_directory(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    cache: &HashCache,
+    started: SystemTime,
+    cancellation: &CancellationToken,
+    counts: &mut HashCounts,
+) -> FileIOResult<Vec<u8>> {
+    let mut entries: Vec<std::fs::DirEntry> = std::fs::read_dir(path)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut hasher = Hasher::new(algorithm);
+    for entry in entries {
+        if cancellation.is_cancelled() {
+            return Err(cancelled());
+        }
+        let entry_path = entry.path();
+        let metadata = std::fs::symlink_metadata(&entry_path)?;
+        let (kind, digest) = if metadata.file_type().is_symlink() {
+            let target = std::fs::read_link(&entry_path)?;
+            let mut link_hasher = Hasher::new(algorithm);
+            link_hasher.update(target.to_string_lossy().as_bytes());
+            (b'l', link_hasher.finish())
+        } else if metadata.is_dir() {
+            (b'd', hash_directory(&entry_path, algorithm, cache, started, cancellation, counts)?)
+        } else if metadata.is_file() {
+            (b'f', hash_file(&entry_path, &metadata, algorithm, cache, started, cancellation, counts)?)
+        } else {
+            (special_kind(&metadata), Vec::new())
+        };
+
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        hasher.update(&[kind]);
+        hasher.update(&(name.len() as u64).to_be_bytes());
+        hasher.update(name.as_bytes());
+        hasher.update(&digest);
+    }
+    Ok(hasher.finish())
+}
+
+/// The kind recorded for an entry that is neither a file, a directory nor a
+/// symlink.
+fn special_kind(metadata: &std::fs::Metadata) -> u8 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        let file_type = metadata.file_type();
+        if file_type.is_fifo() {
+            return b'p';
+        } else if file_type.is_socket() {
+            return b's';
+        } else if file_type.is_block_device() {
+            return b'b';
+        } else if file_type.is_char_device() {
+            return b'c';
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    b'o'
+}
+
+fn cancelled() -> FileIOError {
+    std::io::Error::new(std::io::ErrorKind::Interrupted, "Hash cancelled").into()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -3,62 +3,169 @@
  *  Licensed under the MIT License. See License.txt in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
 
+use crate::services::fileio::backend::{self, FILE_SCHEME};
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
 use crate::services::paths::PathNormalizer;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::{Semaphore, OwnedSemaphorePermit};
-pub struct ResourceLockGuard {
-    _permit: OwnedSemaphorePermit,
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockMode {
+    /// Any number of shared holders, e.g. readers of a file.
+    Shared,
+    /// A single holder, e.g. a writer, with no concurrent readers.
+    Exclusive,
 }
 
-impl Drop for ResourceLockGuard {
-    fn drop(&mut self) {
-        // debug!("ResourceLockGuard dropped, permit will be released");
-    }
+// Held only so that dropping the guard releases the lock
+#[allow(dead_code)]
+enum LockPermit {
+    Shared(OwnedRwLockReadGuard<()>),
+    Exclusive(OwnedRwLockWriteGuard<()>),
 }
 
+pub struct ResourceLockGuard {
+    _permit: LockPermit,
+}
+
+/// Per-path reader/writer locks. The map is only locked while looking up an
+/// entry, never while waiting for one, so waiting on one path does not block
+/// requests for other paths.
 pub struct ResourceLockManager {
-    locks: HashMap<String, Arc<Semaphore>>,
+    locks: Mutex<HashMap<String, Arc<RwLock<()>>>>,
+    timeout: Option<Duration>,
 }
 
 impl ResourceLockManager {
     pub fn new() -> Self {
+        Self::with_timeout(Some(DEFAULT_LOCK_TIMEOUT))
+    }
+
+    /// Creates a manager whose acquisitions fail with ETIMEDOUT after
+    /// `timeout`, or wait indefinitely when it is `None`.
+    pub fn with_timeout(timeout: Option<Duration>) -> Self {
         ResourceLockManager {
-            locks: HashMap::new(),
+            locks: Mutex::new(HashMap::new()),
+            timeout,
         }
     }
 
-    pub async fn acquire_lock(&mut self, resource_path: &str) -> Result<ResourceLockGuard, Box<dyn std::error::Error + Send + Sync>> {
-        let normalized_path = PathNormalizer::normalize(resource_path);
-        // debug!("Acquiring lock for resource: {}", normalized_path);
+    /// Acquires an exclusive lock on `resource_path`.
+    pub async fn acquire_lock(&self, resource_path: &str) -> FileIOResult<ResourceLockGuard> {
+        self.acquire(resource_path, LockMode::Exclusive).await
+    }
 
-        let semaphore = self.locks
-            .entry(normalized_path.clone())
-            .or_insert_with(|| Arc::new(Semaphore::new(1)));
+    /// Acquires a shared lock on `resource_path`.
+    pub async fn acquire_shared(&self, resource_path: &str) -> FileIOResult<ResourceLockGuard> {
+        self.acquire(resource_path, LockMode::Shared).await
+    }
 
-        // debug!("Semaphore created/retrieved, acquiring owned permit...");
-        // Acquire the owned permit which owns the semaphore directly
-        let permit = semaphore.clone().acquire_owned().await?;
-        // debug!("Owned permit acquired successfully");
-        Ok(ResourceLockGuard { _permit: permit })
+    pub async fn acquire(&self, resource_path: &str, mode: LockMode) -> FileIOResult<ResourceLockGuard> {
+        let normalized_path = Self::key(resource_path)?;
+        let lock = self.entry(&normalized_path);
+        self.acquire_entry(lock, mode, &normalized_path).await
+    }
+
+    /// Acquires locks on several paths at once. Paths are locked in sorted
+    /// order so that two requests naming the same paths in opposite order
+    /// cannot deadlock, and a path named twice is locked once in the strongest
+    /// requested mode.
+    pub async fn acquire_many(&self, resources: &[(&str, LockMode)]) -> FileIOResult<Vec<ResourceLockGuard>> {
+        let mut ordered: BTreeMap<String, LockMode> = BTreeMap::new();
+        for (resource_path, mode) in resources {
+            let entry = ordered.entry(Self::key(resource_path)?).or_insert(*mode);
+            *entry = (*entry).max(*mode);
+        }
+
+        let mut guards = Vec::with_capacity(ordered.len());
+        for (normalized_path, mode) in ordered {
+            let lock = self.entry(&normalized_path);
+            // Guards acquired so far are released if a later path times out
+            guards.push(self.acquire_entry(lock, mode, &normalized_path).await?);
+        }
+        Ok(guards)
     }
 
-    pub fn release_lock(&mut self, resource_path: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let _normalized_path = PathNormalizer::normalize(resource_path);
+    pub fn release_lock(&self, resource_path: &str) -> FileIOResult<()> {
+        let _normalized_path = Self::key(resource_path)?;
 
-        // The semaphore automatically releases when the permit is dropped
+        // The lock is released when its guard is dropped
         // We don't need to do anything here as the guard handles it
         Ok(())
     }
 
-    pub fn is_locked(&self, resource_path: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-        let normalized_path = PathNormalizer::normalize(resource_path);
+    /// Whether any holder, shared or exclusive, currently has `resource_path`.
+    pub fn is_locked(&self, resource_path: &str) -> FileIOResult<bool> {
+        let normalized_path = Self::key(resource_path)?;
 
-        if let Some(semaphore) = self.locks.get(&normalized_path) {
-            // If available permits is 0, it's locked
-            Ok(semaphore.available_permits() == 0)
-        } else {
-            Ok(false)
+        match self.locks.lock().unwrap().get(&normalized_path) {
+            Some(lock) => Ok(lock.try_write().is_err()),
+            None => Ok(false),
         }
     }
-}
\ No newline at end of file
+
+    /// Number of paths with a live entry in the lock map.
+    pub fn tracked_paths(&self) -> usize {
+        let mut locks = self.locks.lock().unwrap();
+        Self::evict_idle(&mut locks);
+        locks.len()
+    }
+
+    /// The map key for a request path. Paths are resolved the way the
+    /// backends resolve them, so a `file:` URI and the plain path it names
+    /// share a lock.
+    fn key(resource_path: &str) -> FileIOResult<String> {
+        let (scheme, path) = backend::resolve_uri(resource_path)?;
+        let path = PathNormalizer::normalize(&path);
+        if scheme != FILE_SCHEME {
+            return Ok(format!("{}:{}", scheme, path));
+        }
+        // Names that differ only in case are the same file on the default
+        // Windows and macOS filesystems
+        #[cfg(any(windows, target_os = "macos"))]
+        let path = path.to_lowercase();
+        Ok(path)
+    }
+
+    fn entry(&self, normalized_path: &str) -> Arc<RwLock<()>> {
+        let mut locks = self.locks.lock().unwrap();
+        Self::evict_idle(&mut locks);
+        locks
+            .entry(normalized_path.to_string())
+            .or_insert_with(|| Arc::new(RwLock::new(())))
+            .clone()
+    }
+
+    /// Drops entries that no guard or waiter references any more, so the map
+    /// only holds paths that are in use.
+    fn evict_idle(locks: &mut HashMap<String, Arc<RwLock<()>>>) {
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+    }
+
+    async fn acquire_entry(&self, lock: Arc<RwLock<()>>, mode: LockMode, normalized_path: &str) -> FileIOResult<ResourceLockGuard> {
+        let acquire = async move {
+            match mode {
+                LockMode::Shared => LockPermit::Shared(lock.read_owned().await),
+                LockMode::Exclusive => LockPermit::Exclusive(lock.write_owned().await),
+            }
+        };
+
+        let permit = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, acquire)
+                .await
+                .map_err(|_| FileIOError::LockTimeout(normalized_path.to_string()))?,
+            None => acquire.await,
+        };
+        Ok(ResourceLockGuard { _permit: permit })
+    }
+}
+
+impl Default for ResourceLockManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,346 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! An in-memory filesystem backend, for tests and for documents that never
+//! touch the disk such as untitled files. Paths are `/`-separated from the
+//! root of the backend.
+
+use crate::services::fileio::backend::{path_components as components, ChunkSink, FileSystemBackend};
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::operations::{self, to_millis};
+use crate::services::fileio::types::{
+    CopyRequest, DeleteRequest, DirEntry, FileStat, MkDirRequest, ReadDirRequest, ReadFileRequest, ReadFileResponse,
+    ReadFileStreamRequest, RealPathRequest, RenameRequest, StatRequest, WriteFileRequest,
+};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Clone)]
+enum Node {
+    File { content: Vec<u8>, ctime: i64, mtime: i64 },
+    Directory { entries: BTreeMap<String, Node>, ctime: i64, mtime: i64 },
+}
+
+impl Node {
+    fn new_directory() -> Self {
+        let now = to_millis(SystemTime::now());
+        Node::Directory { entries: BTreeMap::new(), ctime: now, mtime: now }
+    }
+
+    fn get(&self, components: &[String]) -> Option<&Node> {
+        components.iter().try_fold(self, |node, name| match node {
+            Node::Directory { entries, .. } => entries.get(name),
+            Node::File { .. } => None,
+        })
+    }
+
+    fn stat(&self) -> FileStat {
+        let (size, ctime, mtime, is_directory) = match self {
+            Node::File { content, ctime, mtime } => (content.len() as u64, *ctime, *mtime, false),
+            Node::Directory { ctime, mtime, .. } => (0, *ctime, *mtime, true),
+        };
+        FileStat {
+            size,
+            mtime,
+            ctime,
+            is_directory,
+            is_file: !is_directory,
+            is_symlink: false,
+            permissions: 0o666,
+            readonly: false,
+            mode: None,
+            uid: None,
+            gid: None,
+            ino: None,
+            dev: None,
+            nlink: None,
+            symlink_target: None,
+            dangling: false,
+        }
+    }
+
+    fn content(&self) -> Option<&[u8]> {
+        match self {
+            Node::File { content, .. } => Some(content),
+            Node::Directory { .. } => None,
+        }
+    }
+}
+
+/// Files and directories kept in memory for the lifetime of the backend.
+pub struct MemoryBackend {
+    root: Mutex<Node>,
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        MemoryBackend { root: Mutex::new(Node::new_directory()) }
+    }
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn content(&self, components: &[String]) -> FileIOResult<(Vec<u8>, FileStat)> {
+        let root = self.root.lock().unwrap();
+        let node = root.get(components).ok_or_else(|| not_found(components))?;
+        let content = node
+            .content()
+            .ok_or_else(|| io_error(io::ErrorKind::IsADirectory, components, "is a directory"))?;
+        Ok((content.to_vec(), node.stat()))
+    }
+}
+
+fn display(components: &[String]) -> String {
+    format!("/{}", components.join("/"))
+}
+
+fn not_found(components: &[String]) -> FileIOError {
+    FileIOError::NotFound(format!("{} does not exist", display(components)))
+}
+
+fn io_error(kind: io::ErrorKind, components: &[String], what: &str) -> FileIOError {
+    io::Error::new(kind, format!("{} {}", display(components), what)).into()
+}
+
+/// Splits off the last name, failing for the root.
+fn split_last(components: &[String]) -> FileIOResult<(&[String], &String)> {
+    match components.split_last() {
+        Some((name, parent)) => Ok((parent, name)),
+        None => Err(FileIOError::InvalidArgument("The root directory cannot be modified".to_string())),
+    }
+}
+
+/// The entries of the directory at `components`, creating missing
+/// directories along the way when `create` is set.
+fn directory_mut<'a>(root: &'a mut Node, components: &[String], create: bool) -> FileIOResult<&'a mut BTreeMap<String, Node>> {
+    let mut node = root;
+    for (depth, name) in components.iter().enumerate() {
+        let Node::Directory { entries, .. } = node else {
+            return Err(io_error(io::ErrorKind::NotADirectory, &components[..depth], "is not a directory"));
+        };
+        node = match entries.entry(name.clone()) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) if create => entry.insert(Node::new_directory()),
+            std::collections::btree_map::Entry::Vacant(_) => return Err(not_found(&components[..=depth])),
+        };
+    }
+    match node {
+        Node::Directory { entries, .. } => Ok(entries),
+        Node::File { .. } => Err(io_error(io::ErrorKind::NotADirectory, components, "is not a directory")),
+    }
+}
+
+/// Marks the directory at `components` as modified after an entry was added
+/// or removed.
+fn touch(root: &mut Node, components: &[String]) {
+    let mut node = root;
+    for name in components {
+        match node {
+            Node::Directory { entries, .. } => match entries.get_mut(name) {
+                Some(child) => node = child,
+                None => return,
+            },
+            Node::File { .. } => return,
+        }
+    }
+    if let Node::Directory { mtime, .. } = node {
+        *mtime = to_millis(SystemTime::now());
+    }
+}
+
+#[async_trait]
+impl FileSystemBackend for MemoryBackend {
+    async fn read_file(&self, request: ReadFileRequest) -> FileIOResult<ReadFileResponse> {
+        let (content, stat) = self.content(&components(&request.path))?;
+        let (content, encoding, lossy) = operations::encode_content(content, request.encoding.as_deref())?;
+        Ok(ReadFileResponse { content, stat, encoding, lossy })
+    }
+
+    async fn write_file(&self, request: WriteFileRequest) -> FileIOResult<()> {
+        let components = components(&request.path);
+        let content = operations::decode_content(request.content, request.encoding.as_deref(), true)?;
+        let (parent, name) = split_last(&components)?;
+
+        let mut root = self.root.lock().unwrap();
+        let current = root.get(&components);
+        operations::verify_preconditions(
+            &display(&components),
+            &request.preconditions,
+            current.map(Node::stat),
+            current.and_then(Node::content),
+        )?;
+
+        let now = to_millis(SystemTime::now());
+        let entries = directory_mut(&mut root, parent, request.create_dirs.unwrap_or(false))?;
+        match entries.get_mut(name) {
+            Some(Node::File { content: existing, mtime, .. }) => {
+                *existing = content;
+                // Each write gets a new mtime so that mtime preconditions
+                // catch writes made within the same millisecond
+                *mtime = now.max(*mtime + 1);
+            }
+            Some(Node::Directory { .. }) => return Err(io_error(io::ErrorKind::IsADirectory, &components, "is a directory")),
+            None => {
+                entries.insert(name.clone(), Node::File { content, ctime: now, mtime: now });
+                touch(&mut root, parent);
+            }
+        }
+        Ok(())
+    }
+
+    async fn stat(&self, request: StatRequest) -> FileIOResult<FileStat> {
+        let components = components(&request.path);
+        let root = self.root.lock().unwrap();
+        root.get(&components).map(Node::stat).ok_or_else(|| not_found(&components))
+    }
+
+    async fn readdir(&self, request: ReadDirRequest) -> FileIOResult<Vec<DirEntry>> {
+        let components = components(&request.path);
+        let root = self.root.lock().unwrap();
+        let Node::Directory { entries, .. } = root.get(&components).ok_or_else(|| not_found(&components))? else {
+            return Err(io_error(io::ErrorKind::NotADirectory, &components, "is not a directory"));
+        };
+
+        Ok(entries
+            .iter()
+            .map(|(name, node)| {
+                let stat = node.stat();
+                let mut path = components.clone();
+                path.push(name.clone());
+                DirEntry {
+                    name: name.clone(),
+                    path: display(&path),
+                    is_directory: stat.is_directory,
+                    is_file: stat.is_file,
+                    is_symlink: false,
+                    size: Some(stat.size),
+                    mtime: Some(stat.mtime),
+                    symlink_target: None,
+                    dangling: false,
+                }
+            })
+            .collect())
+    }
+
+    async fn mkdir(&self, request: MkDirRequest) -> FileIOResult<()> {
+        let components = components(&request.path);
+        let mut root = self.root.lock().unwrap();
+        if request.recursive.unwrap_or(false) {
+            directory_mut(&mut root, &components, true)?;
+            return Ok(());
+        }
+
+        let (parent, name) = split_last(&components)?;
+        let entries = directory_mut(&mut root, parent, false)?;
+        if entries.contains_key(name) {
+            return Err(FileIOError::AlreadyExists(format!("{} already exists", display(&components))));
+        }
+        entries.insert(name.clone(), Node::new_directory());
+        touch(&mut root, parent);
+        Ok(())
+    }
+
+    async fn delete(&self, request: DeleteRequest) -> FileIOResult<()> {
+        let components = components(&request.path);
+        let (parent, name) = split_last(&components)?;
+
+        let mut root = self.root.lock().unwrap();
+        let node = root.get(&components).ok_or_else(|| not_found(&components))?;
+        operations::verify_preconditions(&display(&components), &request.preconditions, Some(node.stat()), node.content())?;
+        if matches!(node, Node::Directory { entries, .. } if !entries.is_empty()) && !request.recursive.unwrap_or(false) {
+            return Err(io_error(io::ErrorKind::DirectoryNotEmpty, &components, "is not empty"));
+        }
+
+        directory_mut(&mut root, parent, false)?.remove(name);
+        touch(&mut root, parent);
+        Ok(())
+    }
+
+    async fn rename(&self, request: RenameRequest) -> FileIOResult<()> {
+        let from = components(&request.old_path);
+        let to = components(&request.new_path);
+        let (from_parent, from_name) = split_last(&from)?;
+        let (to_parent, to_name) = split_last(&to)?;
+
+        let mut root = self.root.lock().unwrap();
+        let node = root.get(&from).ok_or_else(|| not_found(&from))?;
+        operations::verify_preconditions(&display(&from), &request.preconditions, Some(node.stat()), node.content())?;
+        if from == to {
+            return Ok(());
+        }
+        if to.starts_with(&from) {
+            return Err(FileIOError::InvalidArgument(format!(
+                "Cannot move {} into itself",
+                display(&from)
+            )));
+        }
+
+        // Replacing follows rename(2): a file replaces a file, and a
+        // directory an empty directory
+        match (node, root.get(&to)) {
+            (_, None) => {}
+            (Node::File { .. }, Some(Node::File { .. })) => {}
+            (Node::Directory { .. }, Some(Node::Directory { entries, .. })) if entries.is_empty() => {}
+            (Node::Directory { .. }, Some(Node::Directory { .. })) => {
+                return Err(io_error(io::ErrorKind::DirectoryNotEmpty, &to, "is not empty"))
+            }
+            (Node::Directory { .. }, Some(Node::File { .. })) => {
+                return Err(io_error(io::ErrorKind::NotADirectory, &to, "is not a directory"))
+            }
+            (Node::File { .. }, Some(Node::Directory { .. })) => {
+                return Err(io_error(io::ErrorKind::IsADirectory, &to, "is a directory"))
+            }
+        }
+        directory_mut(&mut root, to_parent, false)?;
+
+        let node = directory_mut(&mut root, from_parent, false)?.remove(from_name).ok_or_else(|| not_found(&from))?;
+        directory_mut(&mut root, to_parent, false)?.insert(to_name.clone(), node);
+        touch(&mut root, from_parent);
+        touch(&mut root, to_parent);
+        Ok(())
+    }
+
+    async fn copy(&self, request: CopyRequest) -> FileIOResult<()> {
+        let source = components(&request.source);
+        let destination = components(&request.destination);
+        if source == destination {
+            return Ok(());
+        }
+        let (parent, name) = split_last(&destination)?;
+
+        let mut root = self.root.lock().unwrap();
+        let node = root.get(&source).ok_or_else(|| not_found(&source))?.clone();
+        if root.get(&destination).is_some() && !request.overwrite.unwrap_or(false) {
+            return Err(FileIOError::AlreadyExists("Destination already exists".to_string()));
+        }
+        directory_mut(&mut root, parent, false)?.insert(name.clone(), node);
+        touch(&mut root, parent);
+        Ok(())
+    }
+
+    async fn realpath(&self, request: RealPathRequest) -> FileIOResult<String> {
+        let components = components(&request.path);
+        let root = self.root.lock().unwrap();
+        root.get(&components).ok_or_else(|| not_found(&components))?;
+        Ok(display(&components))
+    }
+
+    async fn read_file_stream(
+        &self,
+        request: ReadFileStreamRequest,
+        cancellation: CancellationToken,
+        on_chunk: ChunkSink,
+    ) -> FileIOResult<()> {
+        let (content, _) = self.content(&components(&request.path))?;
+        operations::stream_reader(io::Cursor::new(content), request, cancellation, on_chunk).await
+    }
+}
@@ -3,28 +3,43 @@
  *  Licensed under the MIT License. See License.txt in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
 
+pub mod archive;
+pub mod backend;
+pub mod batch;
+pub mod encoding;
+pub mod errors;
+pub mod handles;
+pub mod hash;
 pub mod locks;
+pub mod memory;
 pub mod operations;
 pub mod platform;
+pub mod sandbox;
 pub mod service;
+pub mod trash;
 pub mod types;
+pub mod usage;
+pub mod walk;
 
-pub use service::FileIOService;
-pub use types::{FileIORequest, FileIOResponse};
+#[cfg(test)]
+mod tests;
+
+pub use errors::{FileIOError, FileIOResult, FileSystemProviderErrorCode};
+pub use service::{FileIOService, FileIOServiceConfig};
+pub use types::{FileIORequest, FileIORequestMessage, FileIOResponse, FileIOResponseMessage};
 
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 /// Start the file I/O service and return a handle for IPC communication
-pub async fn start_fileio_service(ipc_sink: Arc<dyn Fn(String) + Send + Sync>) -> FileIOService {
-    FileIOService::new(ipc_sink)
+pub async fn start_fileio_service(ipc_sink: Arc<dyn Fn(String) + Send + Sync>, config: FileIOServiceConfig) -> FileIOService {
+    FileIOService::with_config(ipc_sink, config)
 }
 
-/// Handle an IPC request for file I/O operations
+/// Handle an IPC request for file I/O operations. Requests run concurrently;
+/// the service serializes conflicting ones through its lock manager.
 pub async fn handle_fileio_request(
-    service: &Arc<Mutex<FileIOService>>,
-    request: &crate::rpc::RequestParams<FileIORequest>,
+    service: &Arc<FileIOService>,
+    request: &crate::rpc::RequestParams<FileIORequestMessage>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let service = service.lock().await;
     service.handle_request(request).await
 }
\ No newline at end of file
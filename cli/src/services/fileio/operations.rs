@@ -3,56 +3,55 @@
  *  Licensed under the MIT License. See License.txt in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
 
+use crate::services::fileio::encoding;
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::handles::HandleTable;
+use crate::services::fileio::hash;
 use crate::services::fileio::types::*;
 use crate::services::fileio::platform;
 use crate::services::paths as paths;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64_STANDARD};
-use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::fs;
-use tokio::io::AsyncReadExt;
-use tokio::sync::Semaphore;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
 
-// Global state for handle management
-lazy_static::lazy_static! {
-    static ref HANDLE_MAP: Arc<Mutex<HashMap<u32, std::fs::File>>> = Arc::new(Mutex::new(HashMap::new()));
-    static ref RESOURCE_LOCKS: Arc<Mutex<HashMap<String, Arc<Semaphore>>>> = Arc::new(Mutex::new(HashMap::new()));
-    static ref NEXT_HANDLE: Arc<Mutex<u32>> = Arc::new(Mutex::new(1));
+const DEFAULT_STREAM_BUFFER_SIZE: u32 = 256 * 1024;
+
+/// Milliseconds since the Unix epoch, negative for earlier times.
+pub(crate) fn to_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
 }
 
-pub async fn read_file(request: ReadFileRequest) -> Result<ReadFileResponse, Box<dyn std::error::Error + Send + Sync>> {
-    let path = paths::to_path_buf(&request.path)?;
-    let encoding = request.encoding.as_deref().unwrap_or("utf8");
+fn from_millis(millis: i64) -> SystemTime {
+    if millis >= 0 {
+        UNIX_EPOCH + Duration::from_millis(millis as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_millis(millis.unsigned_abs())
+    }
+}
 
-    let content = match encoding {
-        "utf8" => fs::read_to_string(&path).await?,
-        "base64" => {
-            let bytes = fs::read(&path).await?;
-            BASE64_STANDARD.encode(bytes)
-        }
-        _ => return Err(format!("Unsupported encoding: {}", encoding).into()),
-    };
+pub async fn read_file(request: ReadFileRequest) -> FileIOResult<ReadFileResponse> {
+    let path = paths::to_path_buf(&request.path)?;
+    let bytes = fs::read(&path).await?;
+    let (content, encoding, lossy) = encode_content(bytes, request.encoding.as_deref())?;
 
     let metadata = fs::metadata(&path).await?;
-    let stat = FileStat {
-        size: metadata.len(),
-        mtime: metadata.modified()?.duration_since(std::time::UNIX_EPOCH)?.as_millis() as i64,
-        ctime: metadata.created()?.duration_since(std::time::UNIX_EPOCH)?.as_millis() as i64,
-        is_directory: metadata.is_dir(),
-        is_file: metadata.is_file(),
-        is_symlink: metadata.file_type().is_symlink(),
-        permissions: 0, // TODO: Convert permissions properly
-    };
+    let stat = file_stat(&metadata)?;
 
-    Ok(ReadFileResponse { content, stat })
+    Ok(ReadFileResponse { content, stat, encoding, lossy })
 }
 
-pub async fn write_file(request: WriteFileRequest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn write_file(request: WriteFileRequest) -> FileIOResult<()> {
     let path = paths::to_path_buf(&request.path)?;
-    let encoding = request.encoding.as_deref().unwrap_or("utf8");
     let create_dirs = request.create_dirs.unwrap_or(false);
+    check_preconditions(&path, &request.preconditions).await?;
 
     if create_dirs {
         if let Some(parent) = path.parent() {
@@ -60,14 +59,11 @@ pub async fn write_file(request: WriteFileRequest) -> Result<(), Box<dyn std::er
         }
     }
 
-    let content_bytes = match encoding {
-        "utf8" => request.content.into_bytes(),
-        "base64" => BASE64_STANDARD.decode(&request.content)?,
-        _ => return Err(format!("Unsupported encoding: {}", encoding).into()),
-    };
+    let content_bytes = decode_content(request.content, request.encoding.as_deref(), true)?;
 
     if let Some(atomic_opts) = &request.atomic {
-        write_file_atomic(&path, &content_bytes, &atomic_opts.postfix).await?;
+        let preserve_xattrs = atomic_opts.preserve_xattrs.unwrap_or(false);
+        write_file_atomic(&path, &content_bytes, &atomic_opts.postfix, preserve_xattrs).await?;
     } else {
         fs::write(&path, &content_bytes).await?;
     }
@@ -75,28 +71,38 @@ pub async fn write_file(request: WriteFileRequest) -> Result<(), Box<dyn std::er
     Ok(())
 }
 
-pub async fn write_file_atomic(path: &Path, content: &[u8], postfix: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Stat the target path and reject atomic writes on symbolic links
-    if let Ok(metadata) = std::fs::metadata(path) {
-        if metadata.file_type().is_symlink() {
-            return Err("Atomic writes are not supported for symbolic links".into());
+/// Converts file bytes into response content, as `base64` or as text decoded
+/// from the given encoding. Returns the content, the encoding actually used
+/// and whether invalid sequences were replaced.
+pub fn encode_content(bytes: Vec<u8>, encoding: Option<&str>) -> FileIOResult<(String, String, bool)> {
+    match encoding.unwrap_or("utf8") {
+        "base64" => Ok((BASE64_STANDARD.encode(bytes), "base64".to_string(), false)),
+        label => {
+            let decoded = encoding::decode(&bytes, label)?;
+            Ok((decoded.content, decoded.encoding.id(), decoded.lossy))
         }
     }
+}
 
-    // Construct the temp path as a sibling in the same directory using a configurable postfix
-    let mut temp_path = path.to_path_buf();
-    let file_name = path.file_name().unwrap_or(OsStr::new("")).to_string_lossy();
-    temp_path.set_file_name(format!("{}{}", file_name, postfix));
-
-    // Acquire resource locks for both the target and temp resources while writing and renaming
-    let target_key = path.to_string_lossy().to_string();
-    let temp_key = temp_path.to_string_lossy().to_string();
+/// Converts request content, sent as `base64` or as text in the given
+/// encoding, into the bytes to write. `include_bom` prefixes the encoding's
+/// BOM, e.g. for the first chunk of a stream.
+pub fn decode_content(content: String, encoding: Option<&str>, include_bom: bool) -> FileIOResult<Vec<u8>> {
+    match encoding.unwrap_or("utf8") {
+        "utf8" => Ok(content.into_bytes()),
+        "base64" => BASE64_STANDARD
+            .decode(&content)
+            .map_err(|e| FileIOError::InvalidArgument(format!("Invalid base64 content: {}", e))),
+        label => encoding::encode(&content, label, include_bom),
+    }
+}
 
-    let target_lock = acquire_resource_lock(&target_key).await;
-    let temp_lock = acquire_resource_lock(&temp_key).await;
+pub async fn write_file_atomic(path: &Path, content: &[u8], postfix: &str, preserve_xattrs: bool) -> FileIOResult<()> {
+    reject_symlink_target(path)?;
 
-    let _target_guard = target_lock.acquire().await.unwrap();
-    let _temp_guard = temp_lock.acquire().await.unwrap();
+    // Callers hold the service's exclusive lock on `path` while writing
+    // and renaming
+    let temp_path = atomic_temp_path(path, postfix);
 
     // Write to temp file
     fs::write(&temp_path, content).await?;
@@ -109,23 +115,130 @@ pub async fn write_file_atomic(path: &Path, content: &[u8], postfix: &str) -> Re
         temp_file.sync_data()?;
     }
 
-    // Rename over target
-    match fs::rename(&temp_path, path).await {
+    // The rename replaces the target's inode, and its attributes with it
+    if preserve_xattrs && std::fs::symlink_metadata(path).is_ok() {
+        if let Err(e) = platform::copy_xattrs(path, &temp_path) {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(e);
+        }
+    }
+
+    commit_atomic_write(&temp_path, path).await
+}
+
+/// Stat the target path and reject atomic writes on symbolic links
+fn reject_symlink_target(path: &Path) -> FileIOResult<()> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_symlink() {
+            return Err(FileIOError::Unsupported("Atomic writes are not supported for symbolic links".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Construct the temp path as a sibling in the same directory using a configurable postfix
+fn atomic_temp_path(path: &Path, postfix: &str) -> PathBuf {
+    let mut temp_path = path.to_path_buf();
+    let file_name = path.file_name().unwrap_or(OsStr::new("")).to_string_lossy();
+    temp_path.set_file_name(format!("{}{}", file_name, postfix));
+    temp_path
+}
+
+/// Renames a fully written and synced temp file over its target.
+async fn commit_atomic_write(temp_path: &Path, path: &Path) -> FileIOResult<()> {
+    match fs::rename(temp_path, path).await {
         Ok(()) => Ok(()),
         Err(e) => {
             // On rename failure, attempt to remove the temp file, ignoring errors so the original error bubbles up
-            let _ = fs::remove_file(&temp_path).await;
+            let _ = fs::remove_file(temp_path).await;
             Err(e.into())
         }
     }
 }
 
-async fn acquire_resource_lock(key: &str) -> Arc<Semaphore> {
-    let mut locks = RESOURCE_LOCKS.lock().unwrap();
-    locks.entry(key.to_string()).or_insert_with(|| Arc::new(Semaphore::new(1))).clone()
+/// An in-progress chunked write. Chunks are appended to a temp sibling of the
+/// target, which only replaces the target once the stream is committed.
+pub struct WriteFileStream {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: fs::File,
+    next_sequence: u32,
+    bytes_written: u64,
+}
+
+impl WriteFileStream {
+    pub async fn create(path: &str, create_dirs: bool) -> FileIOResult<Self> {
+        let path = paths::to_path_buf(path)?;
+        reject_symlink_target(&path)?;
+
+        if create_dirs {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+        }
+
+        let temp_path = atomic_temp_path(&path, &format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .await?;
+
+        Ok(WriteFileStream {
+            path,
+            temp_path,
+            file,
+            next_sequence: 0,
+            bytes_written: 0,
+        })
+    }
+
+    /// The file the stream replaces when committed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends the chunk with the given sequence number, which must be the next
+    /// one expected. Returns the total number of bytes written so far.
+    pub async fn write_chunk(&mut self, sequence: u32, data: &[u8]) -> FileIOResult<u64> {
+        if sequence != self.next_sequence {
+            return Err(FileIOError::InvalidArgument(format!(
+                "Invalid chunk sequence: expected {}, got {}",
+                self.next_sequence, sequence
+            )));
+        }
+
+        self.file.write_all(data).await?;
+        self.next_sequence += 1;
+        self.bytes_written += data.len() as u64;
+        Ok(self.bytes_written)
+    }
+
+    /// Syncs the temp file and atomically moves it over the target.
+    /// The caller holds the service's exclusive lock on `path()`.
+    pub async fn commit(mut self) -> FileIOResult<u64> {
+        let synced = match self.file.flush().await {
+            Ok(()) => self.file.sync_data().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = synced {
+            let _ = fs::remove_file(&self.temp_path).await;
+            return Err(e.into());
+        }
+        drop(self.file);
+
+        commit_atomic_write(&self.temp_path, &self.path).await?;
+        Ok(self.bytes_written)
+    }
+
+    /// Discards everything written so far, leaving the target untouched.
+    pub async fn abort(self) {
+        drop(self.file);
+        let _ = fs::remove_file(&self.temp_path).await;
+    }
 }
 
-pub async fn copy(request: CopyRequest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn copy(request: CopyRequest) -> FileIOResult<()> {
     let source = paths::to_path_buf(&request.source)?;
     let destination = paths::to_path_buf(&request.destination)?;
     let overwrite = request.overwrite.unwrap_or(false);
@@ -136,18 +249,254 @@ pub async fn copy(request: CopyRequest) -> Result<(), Box<dyn std::error::Error
     }
 
     if destination.exists() && !overwrite {
-        return Err("Destination already exists".into());
+        return Err(FileIOError::AlreadyExists("Destination already exists".to_string()));
     }
 
-    // Handle symlinks properly (TS provider supports symlink preservation)
+    // Recreate symlinks instead of copying their target, like the TS provider
+    if fs::symlink_metadata(&source).await?.file_type().is_symlink() {
+        return copy_tree_symlink(&source, &destination, overwrite).await;
+    }
     platform::copy_file(&source, &destination).await?;
+    if request.preserve_xattrs.unwrap_or(false) {
+        platform::copy_xattrs(&source, &destination)?;
+    }
+    Ok(())
+}
+
+const COPY_TREE_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Copies a file or directory tree, or moves it when `remove_source` is set.
+/// Symlinks are recreated rather than followed, and permissions and mtimes are
+/// preserved. A failing entry is recorded and skipped so the rest of the tree
+/// is still copied; a move only removes the source if nothing failed. A move
+/// within one filesystem is a single rename, which copies no files and so
+/// reports none.
+pub async fn copy_tree<F>(
+    request: CopyTreeRequest,
+    remove_source: bool,
+    cancellation: CancellationToken,
+    mut on_progress: F,
+) -> FileIOResult<CopyTreeResponse>
+where
+    F: FnMut(CopyTreeProgress) + Send,
+{
+    let source = paths::to_path_buf(&request.source)?;
+    let destination = paths::to_path_buf(&request.destination)?;
+    let overwrite = request.overwrite.unwrap_or(false);
+
+    if source == destination {
+        return Ok(CopyTreeResponse::default());
+    }
+    if destination.starts_with(&source) {
+        return Err(FileIOError::InvalidArgument("Cannot copy a directory into itself".to_string()));
+    }
+
+    let root_metadata = fs::symlink_metadata(&source).await?;
+    if fs::symlink_metadata(&destination).await.is_ok() && !overwrite {
+        return Err(FileIOError::AlreadyExists("Destination already exists".to_string()));
+    }
+
+    // A rename needs no scan, so a move within one filesystem is not slowed
+    // down by the size of the tree
+    if remove_source {
+        match fs::rename(&source, &destination).await {
+            Ok(()) => {
+                on_progress(CopyTreeProgress::default());
+                return Ok(CopyTreeResponse::default());
+            }
+            // Fall back to copy and delete across devices or onto an existing tree
+            Err(e) if matches!(
+                e.kind(),
+                std::io::ErrorKind::CrossesDevices | std::io::ErrorKind::DirectoryNotEmpty | std::io::ErrorKind::AlreadyExists
+            ) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let source_is_dir = root_metadata.is_dir();
+    let (files_total, bytes_total) = scan_tree(&source, &root_metadata).await;
+    let mut progress = CopyTreeProgress {
+        files_total,
+        bytes_total,
+        ..Default::default()
+    };
+
+    let mut failures = Vec::new();
+    // Directory attributes are applied once their contents are written, so
+    // that a read-only directory can still be filled
+    let mut directories = Vec::new();
+    let mut pending = vec![(source.clone(), destination.clone(), root_metadata)];
+    let mut last_report: Option<std::time::Instant> = None;
+    let mut cancelled = false;
+
+    while let Some((from, to, metadata)) = pending.pop() {
+        if cancellation.is_cancelled() {
+            cancelled = true;
+            break;
+        }
+
+        let file_type = metadata.file_type();
+        let result = if file_type.is_dir() {
+            copy_tree_directory(&from, &to, &mut pending, &mut failures).await.map(|()| {
+                directories.push((to, metadata));
+            })
+        } else {
+            let copied = if file_type.is_symlink() {
+                copy_tree_symlink(&from, &to, overwrite).await
+            } else {
+                copy_tree_file(&from, &to, &metadata, overwrite).await
+            };
+            copied.map(|()| {
+                progress.files_done += 1;
+                if !file_type.is_symlink() {
+                    progress.bytes_done += metadata.len();
+                }
+            })
+        };
+
+        match result {
+            Ok(()) => {
+                if last_report.is_none_or(|at| at.elapsed() >= COPY_TREE_PROGRESS_INTERVAL) {
+                    progress.current = Some(from.to_string_lossy().to_string());
+                    on_progress(progress.clone());
+                    last_report = Some(std::time::Instant::now());
+                }
+            }
+            Err(e) => failures.push(copy_tree_failure(&from, &e)),
+        }
+    }
+
+    for (path, metadata) in directories.iter().rev() {
+        if let Err(e) = preserve_attributes(path, metadata) {
+            failures.push(copy_tree_failure(path, &e));
+        }
+    }
+
+    if remove_source && !cancelled && failures.is_empty() {
+        let removed = if source_is_dir {
+            fs::remove_dir_all(&source).await
+        } else {
+            fs::remove_file(&source).await
+        };
+        if let Err(e) = removed {
+            failures.push(copy_tree_failure(&source, &e.into()));
+        }
+    }
+
+    progress.current = None;
+    on_progress(progress.clone());
+
+    Ok(CopyTreeResponse {
+        files_done: progress.files_done,
+        bytes_done: progress.bytes_done,
+        failures,
+        cancelled,
+    })
+}
+
+/// Counts the files and bytes under `path` for progress reporting. Entries
+/// that cannot be read are left out; they are reported during the copy.
+async fn scan_tree(path: &Path, metadata: &std::fs::Metadata) -> (u64, u64) {
+    if !metadata.is_dir() {
+        return (1, if metadata.file_type().is_symlink() { 0 } else { metadata.len() });
+    }
+
+    let (mut files, mut bytes) = (0, 0);
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else { continue };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(metadata) = fs::symlink_metadata(entry.path()).await else { continue };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                files += 1;
+                if metadata.is_file() {
+                    bytes += metadata.len();
+                }
+            }
+        }
+    }
+    (files, bytes)
+}
+
+async fn copy_tree_directory(
+    from: &Path,
+    to: &Path,
+    pending: &mut Vec<(PathBuf, PathBuf, std::fs::Metadata)>,
+    failures: &mut Vec<CopyTreeFailure>,
+) -> FileIOResult<()> {
+    match fs::create_dir(to).await {
+        Ok(()) => {}
+        // Merge into an existing directory; overwrite was checked for the root
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && fs::metadata(to).await?.is_dir() => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut entries = fs::read_dir(from).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let child = entry.path();
+        match fs::symlink_metadata(&child).await {
+            Ok(metadata) => pending.push((child, to.join(entry.file_name()), metadata)),
+            Err(e) => failures.push(copy_tree_failure(&child, &e.into())),
+        }
+    }
+    Ok(())
+}
+
+async fn copy_tree_file(from: &Path, to: &Path, metadata: &std::fs::Metadata, overwrite: bool) -> FileIOResult<()> {
+    if let Ok(existing) = fs::symlink_metadata(to).await {
+        if !overwrite {
+            return Err(FileIOError::AlreadyExists(format!("{} already exists", to.display())));
+        }
+        // Replace a symlink instead of writing through it
+        if existing.file_type().is_symlink() {
+            fs::remove_file(to).await?;
+        }
+    }
+
+    platform::copy_file(from, to).await?;
+    preserve_attributes(to, metadata)
+}
+
+async fn copy_tree_symlink(from: &Path, to: &Path, overwrite: bool) -> FileIOResult<()> {
+    if let Ok(existing) = fs::symlink_metadata(to).await {
+        if !overwrite || existing.is_dir() {
+            return Err(FileIOError::AlreadyExists(format!("{} already exists", to.display())));
+        }
+        fs::remove_file(to).await?;
+    }
+
+    let target = fs::read_link(from).await?;
+    let is_dir = fs::metadata(from).await.map(|m| m.is_dir()).unwrap_or(false);
+    platform::create_symlink(&target, to, is_dir)
+}
+
+/// Copies the mtime, atime and permissions of `metadata` onto `path`. Times
+/// go first since the permissions may stop the file from being opened.
+fn preserve_attributes(path: &Path, metadata: &std::fs::Metadata) -> FileIOResult<()> {
+    let mut times = std::fs::FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    platform::open_for_attributes(path)?.set_times(times)?;
+    std::fs::set_permissions(path, metadata.permissions())?;
     Ok(())
 }
 
-pub async fn delete(request: DeleteRequest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+fn copy_tree_failure(path: &Path, e: &FileIOError) -> CopyTreeFailure {
+    CopyTreeFailure {
+        path: path.to_string_lossy().to_string(),
+        code: e.code().to_string(),
+        message: e.to_string(),
+    }
+}
+
+pub async fn delete(request: DeleteRequest) -> FileIOResult<()> {
     let path = paths::to_path_buf(&request.path)?;
     let recursive = request.recursive.unwrap_or(false);
     let atomic = request.atomic.unwrap_or(false);
+    check_preconditions(&path, &request.preconditions).await?;
 
     if atomic && recursive {
         // For atomic recursive delete, use platform-specific implementation
@@ -167,52 +516,309 @@ pub async fn delete(request: DeleteRequest) -> Result<(), Box<dyn std::error::Er
     Ok(())
 }
 
-pub async fn stat(request: StatRequest) -> Result<FileStat, Box<dyn std::error::Error + Send + Sync>> {
+/// Fails with ENOTEMPTY, as a non-recursive `delete` does, if `path` is a
+/// directory with entries and `recursive` is not set. For deletes that move
+/// the whole directory, such as to the trash.
+pub async fn check_deletable(path: &Path, recursive: bool) -> FileIOResult<()> {
+    if recursive || !fs::symlink_metadata(path).await?.is_dir() {
+        return Ok(());
+    }
+    if fs::read_dir(path).await?.next_entry().await?.is_some() {
+        let message = format!("{} is not empty", path.display());
+        return Err(std::io::Error::new(std::io::ErrorKind::DirectoryNotEmpty, message).into());
+    }
+    Ok(())
+}
+
+/// Fails with `PreconditionFailed` unless `path` still has the expected
+/// mtime, size and content hash. Callers hold the path's exclusive lock so the
+/// check and the mutation that follows are atomic with respect to this service.
+pub async fn check_preconditions(path: &Path, preconditions: &FilePreconditions) -> FileIOResult<()> {
+    if preconditions.is_empty() {
+        return Ok(());
+    }
+
+    let metadata = match fs::metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return verify_preconditions(&path.display().to_string(), preconditions, None, None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let content_hash = if preconditions.expected_hash.is_some() && metadata.is_file() {
+        Some(hash::file_sha256_hex(path).await?)
+    } else {
+        None
+    };
+    compare_preconditions(&path.display().to_string(), preconditions, Some(file_stat(&metadata)?), content_hash.as_deref())
+}
+
+/// Compares preconditions with the current state of a file, `None` if it no
+/// longer exists. `content` is only needed when a hash is expected, and is
+/// `None` for directories.
+pub fn verify_preconditions(
+    path: &str,
+    preconditions: &FilePreconditions,
+    current: Option<FileStat>,
+    content: Option<&[u8]>,
+) -> FileIOResult<()> {
+    let content_hash = preconditions.expected_hash.as_ref().and(content).map(hash::sha256_hex);
+    compare_preconditions(path, preconditions, current, content_hash.as_deref())
+}
+
+fn compare_preconditions(
+    path: &str,
+    preconditions: &FilePreconditions,
+    current: Option<FileStat>,
+    content_hash: Option<&str>,
+) -> FileIOResult<()> {
+    if preconditions.is_empty() {
+        return Ok(());
+    }
+    let Some(current) = current else {
+        return Err(FileIOError::PreconditionFailed {
+            message: format!("{} no longer exists", path),
+            current: None,
+        });
+    };
+
+    let mismatch = if preconditions.expected_mtime.is_some_and(|mtime| mtime != current.mtime) {
+        Some("modification time")
+    } else if preconditions.expected_size.is_some_and(|size| size != current.size) {
+        Some("size")
+    } else if let Some(expected_hash) = &preconditions.expected_hash {
+        (!content_hash.unwrap_or_default().eq_ignore_ascii_case(expected_hash)).then_some("content hash")
+    } else {
+        None
+    };
+
+    match mismatch {
+        Some(what) => Err(FileIOError::PreconditionFailed {
+            message: format!("{} has changed ({} does not match)", path, what),
+            current: Some(Box::new(current)),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Stats `path`, following a symlink to describe its target. A dangling
+/// symlink is described by the link itself.
+pub async fn stat(request: StatRequest) -> FileIOResult<FileStat> {
     let path = paths::to_path_buf(&request.path)?;
-    let metadata = fs::metadata(&path).await?;
+    let metadata = fs::symlink_metadata(&path).await?;
+    if !metadata.file_type().is_symlink() {
+        return file_stat(&metadata);
+    }
 
-    Ok(FileStat {
+    let (target, dangling, target_metadata) = resolve_symlink(&path).await?;
+    let mut stat = file_stat(target_metadata.as_ref().unwrap_or(&metadata))?;
+    stat.is_symlink = true;
+    stat.symlink_target = Some(target);
+    stat.dangling = dangling;
+    Ok(stat)
+}
+
+/// Reads a symlink's target and stats what it points to, which is `None` when
+/// the link is dangling. A link whose target can't be resolved, because of a
+/// loop, a non-directory path component or a denied lookup, counts as dangling.
+async fn resolve_symlink(path: &Path) -> FileIOResult<(String, bool, Option<std::fs::Metadata>)> {
+    let target = fs::read_link(path).await?.to_string_lossy().to_string();
+    match fs::metadata(path).await {
+        Ok(metadata) => Ok((target, false, Some(metadata))),
+        Err(e) if is_unresolvable(&e) => Ok((target, true, None)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn is_unresolvable(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    #[cfg(unix)]
+    {
+        if e.raw_os_error() == Some(libc::ELOOP) {
+            return true;
+        }
+    }
+
+    matches!(e.kind(), ErrorKind::NotFound | ErrorKind::PermissionDenied | ErrorKind::NotADirectory)
+}
+
+/// Builds a `FileStat` from metadata, including POSIX mode and ownership on Unix.
+pub fn file_stat(metadata: &std::fs::Metadata) -> FileIOResult<FileStat> {
+    let mtime = to_millis(metadata.modified()?);
+    // Not every filesystem records a birth time
+    let ctime = metadata.created().map(to_millis).unwrap_or(mtime);
+    let readonly = metadata.permissions().readonly();
+
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut stat = FileStat {
         size: metadata.len(),
-        mtime: metadata.modified()?.duration_since(std::time::UNIX_EPOCH)?.as_millis() as i64,
-        ctime: metadata.created()?.duration_since(std::time::UNIX_EPOCH)?.as_millis() as i64,
+        mtime,
+        ctime,
         is_directory: metadata.is_dir(),
         is_file: metadata.is_file(),
         is_symlink: metadata.file_type().is_symlink(),
-        permissions: 0, // TODO: Convert permissions properly
-    })
+        permissions: if readonly { 0o444 } else { 0o666 },
+        readonly,
+        mode: None,
+        uid: None,
+        gid: None,
+        ino: None,
+        dev: None,
+        nlink: None,
+        symlink_target: None,
+        dangling: false,
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        stat.permissions = metadata.mode() & 0o7777;
+        stat.mode = Some(metadata.mode());
+        stat.uid = Some(metadata.uid());
+        stat.gid = Some(metadata.gid());
+        stat.ino = Some(metadata.ino());
+        stat.dev = Some(metadata.dev());
+        stat.nlink = Some(metadata.nlink());
+    }
+
+    Ok(stat)
+}
+
+pub async fn chmod(request: ChmodRequest) -> FileIOResult<()> {
+    let path = paths::to_path_buf(&request.path)?;
+    platform::set_mode(&path, request.mode)
 }
 
-pub async fn readdir(request: ReadDirRequest) -> Result<Vec<DirEntry>, Box<dyn std::error::Error + Send + Sync>> {
+pub async fn chown(request: ChownRequest) -> FileIOResult<()> {
+    let path = paths::to_path_buf(&request.path)?;
+    let follow_symlinks = request.follow_symlinks.unwrap_or(true);
+    platform::set_owner(&path, request.uid, request.gid, follow_symlinks)
+}
+
+pub async fn set_times(request: SetTimesRequest) -> FileIOResult<()> {
+    let path = paths::to_path_buf(&request.path)?;
+
+    let mut times = std::fs::FileTimes::new();
+    if let Some(atime) = request.atime {
+        times = times.set_accessed(from_millis(atime));
+    }
+    if let Some(mtime) = request.mtime {
+        times = times.set_modified(from_millis(mtime));
+    }
+
+    platform::open_for_attributes(&path)?.set_times(times)?;
+    Ok(())
+}
+
+pub async fn readdir(request: ReadDirRequest) -> FileIOResult<Vec<DirEntry>> {
     let path = paths::to_path_buf(&request.path)?;
     let mut entries = Vec::new();
 
     let mut dir_entries = fs::read_dir(&path).await?;
     while let Some(entry) = dir_entries.next_entry().await? {
-        let metadata = entry.metadata().await?;
+        let mut metadata = entry.metadata().await?;
         let name = entry.file_name().to_string_lossy().to_string();
-        let entry_path = entry.path().to_string_lossy().to_string();
+        let is_symlink = metadata.file_type().is_symlink();
+
+        // Symlinks are described by their target, as in `stat`
+        let (symlink_target, dangling) = if is_symlink {
+            let (target, dangling, target_metadata) = resolve_symlink(&entry.path()).await?;
+            metadata = target_metadata.unwrap_or(metadata);
+            (Some(target), dangling)
+        } else {
+            (None, false)
+        };
 
         entries.push(DirEntry {
             name,
-            path: entry_path,
+            path: entry.path().to_string_lossy().to_string(),
             is_directory: metadata.is_dir(),
             is_file: metadata.is_file(),
-            is_symlink: metadata.file_type().is_symlink(),
+            is_symlink,
             size: Some(metadata.len()),
-            mtime: Some(metadata.modified()?.duration_since(std::time::UNIX_EPOCH)?.as_millis() as i64),
+            mtime: Some(to_millis(metadata.modified()?)),
+            symlink_target,
+            dangling,
         });
     }
 
     Ok(entries)
 }
 
-pub async fn realpath(request: RealPathRequest) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+/// Creates a symlink at `request.path`. Fails with EEXIST if anything,
+/// including a dangling symlink, is already there.
+pub async fn symlink(request: SymlinkRequest) -> FileIOResult<()> {
+    let path = paths::to_path_buf(&request.path)?;
+    let target = PathBuf::from(&request.target);
+    let is_dir = match request.is_directory {
+        Some(is_dir) => is_dir,
+        None => {
+            let resolved = match path.parent() {
+                Some(parent) => parent.join(&target),
+                None => target.clone(),
+            };
+            fs::metadata(&resolved).await.is_ok_and(|m| m.is_dir())
+        }
+    };
+
+    if fs::symlink_metadata(&path).await.is_ok() {
+        return Err(FileIOError::AlreadyExists(format!("{} already exists", path.display())));
+    }
+    platform::create_symlink(&target, &path, is_dir)
+}
+
+pub async fn readlink(request: ReadLinkRequest) -> FileIOResult<String> {
+    let path = paths::to_path_buf(&request.path)?;
+    let target = fs::read_link(&path).await?;
+    Ok(target.to_string_lossy().to_string())
+}
+
+pub async fn hard_link(request: HardLinkRequest) -> FileIOResult<()> {
+    let target = paths::to_path_buf(&request.target)?;
+    let path = paths::to_path_buf(&request.path)?;
+    if fs::metadata(&target).await?.is_dir() {
+        return Err(FileIOError::InvalidArgument(format!("Cannot hard link directory {}", target.display())));
+    }
+    fs::hard_link(&target, &path).await?;
+    Ok(())
+}
+
+pub async fn list_xattr(request: ListXattrRequest) -> FileIOResult<Vec<String>> {
+    let path = paths::to_path_buf(&request.path)?;
+    run_blocking(move || platform::list_xattrs(&path)).await
+}
+
+pub async fn get_xattr(request: XattrRequest) -> FileIOResult<String> {
+    let path = paths::to_path_buf(&request.path)?;
+    let value = run_blocking(move || {
+        platform::get_xattr(&path, &request.name)?
+            .ok_or_else(|| FileIOError::NotFound(format!("{} has no attribute {}", path.display(), request.name)))
+    })
+    .await?;
+    Ok(BASE64_STANDARD.encode(value))
+}
+
+pub async fn set_xattr(request: SetXattrRequest) -> FileIOResult<()> {
+    let path = paths::to_path_buf(&request.path)?;
+    let value = BASE64_STANDARD
+        .decode(&request.value)
+        .map_err(|e| FileIOError::InvalidArgument(format!("Invalid base64 value: {}", e)))?;
+    run_blocking(move || platform::set_xattr(&path, &request.name, &value)).await
+}
+
+pub async fn remove_xattr(request: XattrRequest) -> FileIOResult<()> {
+    let path = paths::to_path_buf(&request.path)?;
+    run_blocking(move || platform::remove_xattr(&path, &request.name)).await
+}
+
+pub async fn realpath(request: RealPathRequest) -> FileIOResult<String> {
     let path = paths::to_path_buf(&request.path)?;
     let canonical = fs::canonicalize(&path).await?;
     Ok(canonical.to_string_lossy().to_string())
 }
 
-pub async fn mkdir(request: MkDirRequest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn mkdir(request: MkDirRequest) -> FileIOResult<()> {
     let path = paths::to_path_buf(&request.path)?;
     let recursive = request.recursive.unwrap_or(false);
 
@@ -225,15 +831,16 @@ pub async fn mkdir(request: MkDirRequest) -> Result<(), Box<dyn std::error::Erro
     Ok(())
 }
 
-pub async fn rename(request: RenameRequest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn rename(request: RenameRequest) -> FileIOResult<()> {
     let old_path = paths::to_path_buf(&request.old_path)?;
     let new_path = paths::to_path_buf(&request.new_path)?;
+    check_preconditions(&old_path, &request.preconditions).await?;
 
     fs::rename(&old_path, &new_path).await?;
     Ok(())
 }
 
-pub async fn open_file(request: OpenFileRequest) -> Result<OpenFileResponse, Box<dyn std::error::Error + Send + Sync>> {
+pub async fn open_file(handles: &HandleTable, request: OpenFileRequest) -> FileIOResult<OpenFileResponse> {
     let path = paths::to_path_buf(&request.path)?;
     let create = request.create.unwrap_or(true);
     let unlock = request.unlock.unwrap_or(false);
@@ -241,7 +848,7 @@ pub async fn open_file(request: OpenFileRequest) -> Result<OpenFileResponse, Box
     // Check if file exists for validation (similar to TS provider)
     let exists = path.exists();
     if !create && !exists {
-        return Err("File does not exist".into());
+        return Err(FileIOError::NotFound("File does not exist".to_string()));
     }
 
     // Handle unlock option similar to TS provider
@@ -249,6 +856,13 @@ pub async fn open_file(request: OpenFileRequest) -> Result<OpenFileResponse, Box
         // Try to make file writable if it's locked
         if let Ok(metadata) = std::fs::metadata(&path) {
             let mut permissions = metadata.permissions();
+            // Only give the owner write access back, rather than everyone
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                permissions.set_mode(permissions.mode() | 0o200);
+            }
+            #[cfg(not(unix))]
             permissions.set_readonly(false);
             std::fs::set_permissions(&path, permissions)?;
         }
@@ -262,77 +876,297 @@ pub async fn open_file(request: OpenFileRequest) -> Result<OpenFileResponse, Box
         .truncate(create)  // Truncate when creating/writing
         .open(&path)?;
 
-    let mut next_handle = NEXT_HANDLE.lock().unwrap();
-    let handle = *next_handle;
-    *next_handle += 1;
-
-    HANDLE_MAP.lock().unwrap().insert(handle, file);
+    let handle = handles.insert(file, request.path, create)?;
 
     Ok(OpenFileResponse { handle })
 }
 
-pub async fn close_file(request: CloseFileRequest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut handle_map = HANDLE_MAP.lock().unwrap();
-    if let Some(_file) = handle_map.remove(&request.handle) {
-        // File is automatically closed when dropped
-        Ok(())
-    } else {
-        Err("Invalid handle".into())
-    }
+pub async fn close_file(handles: &HandleTable, request: CloseFileRequest) -> FileIOResult<()> {
+    // The file is closed once any in-flight reads or writes release it
+    handles.remove(request.handle)
 }
 
-pub async fn read_file_handle(request: ReadFileHandleRequest) -> Result<ReadFileHandleResponse, Box<dyn std::error::Error + Send + Sync>> {
-    let handle_map = HANDLE_MAP.lock().unwrap();
-    if let Some(file) = handle_map.get(&request.handle) {
-        let mut data = vec![0; request.length as usize];
-        let bytes_read = std::io::Read::read(&mut std::io::BufReader::new(file), &mut data)?;
-        data.truncate(bytes_read);
-        Ok(ReadFileHandleResponse { data, bytes_read: bytes_read as u32 })
-    } else {
-        Err("Invalid handle".into())
-    }
+/// Runs blocking handle I/O off the async runtime.
+async fn run_blocking<T, E, F>(f: F) -> FileIOResult<T>
+where
+    T: Send + 'static,
+    E: Into<FileIOError> + Send + 'static,
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| FileIOError::Other(Box::new(e)))?;
+    result.map_err(Into::into)
 }
 
-pub async fn write_file_handle(request: WriteFileHandleRequest) -> Result<WriteFileHandleResponse, Box<dyn std::error::Error + Send + Sync>> {
-    let handle_map = HANDLE_MAP.lock().unwrap();
-    if let Some(file) = handle_map.get(&request.handle) {
-        let data_slice = &request.data[request.offset as usize..(request.offset + request.length) as usize];
-        let bytes_written = std::io::Write::write(&mut std::io::BufWriter::new(file), data_slice)?;
-        Ok(WriteFileHandleResponse { bytes_written: bytes_written as u32 })
-    } else {
-        Err("Invalid handle".into())
+/// Reads up to `length` bytes, at `position` when given or from the handle's
+/// cursor otherwise. Fewer bytes are only returned at end of file, as of when
+/// the read starts. Something other than a regular file, whose size is not
+/// known, is read in chunks of at most the default stream buffer size.
+pub async fn read_file_handle(handles: &HandleTable, request: ReadFileHandleRequest) -> FileIOResult<ReadFileHandleResponse> {
+    let file = handles.get(request.handle)?;
+    let length = request.length as u64;
+    let position = request.position;
+
+    let data = run_blocking(move || {
+        // The buffer holds what the file has left rather than whatever
+        // length the client asked for
+        let metadata = file.metadata()?;
+        let available = if metadata.is_file() {
+            let start = match position {
+                Some(position) => position,
+                None => std::io::Seek::stream_position(&mut &*file)?,
+            };
+            metadata.len().saturating_sub(start)
+        } else {
+            DEFAULT_STREAM_BUFFER_SIZE as u64
+        };
+        let length = length.min(available) as usize;
+
+        let mut data = vec![0; length];
+        let mut filled = 0;
+        while filled < length {
+            let result = match position {
+                Some(position) => platform::read_at(&file, &mut data[filled..], position + filled as u64),
+                None => std::io::Read::read(&mut &*file, &mut data[filled..]),
+            };
+            match result {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        data.truncate(filled);
+        Ok(data)
+    })
+    .await?;
+
+    let bytes_read = data.len() as u32;
+    Ok(ReadFileHandleResponse { data, bytes_read })
+}
+
+/// Writes `data[offset..offset + length]`, at `position` when given or at the
+/// handle's cursor otherwise.
+pub async fn write_file_handle(handles: &HandleTable, request: WriteFileHandleRequest) -> FileIOResult<WriteFileHandleResponse> {
+    let file = handles.get(request.handle)?;
+    let start = request.offset as usize;
+    let end = start + request.length as usize;
+    if end > request.data.len() {
+        return Err(FileIOError::InvalidArgument(format!(
+            "Write range {}..{} exceeds buffer of {} bytes",
+            start,
+            end,
+            request.data.len()
+        )));
     }
+
+    let mut data = request.data;
+    data.truncate(end);
+    data.drain(..start);
+    let position = request.position;
+
+    run_blocking(move || match position {
+        Some(position) => platform::write_all_at(&file, &data, position),
+        None => std::io::Write::write_all(&mut &*file, &data),
+    })
+    .await?;
+
+    Ok(WriteFileHandleResponse { bytes_written: request.length })
 }
 
-pub async fn read_file_stream(request: ReadFileStreamRequest) -> Result<ReadFileStreamResponse, Box<dyn std::error::Error + Send + Sync>> {
+/// Flushes a handle's data, and its metadata unless `data_only` is set, to disk.
+pub async fn fsync_file_handle(handles: &HandleTable, request: FsyncFileHandleRequest) -> FileIOResult<()> {
+    let file = handles.get(request.handle)?;
+    let data_only = request.data_only.unwrap_or(false);
+
+    run_blocking(move || if data_only { file.sync_data() } else { file.sync_all() }).await
+}
+
+/// Streams `request.path` in `buffer_size` chunks, honoring the `start`/`length`
+/// range. Every chunk is handed to `on_chunk`, followed by a final empty chunk
+/// with `done` set. Cancelling `cancellation` stops the stream before the next
+/// read and marks the final chunk as `cancelled`.
+pub async fn read_file_stream<F>(
+    request: ReadFileStreamRequest,
+    cancellation: CancellationToken,
+    on_chunk: F,
+) -> FileIOResult<()>
+where
+    F: FnMut(ReadFileStreamResponse) + Send,
+{
     let path = paths::to_path_buf(&request.path)?;
-    let buffer_size = request.options.as_ref().and_then(|o| o.buffer_size).unwrap_or(256 * 1024) as usize;
+    let file = fs::File::open(&path).await?;
+    stream_reader(file, request, cancellation, on_chunk).await
+}
+
+/// Sends the range of `reader` selected by the request's options as chunks,
+/// for backends that stream something other than a local file.
+pub async fn stream_reader<R, F>(
+    mut reader: R,
+    request: ReadFileStreamRequest,
+    cancellation: CancellationToken,
+    mut on_chunk: F,
+) -> FileIOResult<()>
+where
+    R: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin,
+    F: FnMut(ReadFileStreamResponse) + Send,
+{
+    let options = request.options.as_ref();
+    let start = options.and_then(|o| o.start).unwrap_or(0);
+    let mut remaining = options.and_then(|o| o.length);
+    let buffer_size = options.and_then(|o| o.buffer_size).unwrap_or(DEFAULT_STREAM_BUFFER_SIZE).max(1) as usize;
+
+    if start > 0 {
+        reader.seek(SeekFrom::Start(start)).await?;
+    }
+
+    let mut buffer = vec![0; buffer_size];
+    let mut position = start;
+
+    loop {
+        if cancellation.is_cancelled() {
+            on_chunk(ReadFileStreamResponse {
+                stream_id: request.stream_id,
+                chunk: Vec::new(),
+                position,
+                done: true,
+                cancelled: true,
+            });
+            return Ok(());
+        }
+
+        let to_read = match remaining {
+            Some(0) => break,
+            Some(remaining) => remaining.min(buffer_size as u64) as usize,
+            None => buffer_size,
+        };
+
+        let bytes_read = reader.read(&mut buffer[..to_read]).await?;
+        if bytes_read == 0 {
+            break;
+        }
+
+        on_chunk(ReadFileStreamResponse {
+            stream_id: request.stream_id,
+            chunk: buffer[..bytes_read].to_vec(),
+            position,
+            done: false,
+            cancelled: false,
+        });
+
+        position += bytes_read as u64;
+        if let Some(remaining) = remaining.as_mut() {
+            *remaining -= bytes_read as u64;
+        }
+    }
+
+    on_chunk(ReadFileStreamResponse {
+        stream_id: request.stream_id,
+        chunk: Vec::new(),
+        position,
+        done: true,
+        cancelled: false,
+    });
+
+    Ok(())
+}
+
+/// Like `stream_reader`, for a reader that can only be read in order, such as
+/// a decompressor. The bytes before `start` are read and dropped. Blocks, so
+/// it is run on a blocking thread.
+pub fn stream_sequential_reader<R, F>(
+    mut reader: R,
+    request: ReadFileStreamRequest,
+    cancellation: CancellationToken,
+    mut on_chunk: F,
+) -> FileIOResult<()>
+where
+    R: std::io::Read,
+    F: FnMut(ReadFileStreamResponse),
+{
+    let options = request.options.as_ref();
+    let start = options.and_then(|o| o.start).unwrap_or(0);
+    let mut remaining = options.and_then(|o| o.length);
+    let buffer_size = options.and_then(|o| o.buffer_size).unwrap_or(DEFAULT_STREAM_BUFFER_SIZE).max(1) as usize;
+
+    if start > 0 {
+        std::io::copy(&mut std::io::Read::take(&mut reader, start), &mut std::io::sink())?;
+    }
 
-    let mut file = fs::File::open(&path).await?;
     let mut buffer = vec![0; buffer_size];
+    let mut position = start;
 
-    let bytes_read = file.read(&mut buffer).await?;
-    buffer.truncate(bytes_read);
+    loop {
+        if cancellation.is_cancelled() {
+            on_chunk(ReadFileStreamResponse {
+                stream_id: request.stream_id,
+                chunk: Vec::new(),
+                position,
+                done: true,
+                cancelled: true,
+            });
+            return Ok(());
+        }
+
+        let to_read = match remaining {
+            Some(0) => break,
+            Some(remaining) => remaining.min(buffer_size as u64) as usize,
+            None => buffer_size,
+        };
 
-    let done = bytes_read == 0;
+        let bytes_read = match std::io::Read::read(&mut reader, &mut buffer[..to_read]) {
+            Ok(bytes_read) => bytes_read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if bytes_read == 0 {
+            break;
+        }
 
-    Ok(ReadFileStreamResponse { chunk: buffer, done })
+        on_chunk(ReadFileStreamResponse {
+            stream_id: request.stream_id,
+            chunk: buffer[..bytes_read].to_vec(),
+            position,
+            done: false,
+            cancelled: false,
+        });
+
+        position += bytes_read as u64;
+        if let Some(remaining) = remaining.as_mut() {
+            *remaining -= bytes_read as u64;
+        }
+    }
+
+    on_chunk(ReadFileStreamResponse {
+        stream_id: request.stream_id,
+        chunk: Vec::new(),
+        position,
+        done: true,
+        cancelled: false,
+    });
+
+    Ok(())
 }
 
-pub async fn clone_file(request: CloneRequest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn clone_file(request: CloneRequest) -> FileIOResult<CloneResponse> {
     let source = paths::to_path_buf(&request.source)?;
     let destination = paths::to_path_buf(&request.destination)?;
 
     // Validate that source and destination are different (similar to TS provider)
     if source == destination {
-        return Ok(()); // No-op if same file, matching TS behavior
+        return Ok(CloneResponse { strategy: None }); // No-op if same file, matching TS behavior
     }
 
     // Check if destination exists and handle accordingly (TS provider validates this)
     if destination.exists() {
-        return Err("Destination already exists".into());
+        return Err(FileIOError::AlreadyExists("Destination already exists".to_string()));
     }
 
-    fs::copy(&source, &destination).await?;
-    Ok(())
+    let strategy = platform::clone_file(&source, &destination).await?;
+    // Neither reflinks nor copy_file_range carry extended attributes
+    if request.preserve_xattrs.unwrap_or(false) {
+        platform::copy_xattrs(&source, &destination)?;
+    }
+    Ok(CloneResponse { strategy: Some(strategy) })
 }
\ No newline at end of file
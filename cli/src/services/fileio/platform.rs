@@ -3,7 +3,9 @@
  *  Licensed under the MIT License. See License.txt in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
 
-use std::path::Path;
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::types::CloneStrategy;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 
 #[cfg(target_os = "linux")]
@@ -108,6 +110,97 @@ mod linux {
         std::fs::set_permissions(destination, source_metadata.permissions())?;
         Ok(())
     }
+
+    /// Clones through a reflink where the filesystem supports it (btrfs, XFS),
+    /// then in-kernel `copy_file_range`, then a plain copy.
+    pub async fn clone_file(source: &Path, destination: &Path) -> FileIOResult<CloneStrategy> {
+        let source = source.to_path_buf();
+        let destination = destination.to_path_buf();
+        tokio::task::spawn_blocking(move || clone_file_blocking(&source, &destination))
+            .await
+            .map_err(|e| FileIOError::Other(Box::new(e)))?
+    }
+
+    fn clone_file_blocking(source: &Path, destination: &Path) -> FileIOResult<CloneStrategy> {
+        let mut source_file = std::fs::File::open(source)?;
+        let metadata = source_file.metadata()?;
+        let mut dest_file = std::fs::OpenOptions::new().write(true).create_new(true).open(destination)?;
+
+        // The destination is ours from here on, so a failure removes it.
+        // Anything that was already there made `create_new` fail above.
+        let result = clone_into(&mut source_file, &mut dest_file, &metadata);
+        if result.is_err() {
+            drop(dest_file);
+            let _ = std::fs::remove_file(destination);
+        }
+        result
+    }
+
+    fn clone_into(
+        source_file: &mut std::fs::File,
+        dest_file: &mut std::fs::File,
+        metadata: &std::fs::Metadata,
+    ) -> FileIOResult<CloneStrategy> {
+        let strategy = if unsafe { libc::ioctl(dest_file.as_raw_fd(), libc::FICLONE, source_file.as_raw_fd()) } == 0 {
+            CloneStrategy::Reflink
+        } else {
+            let err = std::io::Error::last_os_error();
+            if !is_unsupported(&err) {
+                return Err(err.into());
+            }
+            copy_range_or_fallback(source_file, dest_file, metadata.len())?
+        };
+
+        dest_file.set_permissions(metadata.permissions())?;
+        Ok(strategy)
+    }
+
+    /// Copies with `copy_file_range`, finishing with a userspace copy from the
+    /// current offsets if the kernel or filesystem does not support it.
+    fn copy_range_or_fallback(source: &mut std::fs::File, destination: &mut std::fs::File, len: u64) -> FileIOResult<CloneStrategy> {
+        let mut copied = 0u64;
+        while copied < len {
+            let remaining = (len - copied).min(isize::MAX as u64) as usize;
+            let result = unsafe {
+                libc::copy_file_range(
+                    source.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    destination.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    remaining,
+                    0,
+                )
+            };
+            if result > 0 {
+                copied += result as u64;
+                continue;
+            }
+            if result == 0 {
+                break;
+            }
+
+            let err = std::io::Error::last_os_error();
+            match err.kind() {
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock => continue,
+                _ if is_unsupported(&err) => {
+                    std::io::copy(source, destination)?;
+                    return Ok(CloneStrategy::Copy);
+                }
+                _ => return Err(err.into()),
+            }
+        }
+
+        // The file may have grown since it was stat'ed
+        std::io::copy(source, destination)?;
+        Ok(CloneStrategy::CopyFileRange)
+    }
+
+    fn is_unsupported(err: &std::io::Error) -> bool {
+        matches!(
+            err.raw_os_error(),
+            Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EXDEV | libc::EINVAL | libc::ENOSYS | libc::EBADF | libc::EPERM)
+        )
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -131,6 +224,21 @@ mod macos {
         Ok(())
     }
 
+    pub async fn clone_file(source: &Path, destination: &Path) -> FileIOResult<CloneStrategy> {
+        use std::ffi::CString;
+        let source_c = CString::new(source.to_string_lossy().as_bytes()).map_err(|e| FileIOError::Other(Box::new(e)))?;
+        let dest_c = CString::new(destination.to_string_lossy().as_bytes()).map_err(|e| FileIOError::Other(Box::new(e)))?;
+
+        unsafe {
+            if libc::clonefile(source_c.as_ptr(), dest_c.as_ptr(), 0) == 0 {
+                return Ok(CloneStrategy::Reflink);
+            }
+        }
+
+        fs::copy(source, destination).await?;
+        Ok(CloneStrategy::Copy)
+    }
+
     pub fn normalize_path(path: &Path) -> String {
         use std::ffi::OsString;
         use std::os::unix::ffi::OsStringExt;
@@ -159,10 +267,10 @@ mod windows {
 }
 
 #[cfg(target_os = "linux")]
-pub use linux::copy_file;
+pub use linux::{clone_file, copy_file};
 
 #[cfg(target_os = "macos")]
-pub use macos::copy_file;
+pub use macos::{clone_file, copy_file};
 
 #[cfg(target_os = "windows")]
 pub use windows::copy_file;
@@ -174,6 +282,315 @@ pub async fn copy_file(source: &Path, destination: &Path) -> Result<(), Box<dyn
     Ok(())
 }
 
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub async fn clone_file(source: &Path, destination: &Path) -> FileIOResult<CloneStrategy> {
+    fs::copy(source, destination).await?;
+    Ok(CloneStrategy::Copy)
+}
+
+#[cfg(unix)]
+pub fn set_mode(path: &Path, mode: u32) -> FileIOResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn set_mode(path: &Path, mode: u32) -> FileIOResult<()> {
+    // Only the owner write bit maps onto the readonly attribute
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    std::fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn set_owner(path: &Path, uid: Option<u32>, gid: Option<u32>, follow_symlinks: bool) -> FileIOResult<()> {
+    if follow_symlinks {
+        std::os::unix::fs::chown(path, uid, gid)?;
+    } else {
+        std::os::unix::fs::lchown(path, uid, gid)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn set_owner(_path: &Path, _uid: Option<u32>, _gid: Option<u32>, _follow_symlinks: bool) -> FileIOResult<()> {
+    Err(FileIOError::Unsupported(
+        "Changing file ownership is not supported on this platform".to_string(),
+    ))
+}
+
+/// Opens a file or directory with enough access to update its timestamps.
+#[cfg(not(windows))]
+pub fn open_for_attributes(path: &Path) -> FileIOResult<std::fs::File> {
+    Ok(std::fs::File::open(path)?)
+}
+
+#[cfg(windows)]
+pub fn open_for_attributes(path: &Path) -> FileIOResult<std::fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_WRITE_ATTRIBUTES: u32 = 0x100;
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+    Ok(std::fs::OpenOptions::new()
+        .access_mode(FILE_WRITE_ATTRIBUTES)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?)
+}
+
+/// Creates `link` pointing at `target`. Windows needs to know whether the
+/// target is a directory.
+#[cfg(unix)]
+pub fn create_symlink(target: &Path, link: &Path, _is_dir: bool) -> FileIOResult<()> {
+    std::os::unix::fs::symlink(target, link)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn create_symlink(target: &Path, link: &Path, is_dir: bool) -> FileIOResult<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)?;
+    } else {
+        std::os::windows::fs::symlink_file(target, link)?;
+    }
+    Ok(())
+}
+
+/// Names of the extended attributes of `path`, e.g. `user.origin` on Linux.
+/// Like `stat`, the xattr functions follow a symlink to its target; Linux does
+/// not allow `user.` attributes on the link itself.
+#[cfg(unix)]
+pub fn list_xattrs(path: &Path) -> FileIOResult<Vec<String>> {
+    let mut names: Vec<String> = xattr::list_deref(path)?.map(|name| name.to_string_lossy().to_string()).collect();
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(unix)]
+pub fn get_xattr(path: &Path, name: &str) -> FileIOResult<Option<Vec<u8>>> {
+    Ok(xattr::get_deref(path, name)?)
+}
+
+#[cfg(unix)]
+pub fn set_xattr(path: &Path, name: &str, value: &[u8]) -> FileIOResult<()> {
+    xattr::set_deref(path, name, value)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn remove_xattr(path: &Path, name: &str) -> FileIOResult<()> {
+    if xattr::get_deref(path, name)?.is_none() {
+        return Err(FileIOError::NotFound(format!("{} has no attribute {}", path.display(), name)));
+    }
+    xattr::remove_deref(path, name)?;
+    Ok(())
+}
+
+/// Copies every extended attribute of `source` onto `destination`, without
+/// following symlinks since copies recreate links as links. A source on a
+/// filesystem without xattr support has nothing to copy.
+#[cfg(unix)]
+pub fn copy_xattrs(source: &Path, destination: &Path) -> FileIOResult<()> {
+    let names = match xattr::list(source) {
+        Ok(names) => names,
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported || e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for name in names {
+        if let Some(value) = xattr::get(source, &name)? {
+            xattr::set(destination, &name, &value)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn list_xattrs(_path: &Path) -> FileIOResult<Vec<String>> {
+    Err(FileIOError::Unsupported("Extended attributes are not supported on this platform".to_string()))
+}
+
+#[cfg(not(unix))]
+pub fn get_xattr(_path: &Path, _name: &str) -> FileIOResult<Option<Vec<u8>>> {
+    Err(FileIOError::Unsupported("Extended attributes are not supported on this platform".to_string()))
+}
+
+#[cfg(not(unix))]
+pub fn set_xattr(_path: &Path, _name: &str, _value: &[u8]) -> FileIOResult<()> {
+    Err(FileIOError::Unsupported("Extended attributes are not supported on this platform".to_string()))
+}
+
+#[cfg(not(unix))]
+pub fn remove_xattr(_path: &Path, _name: &str) -> FileIOResult<()> {
+    Err(FileIOError::Unsupported("Extended attributes are not supported on this platform".to_string()))
+}
+
+#[cfg(not(unix))]
+pub fn copy_xattrs(_source: &Path, _destination: &Path) -> FileIOResult<()> {
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct MountEntry {
+    pub mount_point: PathBuf,
+    /// As reported by the kernel, e.g. `ext4`, `nfs4` or `fuse.sshfs`.
+    pub fs_type: String,
+}
+
+/// Mounted filesystems from `/proc/self/mounts`, whose fields escape spaces
+/// and other whitespace as octal.
+#[cfg(target_os = "linux")]
+pub fn mounts() -> Vec<MountEntry> {
+    use std::os::unix::ffi::OsStringExt;
+
+    fn unescape(field: &str) -> Vec<u8> {
+        let bytes = field.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let octal = bytes.get(i + 1..i + 4).and_then(|o| std::str::from_utf8(o).ok());
+            match (bytes[i], octal.and_then(|o| u8::from_str_radix(o, 8).ok())) {
+                (b'\\', Some(byte)) => {
+                    decoded.push(byte);
+                    i += 4;
+                }
+                (byte, _) => {
+                    decoded.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        decoded
+    }
+
+    let Ok(mounts) = std::fs::read_to_string("/proc/self/mounts") else {
+        return Vec::new();
+    };
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ').skip(1);
+            let mount_point = fields.next()?;
+            let fs_type = fields.next()?;
+            Some(MountEntry {
+                mount_point: PathBuf::from(std::ffi::OsString::from_vec(unescape(mount_point))),
+                fs_type: fs_type.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn mounts() -> Vec<MountEntry> {
+    Vec::new()
+}
+
+/// Space and inode counts of the filesystem holding a path.
+#[derive(Debug, Clone, Default)]
+pub struct FsUsage {
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    /// Free bytes usable by unprivileged users.
+    pub available_bytes: u64,
+    pub total_inodes: u64,
+    pub free_inodes: u64,
+    pub fs_type: String,
+    pub mount_point: PathBuf,
+}
+
+#[cfg(target_os = "linux")]
+pub fn fs_usage(path: &Path) -> FileIOResult<FsUsage> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let canonical = std::fs::canonicalize(path)?;
+    let c_path = std::ffi::CString::new(canonical.as_os_str().as_bytes())
+        .map_err(|e| FileIOError::InvalidArgument(e.to_string()))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    // The innermost mount containing the path
+    let mount = mounts()
+        .into_iter()
+        .filter(|mount| canonical.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.components().count());
+
+    let block_size = stat.f_frsize as u64;
+    Ok(FsUsage {
+        total_bytes: stat.f_blocks as u64 * block_size,
+        free_bytes: stat.f_bfree as u64 * block_size,
+        available_bytes: stat.f_bavail as u64 * block_size,
+        total_inodes: stat.f_files as u64,
+        free_inodes: stat.f_ffree as u64,
+        fs_type: mount.as_ref().map(|m| m.fs_type.clone()).unwrap_or_default(),
+        mount_point: mount.map_or_else(|| PathBuf::from("/"), |m| m.mount_point),
+    })
+}
+
+#[cfg(target_os = "macos")]
+pub fn fs_usage(path: &Path) -> FileIOResult<FsUsage> {
+    use std::ffi::CStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|e| FileIOError::InvalidArgument(e.to_string()))?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let block_size = stat.f_bsize as u64;
+    let fs_type = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    let mount_point = unsafe { CStr::from_ptr(stat.f_mntonname.as_ptr()) };
+    Ok(FsUsage {
+        total_bytes: stat.f_blocks * block_size,
+        free_bytes: stat.f_bfree * block_size,
+        available_bytes: stat.f_bavail * block_size,
+        total_inodes: stat.f_files,
+        free_inodes: stat.f_ffree,
+        fs_type: fs_type.to_string_lossy().to_string(),
+        mount_point: PathBuf::from(std::ffi::OsStr::from_bytes(mount_point.to_bytes())),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn fs_usage(_path: &Path) -> FileIOResult<FsUsage> {
+    Err(FileIOError::Unsupported("Filesystem usage is not supported on this platform".to_string()))
+}
+
+/// Reads at `offset` without relying on the handle's cursor.
+#[cfg(unix)]
+pub fn read_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+pub fn read_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// Writes all of `buf` at `offset` without relying on the handle's cursor.
+#[cfg(unix)]
+pub fn write_all_at(file: &std::fs::File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+pub fn write_all_at(file: &std::fs::File, mut buf: &[u8], mut offset: u64) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_write(file, buf, offset) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
 pub async fn optimized_read_file(path: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
     // Platform-specific optimizations for reading files
     // For now, just use standard tokio::fs::read
@@ -0,0 +1,123 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::paths;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Symlink hops followed while resolving a path before giving up, like ELOOP.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AccessMode {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// What a request does with a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathAccess {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxRoot {
+    pub path: PathBuf,
+    pub mode: AccessMode,
+}
+
+/// Restricts the service to a set of allowed roots. Paths are resolved through
+/// `..` segments and symlinks before being checked, so neither can be used to
+/// reach outside a root.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    roots: Vec<SandboxRoot>,
+}
+
+impl Sandbox {
+    /// Creates a sandbox over `roots`, which must exist.
+    pub fn new(roots: Vec<SandboxRoot>) -> FileIOResult<Self> {
+        let mut canonical_roots = Vec::with_capacity(roots.len());
+        for root in roots {
+            canonical_roots.push(SandboxRoot {
+                path: std::fs::canonicalize(&root.path)?,
+                mode: root.mode,
+            });
+        }
+        // Longest first, so a nested root overrides the mode of its parent
+        canonical_roots.sort_by_key(|root| std::cmp::Reverse(root.path.components().count()));
+        Ok(Sandbox { roots: canonical_roots })
+    }
+
+    pub fn roots(&self) -> &[SandboxRoot] {
+        &self.roots
+    }
+
+    /// Returns the resolved path if `access` to `path` is allowed, and
+    /// `AccessDenied` otherwise.
+    pub fn check(&self, path: &str, access: PathAccess) -> FileIOResult<PathBuf> {
+        let lexical = paths::to_path_buf(path)?;
+        if !lexical.is_absolute() {
+            return Err(FileIOError::AccessDenied(format!("{} is not an absolute path", path)));
+        }
+
+        let resolved = resolve(&lexical, MAX_SYMLINK_HOPS)?;
+        let root = self
+            .roots
+            .iter()
+            .find(|root| resolved.starts_with(&root.path))
+            .ok_or_else(|| FileIOError::AccessDenied(format!("{} is outside the allowed roots", path)))?;
+
+        if access == PathAccess::Write && root.mode == AccessMode::ReadOnly {
+            return Err(FileIOError::AccessDenied(format!("{} is in a read-only root", path)));
+        }
+        Ok(resolved)
+    }
+}
+
+/// Canonicalizes `path`, including the parts that do not exist yet. A
+/// dangling symlink is resolved through its target, since writing to it would
+/// create the target.
+fn resolve(path: &Path, hops: usize) -> FileIOResult<PathBuf> {
+    let mut existing = path.to_path_buf();
+    let mut missing = Vec::new();
+
+    loop {
+        match std::fs::canonicalize(&existing) {
+            Ok(mut canonical) => {
+                canonical.extend(missing.iter().rev());
+                return Ok(canonical);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        if std::fs::symlink_metadata(&existing).is_ok_and(|m| m.file_type().is_symlink()) {
+            if hops == 0 {
+                return Err(FileIOError::InvalidArgument(format!("Too many levels of symbolic links: {}", path.display())));
+            }
+            let target = std::fs::read_link(&existing)?;
+            let target = match existing.parent() {
+                Some(parent) => paths::to_path_buf(&parent.join(target).to_string_lossy())?,
+                None => target,
+            };
+            let mut resolved = resolve(&target, hops - 1)?;
+            resolved.extend(missing.iter().rev());
+            return Ok(resolved);
+        }
+
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                missing.push(name.to_os_string());
+                existing = parent.to_path_buf();
+            }
+            _ => return Err(FileIOError::NotFound(format!("{} does not exist", path.display()))),
+        }
+    }
+}
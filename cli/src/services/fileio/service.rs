@@ -4,159 +4,857 @@
  *--------------------------------------------------------------------------------------------*/
 
 use crate::services::fileio::{
-    locks::ResourceLockManager,
-    operations,
-    types::{FileIOError, FileIOResponse, FileIORequest},
+    backend::{self, BackendRegistry, FileSystemBackend},
+    batch,
+    errors::{FileIOError, FileIOResult},
+    handles::{HandleTable, HandleTableConfig},
+    hash::{self, HashCache},
+    locks::{LockMode, ResourceLockManager},
+    sandbox::{PathAccess, Sandbox},
+    trash::Trash,
+    usage,
+    operations::{self, WriteFileStream},
+    types::{
+        BatchRequest, BatchResponse, BatchResult, CopyTreeRequest, FileIOErrorResponse, FileIORequest, FileIORequestMessage, FileIOResponse, FileIOResponseMessage,
+        DirSizeRequest, HashRequest, ReadFileStreamRequest, WalkRequest, WriteFileStreamRequest, WriteFileStreamResponse,
+    },
+    walk,
 };
+use crate::services::paths;
 use serde_json;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Default)]
+pub struct FileIOServiceConfig {
+    pub handles: HandleTableConfig,
+    /// Restricts requests to allowed roots; `None` allows any path.
+    pub sandbox: Option<Sandbox>,
+    /// Home trash directory; `$XDG_DATA_HOME/Trash` when `None`.
+    pub trash_dir: Option<std::path::PathBuf>,
+}
+
+/// A write stream between chunks. While a chunk is written the stream is
+/// taken out of its slot, so its I/O does not hold up other streams.
+enum WriteStreamSlot {
+    Idle { stream: WriteFileStream, last_used: Instant },
+    Writing,
+    /// Cancelled while a chunk was being written, which discards the stream
+    /// once the chunk is done.
+    Cancelled,
+}
 
 pub struct FileIOService {
-    lock_manager: Mutex<ResourceLockManager>,
+    lock_manager: Arc<ResourceLockManager>,
     ipc_sink: Arc<dyn Fn(String) + Send + Sync>,
+    streams: Arc<std::sync::Mutex<HashMap<u32, CancellationToken>>>,
+    /// Long-running requests that can be cancelled, keyed by request id.
+    cancellable: Arc<std::sync::Mutex<HashMap<u32, CancellationToken>>>,
+    write_streams: Mutex<HashMap<u32, WriteStreamSlot>>,
+    /// Write streams without a chunk for this long are discarded.
+    write_stream_idle_timeout: Option<Duration>,
+    handles: HandleTable,
+    hash_cache: Arc<HashCache>,
+    sandbox: Option<Sandbox>,
+    trash: Trash,
+    backends: BackendRegistry,
 }
 
 impl FileIOService {
     pub fn new(ipc_sink: Arc<dyn Fn(String) + Send + Sync>) -> Self {
+        Self::with_config(ipc_sink, FileIOServiceConfig::default())
+    }
+
+    pub fn with_config(ipc_sink: Arc<dyn Fn(String) + Send + Sync>, config: FileIOServiceConfig) -> Self {
         FileIOService {
-            lock_manager: Mutex::new(ResourceLockManager::new()),
+            lock_manager: Arc::new(ResourceLockManager::new()),
             ipc_sink,
+            streams: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            cancellable: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            write_streams: Mutex::new(HashMap::new()),
+            write_stream_idle_timeout: config.handles.idle_timeout,
+            handles: HandleTable::new(config.handles),
+            hash_cache: Arc::new(HashCache::new()),
+            sandbox: config.sandbox,
+            trash: config.trash_dir.map_or_else(Trash::default, |dir| Trash::new(Some(dir))),
+            backends: BackendRegistry::new(),
         }
     }
 
-    pub async fn handle_request(&self, request: &crate::rpc::RequestParams<FileIORequest>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let fileio_request: FileIORequest = request.params.clone();
+    /// Serves paths of `scheme` with `backend`. Registering `file` replaces
+    /// the disk for plain paths too.
+    pub fn register_backend(&self, scheme: &str, backend: Arc<dyn FileSystemBackend>) {
+        self.backends.register(scheme, backend);
+    }
+
+    pub async fn handle_request(&self, request: &crate::rpc::RequestParams<FileIORequestMessage>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let FileIORequestMessage { id, request: fileio_request } = request.params.clone();
+        let operation = fileio_request.operation();
+
+        if let Err(e) = self.authorize(id, &fileio_request).and_then(|_| Self::check_local_paths(&fileio_request)) {
+            return Self::send_response(&self.ipc_sink, id, operation, Self::error_response(&e));
+        }
+
+        // Streams and tree-wide operations run in their own tasks, where they can be cancelled, and
+        // most answer with a sequence of messages rather than a single response
+        match fileio_request {
+            FileIORequest::ReadFileStream(req) => {
+                self.start_read_stream(id, req).await;
+                return Ok(());
+            }
+            FileIORequest::CopyTree(req) => {
+                self.start_copy_tree(id, req, false);
+                return Ok(());
+            }
+            FileIORequest::MoveTree(req) => {
+                self.start_copy_tree(id, req, true);
+                return Ok(());
+            }
+            FileIORequest::Walk(req) => {
+                self.start_walk(id, req);
+                return Ok(());
+            }
+            FileIORequest::DirSize(req) => {
+                self.start_dir_size(id, req);
+                return Ok(());
+            }
+            FileIORequest::Hash(req) => {
+                self.start_hash(id, req);
+                return Ok(());
+            }
+            _ => {}
+        }
 
         let response = match self.process_request(fileio_request).await {
             Ok(response) => response,
+            Err(e) => Self::error_response(&e),
+        };
+
+        Self::send_response(&self.ipc_sink, id, operation, response)
+    }
+
+    /// Checks every path of the request against the sandbox, recording an
+    /// audit entry for each denial.
+    fn authorize(&self, id: Option<u32>, request: &FileIORequest) -> FileIOResult<()> {
+        for (path, access) in request.accessed_paths() {
+            self.authorize_path(id, request.operation(), path, access)?;
+        }
+        Ok(())
+    }
+
+    fn authorize_path(&self, id: Option<u32>, operation: &str, path: &str, access: PathAccess) -> FileIOResult<()> {
+        let Some(sandbox) = &self.sandbox else {
+            return Ok(());
+        };
+        // Other schemes are checked through the local file their backend
+        // reads, if any, e.g. the archive for `archive:` paths
+        let local_path = match backend::local_path(path)? {
+            Some(local_path) => local_path,
+            None => match self.backends.resolve(path) {
+                Ok((backend, backend_path)) => match backend.disk_path(&backend_path) {
+                    Some(disk_path) => disk_path,
+                    None => return Ok(()),
+                },
+                Err(_) => return Ok(()),
+            },
+        };
+
+        if let Err(e) = sandbox.check(&local_path, access) {
+            log::warn!(
+                target: "fileio::audit",
+                "denied {} {:?} access to {} (request {:?}): {}",
+                operation,
+                access,
+                path,
+                id,
+                e
+            );
+            return Err(match e {
+                FileIOError::AccessDenied(_) => e,
+                // Paths that cannot be resolved are denied rather than
+                // reported, which could reveal what exists outside the roots
+                _ => FileIOError::AccessDenied(format!("{} could not be resolved", path)),
+            });
+        }
+        Ok(())
+    }
+
+    /// Only requests served through a `FileSystemBackend` accept URIs; the
+    /// others work on local paths.
+    fn check_local_paths(request: &FileIORequest) -> FileIOResult<()> {
+        match request {
+            FileIORequest::ReadFile(_)
+            | FileIORequest::ReadFileStream(_)
+            | FileIORequest::WriteFile(_)
+            | FileIORequest::Copy(_)
+            | FileIORequest::Stat(_)
+            | FileIORequest::ReadDir(_)
+            | FileIORequest::RealPath(_)
+            | FileIORequest::MkDir(_)
+            | FileIORequest::Rename(_) => Ok(()),
+            FileIORequest::Delete(req) if !req.use_trash.unwrap_or(false) => Ok(()),
+            FileIORequest::Batch(req) if !req.transactional.unwrap_or(false) => {
+                req.operations.iter().try_for_each(Self::check_local_paths)
+            }
+            _ => match request.accessed_paths().into_iter().find(|(path, _)| backend::split_scheme(path).is_some()) {
+                Some((path, _)) => Err(FileIOError::Unsupported(format!(
+                    "{} only supports local paths, not {}",
+                    request.operation(),
+                    path
+                ))),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// The backend serving a request path, which is replaced with the path
+    /// the backend expects.
+    fn route(&self, path: &mut String) -> FileIOResult<Arc<dyn FileSystemBackend>> {
+        let (backend, backend_path) = self.backends.resolve(path)?;
+        *path = backend_path;
+        Ok(backend)
+    }
+
+    /// Like `route` for requests with two paths, which must be served by the
+    /// same backend.
+    fn route_pair(&self, first: &mut String, second: &mut String) -> FileIOResult<Arc<dyn FileSystemBackend>> {
+        let (backend, first_path) = self.backends.resolve(first)?;
+        let (other, second_path) = self.backends.resolve(second)?;
+        if !Arc::ptr_eq(&backend, &other) {
+            return Err(FileIOError::Unsupported(format!("Cannot operate across filesystems: {} and {}", first, second)));
+        }
+        *first = first_path;
+        *second = second_path;
+        Ok(backend)
+    }
+
+    /// Spawns the read so that the service stays available for other requests,
+    /// including a `CancelStream` for this one, while chunks are being sent.
+    async fn start_read_stream(&self, id: Option<u32>, mut request: ReadFileStreamRequest) {
+        let backend = match self.route(&mut request.path) {
+            Ok(backend) => backend,
             Err(e) => {
-                let error = FileIOError {
-                    message: e.to_string(),
-                    code: Self::map_error_to_code(&e),
-                };
-                FileIOResponse::Error(error)
+                let _ = Self::send_response(&self.ipc_sink, id, "ReadFileStream", Self::error_response(&e));
+                return;
             }
         };
+        let cancellation = CancellationToken::new();
+        if let Some(stream_id) = request.stream_id {
+            // Read and write streams share ids, which `CancelStream` looks up in both
+            let in_use = self.write_streams.lock().unwrap().contains_key(&stream_id) || {
+                let mut streams = self.streams.lock().unwrap();
+                let in_use = streams.contains_key(&stream_id);
+                if !in_use {
+                    streams.insert(stream_id, cancellation.clone());
+                }
+                in_use
+            };
+            if in_use {
+                let e = FileIOError::AlreadyExists(format!("Stream {} already exists", stream_id));
+                let _ = Self::send_response(&self.ipc_sink, id, "ReadFileStream", Self::error_response(&e));
+                return;
+            }
+        }
+
+        let ipc_sink = self.ipc_sink.clone();
+        let streams = self.streams.clone();
+        tokio::spawn(async move {
+            let stream_id = request.stream_id;
+            let chunk_sink = ipc_sink.clone();
+            let on_chunk = Box::new(move |chunk| {
+                let _ = Self::send_response(&chunk_sink, id, "ReadFileStream", FileIOResponse::ReadFileStream(chunk));
+            });
+            let result = backend.read_file_stream(request, cancellation, on_chunk).await;
+
+            if let Some(stream_id) = stream_id {
+                streams.lock().unwrap().remove(&stream_id);
+            }
+            if let Err(e) = result {
+                let _ = Self::send_response(&ipc_sink, id, "ReadFileStream", Self::error_response(&e));
+            }
+        });
+    }
+
+    /// Spawns a recursive copy or move that reports progress until it
+    /// completes or is cancelled through a `Cancel` for its request id.
+    fn start_copy_tree(&self, id: Option<u32>, request: CopyTreeRequest, remove_source: bool) {
+        let operation = if remove_source { "MoveTree" } else { "CopyTree" };
+        let cancellation = CancellationToken::new();
+        if let Some(id) = id {
+            self.cancellable.lock().unwrap().insert(id, cancellation.clone());
+        }
+
+        let ipc_sink = self.ipc_sink.clone();
+        let lock_manager = self.lock_manager.clone();
+        let cancellable = self.cancellable.clone();
+        tokio::spawn(async move {
+            let source_mode = if remove_source { LockMode::Exclusive } else { LockMode::Shared };
+            let result = match lock_manager
+                .acquire_many(&[(&request.source, source_mode), (&request.destination, LockMode::Exclusive)])
+                .await
+            {
+                Ok(_locks) => {
+                    let progress_sink = ipc_sink.clone();
+                    operations::copy_tree(request, remove_source, cancellation, move |progress| {
+                        let _ = Self::send_response(&progress_sink, id, operation, FileIOResponse::CopyTreeProgress(progress));
+                    })
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+
+            if let Some(id) = id {
+                cancellable.lock().unwrap().remove(&id);
+            }
+            let response = match result {
+                Ok(result) if remove_source => FileIOResponse::MoveTree(result),
+                Ok(result) => FileIOResponse::CopyTree(result),
+                Err(e) => Self::error_response(&e),
+            };
+            let _ = Self::send_response(&ipc_sink, id, operation, response);
+        });
+    }
+
+    /// Spawns a walk that sends entries in batches until the tree is exhausted
+    /// or the walk is cancelled through a `Cancel` for its request id.
+    fn start_walk(&self, id: Option<u32>, request: WalkRequest) {
+        let cancellation = CancellationToken::new();
+        if let Some(id) = id {
+            self.cancellable.lock().unwrap().insert(id, cancellation.clone());
+        }
 
+        let ipc_sink = self.ipc_sink.clone();
+        let sandbox = self.sandbox.clone();
+        let cancellable = self.cancellable.clone();
+        tokio::spawn(async move {
+            let batch_sink = ipc_sink.clone();
+            let result = walk::walk(request, sandbox, cancellation, move |batch| {
+                let _ = Self::send_response(&batch_sink, id, "Walk", FileIOResponse::Walk(batch));
+            })
+            .await;
+
+            if let Some(id) = id {
+                cancellable.lock().unwrap().remove(&id);
+            }
+            if let Err(e) = result {
+                let _ = Self::send_response(&ipc_sink, id, "Walk", Self::error_response(&e));
+            }
+        });
+    }
+
+    /// Spawns a directory size computation that reports progress until it
+    /// completes or is cancelled through a `Cancel` for its request id.
+    fn start_dir_size(&self, id: Option<u32>, request: DirSizeRequest) {
+        let cancellation = CancellationToken::new();
+        if let Some(id) = id {
+            self.cancellable.lock().unwrap().insert(id, cancellation.clone());
+        }
+
+        let ipc_sink = self.ipc_sink.clone();
+        let cancellable = self.cancellable.clone();
+        tokio::spawn(async move {
+            let progress_sink = ipc_sink.clone();
+            let result = usage::dir_size(request, cancellation, move |progress| {
+                let _ = Self::send_response(&progress_sink, id, "DirSize", FileIOResponse::DirSizeProgress(progress));
+            })
+            .await;
+
+            if let Some(id) = id {
+                cancellable.lock().unwrap().remove(&id);
+            }
+            let response = match result {
+                Ok(result) => FileIOResponse::DirSize(result),
+                Err(e) => Self::error_response(&e),
+            };
+            let _ = Self::send_response(&ipc_sink, id, "DirSize", response);
+        });
+    }
+
+    /// Spawns a hash, which can take long for a large tree, so that it can be
+    /// cancelled through a `Cancel` for its request id.
+    fn start_hash(&self, id: Option<u32>, request: HashRequest) {
+        let cancellation = CancellationToken::new();
+        if let Some(id) = id {
+            self.cancellable.lock().unwrap().insert(id, cancellation.clone());
+        }
+
+        let ipc_sink = self.ipc_sink.clone();
+        let lock_manager = self.lock_manager.clone();
+        let hash_cache = self.hash_cache.clone();
+        let cancellable = self.cancellable.clone();
+        tokio::spawn(async move {
+            let result = match lock_manager.acquire_shared(&request.path).await {
+                Ok(_lock) => hash::hash(request, hash_cache, cancellation).await,
+                Err(e) => Err(e),
+            };
+
+            if let Some(id) = id {
+                cancellable.lock().unwrap().remove(&id);
+            }
+            let response = match result {
+                Ok(result) => FileIOResponse::Hash(result),
+                Err(e) => Self::error_response(&e),
+            };
+            let _ = Self::send_response(&ipc_sink, id, "Hash", response);
+        });
+    }
+
+    /// Cancels a pending read stream or discards a pending write stream.
+    async fn cancel_stream(&self, stream_id: u32) -> bool {
+        let cancellation = self.streams.lock().unwrap().remove(&stream_id);
+        if let Some(cancellation) = cancellation {
+            cancellation.cancel();
+            return true;
+        }
+
+        self.reap_write_streams().await;
+        let stream = {
+            let mut write_streams = self.write_streams.lock().unwrap();
+            let Some(slot) = write_streams.get_mut(&stream_id) else {
+                return false;
+            };
+            match std::mem::replace(slot, WriteStreamSlot::Cancelled) {
+                WriteStreamSlot::Idle { stream, .. } => {
+                    write_streams.remove(&stream_id);
+                    stream
+                }
+                // The chunk being written discards the stream when it is done
+                _ => return true,
+            }
+        };
+        stream.abort().await;
+        true
+    }
+
+    /// Discards write streams that have gone without a chunk for the idle
+    /// timeout, along with their temp files. Like idle handles, they are
+    /// reaped whenever write streams are used rather than by a timer.
+    async fn reap_write_streams(&self) {
+        let Some(idle_timeout) = self.write_stream_idle_timeout else {
+            return;
+        };
+        let reaped: Vec<WriteFileStream> = {
+            let mut write_streams = self.write_streams.lock().unwrap();
+            let idle: Vec<u32> = write_streams
+                .iter()
+                .filter(|(_, slot)| matches!(slot, WriteStreamSlot::Idle { last_used, .. } if last_used.elapsed() >= idle_timeout))
+                .map(|(stream_id, _)| *stream_id)
+                .collect();
+            idle.iter()
+                .filter_map(|stream_id| match write_streams.remove(stream_id) {
+                    Some(WriteStreamSlot::Idle { stream, .. }) => Some(stream),
+                    _ => None,
+                })
+                .collect()
+        };
+        for stream in reaped {
+            stream.abort().await;
+        }
+    }
+
+    /// Appends one chunk to a write stream, opening it on sequence 0 and
+    /// committing it on the last chunk. Any failure discards the stream.
+    async fn write_stream_chunk(&self, request: WriteFileStreamRequest) -> FileIOResult<WriteFileStreamResponse> {
+        let path = paths::to_path_buf(&request.path)?;
+        self.reap_write_streams().await;
+
+        let mut stream = if request.sequence == 0 {
+            {
+                let mut write_streams = self.write_streams.lock().unwrap();
+                if write_streams.contains_key(&request.stream_id) || self.streams.lock().unwrap().contains_key(&request.stream_id) {
+                    return Err(FileIOError::AlreadyExists(format!("Stream {} already exists", request.stream_id)));
+                }
+                // Reserves the id while the temp file is created
+                write_streams.insert(request.stream_id, WriteStreamSlot::Writing);
+            }
+            match WriteFileStream::create(&request.path, request.create_dirs.unwrap_or(false)).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    self.write_streams.lock().unwrap().remove(&request.stream_id);
+                    return Err(e);
+                }
+            }
+        } else {
+            let mut write_streams = self.write_streams.lock().unwrap();
+            let slot = write_streams
+                .get_mut(&request.stream_id)
+                .ok_or_else(|| FileIOError::InvalidArgument(format!("Invalid write stream: {}", request.stream_id)))?;
+            match std::mem::replace(slot, WriteStreamSlot::Writing) {
+                WriteStreamSlot::Idle { stream, .. } => stream,
+                busy => {
+                    *slot = busy;
+                    return Err(FileIOError::InvalidArgument(format!(
+                        "Write stream {} is already writing a chunk",
+                        request.stream_id
+                    )));
+                }
+            }
+        };
+
+        // Later chunks only pass the sandbox for the path they name, so they
+        // must name the file the stream was opened for
+        let written = if stream.path() != path {
+            Err(FileIOError::InvalidArgument(format!(
+                "Write stream {} is for {}, not {}",
+                request.stream_id,
+                stream.path().display(),
+                request.path
+            )))
+        } else {
+            match operations::decode_content(request.chunk, request.encoding.as_deref(), request.sequence == 0) {
+                Ok(data) => stream.write_chunk(request.sequence, &data).await,
+                Err(e) => Err(e),
+            }
+        };
+
+        // The stream goes back for its next chunk unless it is finished,
+        // failed or was cancelled meanwhile
+        let written = {
+            let mut write_streams = self.write_streams.lock().unwrap();
+            let written = match write_streams.get(&request.stream_id) {
+                Some(WriteStreamSlot::Cancelled) => {
+                    Err(FileIOError::InvalidArgument(format!("Write stream {} was cancelled", request.stream_id)))
+                }
+                _ => written,
+            };
+            match written {
+                Ok(bytes_written) if !request.last => {
+                    let last_used = Instant::now();
+                    write_streams.insert(request.stream_id, WriteStreamSlot::Idle { stream, last_used });
+                    return Ok(WriteFileStreamResponse {
+                        stream_id: request.stream_id,
+                        bytes_written,
+                        committed: false,
+                    });
+                }
+                written => {
+                    write_streams.remove(&request.stream_id);
+                    written
+                }
+            }
+        };
+        if let Err(e) = written {
+            stream.abort().await;
+            return Err(e);
+        }
+
+        // The stream's own path is what gets replaced
+        let target = stream.path().to_string_lossy().to_string();
+        let lock = match self.authorize_path(None, "WriteFileStream", &target, PathAccess::Write) {
+            Ok(()) => self.lock_manager.acquire_lock(&target).await,
+            Err(e) => Err(e),
+        };
+        let _lock = match lock {
+            Ok(lock) => lock,
+            Err(e) => {
+                stream.abort().await;
+                return Err(e);
+            }
+        };
+        let bytes_written = stream.commit().await?;
+        Ok(WriteFileStreamResponse {
+            stream_id: request.stream_id,
+            bytes_written,
+            committed: true,
+        })
+    }
+
+    /// Runs the operations of a batch in order, reporting a result for each.
+    /// Transactional batches are applied by `batch::run_transaction`; other
+    /// batches process each operation as if it had been sent on its own.
+    async fn run_batch(&self, request: BatchRequest) -> FileIOResult<BatchResponse> {
+        if request.operations.iter().any(|operation| matches!(operation, FileIORequest::Batch(_))) {
+            return Err(FileIOError::InvalidArgument("Batches cannot be nested".to_string()));
+        }
+
+        let operations: Vec<String> = request.operations.iter().map(|operation| operation.operation().to_string()).collect();
+        let (responses, rolled_back) = if request.transactional.unwrap_or(false) {
+            let outcome = batch::run_transaction(&self.lock_manager, request.operations).await?;
+            (outcome.results, outcome.rolled_back)
+        } else {
+            let continue_on_error = request.continue_on_error.unwrap_or(false);
+            let mut responses = Vec::with_capacity(request.operations.len());
+            for operation in request.operations {
+                let response = Box::pin(self.process_request(operation)).await;
+                let failed = response.is_err();
+                responses.push(response);
+                if failed && !continue_on_error {
+                    break;
+                }
+            }
+            (responses, false)
+        };
+
+        let committed = responses.len() == operations.len() && responses.iter().all(Result::is_ok);
+        let mut responses = responses.into_iter();
+        let results = operations
+            .into_iter()
+            .map(|operation| BatchResult {
+                operation,
+                response: responses.next().map(|response| response.unwrap_or_else(|e| Self::error_response(&e))),
+            })
+            .collect();
+        Ok(BatchResponse { results, committed, rolled_back })
+    }
+
+    fn error_response(e: &FileIOError) -> FileIOResponse {
+        FileIOResponse::Error(FileIOErrorResponse {
+            message: e.to_string(),
+            code: e.code().to_string(),
+            category: e.category(),
+            current: match e {
+                FileIOError::PreconditionFailed { current, .. } => current.as_deref().cloned(),
+                _ => None,
+            },
+        })
+    }
+
+    fn send_response(
+        ipc_sink: &Arc<dyn Fn(String) + Send + Sync>,
+        id: Option<u32>,
+        operation: &str,
+        response: FileIOResponse,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Create structured response object matching the RPC protocol
         let structured_response = serde_json::json!({
             "method": "onFileIOResponse",
-            "params": response
+            "params": FileIOResponseMessage {
+                id,
+                operation: operation.to_string(),
+                response,
+            }
         });
 
         let message = serde_json::to_string(&structured_response)?;
 
-        (self.ipc_sink)(message);
+        ipc_sink(message);
         Ok(())
     }
 
-    async fn process_request(&self, request: FileIORequest) -> Result<FileIOResponse, Box<dyn std::error::Error + Send + Sync>> {
+    pub(crate) async fn process_request(&self, request: FileIORequest) -> FileIOResult<FileIOResponse> {
         match request {
-            FileIORequest::ReadFile(req) => {
-                // Acquire lock for reading
-                let _lock = self.lock_manager.lock().await.acquire_lock(&req.path).await?;
-                let result = operations::read_file(req).await?;
+            FileIORequest::ReadFile(mut req) => {
+                // Readers share the lock
+                let _lock = self.lock_manager.acquire_shared(&req.path).await?;
+                let result = self.route(&mut req.path)?.read_file(req).await?;
                 Ok(FileIOResponse::ReadFile(result))
             }
-            FileIORequest::WriteFile(req) => {
+            FileIORequest::WriteFile(mut req) => {
                 // Acquire lock for writing
-                let _lock = self.lock_manager.lock().await.acquire_lock(&req.path).await?;
-                operations::write_file(req).await?;
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                self.route(&mut req.path)?.write_file(req).await?;
                 Ok(FileIOResponse::WriteFile(()))
             }
-            FileIORequest::Copy(req) => {
+            FileIORequest::WriteFileStream(req) => {
+                let result = self.write_stream_chunk(req).await?;
+                Ok(FileIOResponse::WriteFileStream(result))
+            }
+            FileIORequest::Copy(mut req) => {
                 // Acquire locks for both source and destination
-                let mut locks = self.lock_manager.lock().await;
-                let _source_lock = locks.acquire_lock(&req.source).await?;
-                let _dest_lock = locks.acquire_lock(&req.destination).await?;
-                operations::copy(req).await?;
+                let _locks = self
+                    .lock_manager
+                    .acquire_many(&[(&req.source, LockMode::Shared), (&req.destination, LockMode::Exclusive)])
+                    .await?;
+                self.route_pair(&mut req.source, &mut req.destination)?.copy(req).await?;
                 Ok(FileIOResponse::Copy(()))
             }
-            FileIORequest::Delete(req) => {
+            FileIORequest::Delete(req) if req.use_trash.unwrap_or(false) => {
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                let path = paths::to_path_buf(&req.path)?;
+                operations::check_preconditions(&path, &req.preconditions).await?;
+                operations::check_deletable(&path, req.recursive.unwrap_or(false)).await?;
+                let item = self.trash.trash(&req.path).await?;
+                Ok(FileIOResponse::Trash(item))
+            }
+            FileIORequest::Delete(mut req) => {
                 // Acquire lock for deletion
-                let _lock = self.lock_manager.lock().await.acquire_lock(&req.path).await?;
-                operations::delete(req).await?;
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                self.route(&mut req.path)?.delete(req).await?;
                 Ok(FileIOResponse::Delete(()))
             }
-            FileIORequest::Stat(req) => {
-                let result = operations::stat(req).await?;
+            FileIORequest::ListTrash => {
+                // Only items that came from inside the sandbox are visible
+                let items = self.trash.list().await?;
+                let visible = items
+                    .into_iter()
+                    .filter(|item| self.sandbox.as_ref().is_none_or(|sandbox| sandbox.check(&item.original_path, PathAccess::Read).is_ok()))
+                    .collect();
+                Ok(FileIOResponse::ListTrash(visible))
+            }
+            FileIORequest::RestoreTrash(req) => {
+                let item = self.trash.item(&req.id).await?;
+                self.authorize_path(None, "RestoreTrash", &item.original_path, PathAccess::Write)?;
+                let _lock = self.lock_manager.acquire_lock(&item.original_path).await?;
+                let result = self.trash.restore(&req.id, req.overwrite.unwrap_or(false), self.sandbox.clone()).await?;
+                Ok(FileIOResponse::RestoreTrash(result))
+            }
+            FileIORequest::EmptyTrash(req) => {
+                let ids = match (req.ids, &self.sandbox) {
+                    (Some(ids), _) => {
+                        for id in &ids {
+                            let item = self.trash.item(id).await?;
+                            self.authorize_path(None, "EmptyTrash", &item.original_path, PathAccess::Write)?;
+                        }
+                        Some(ids)
+                    }
+                    // A sandboxed client only empties items from inside its roots
+                    (None, Some(sandbox)) => Some(
+                        self.trash
+                            .list()
+                            .await?
+                            .into_iter()
+                            .filter(|item| sandbox.check(&item.original_path, PathAccess::Write).is_ok())
+                            .map(|item| item.id)
+                            .collect(),
+                    ),
+                    (None, None) => None,
+                };
+                Ok(FileIOResponse::EmptyTrash(self.trash.empty(ids).await?))
+            }
+            FileIORequest::Stat(mut req) => {
+                let result = self.route(&mut req.path)?.stat(req).await?;
                 Ok(FileIOResponse::Stat(result))
             }
-            FileIORequest::ReadDir(req) => {
-                let result = operations::readdir(req).await?;
+            FileIORequest::ReadDir(mut req) => {
+                let uri = req.path.clone();
+                let mut result = self.route(&mut req.path)?.readdir(req).await?;
+                for entry in &mut result {
+                    entry.path = backend::to_request_uri(&uri, std::mem::take(&mut entry.path));
+                }
                 Ok(FileIOResponse::ReadDir(result))
             }
-            FileIORequest::RealPath(req) => {
-                let result = operations::realpath(req).await?;
-                Ok(FileIOResponse::RealPath(result))
+            FileIORequest::Hash(req) => {
+                let _lock = self.lock_manager.acquire_shared(&req.path).await?;
+                let result = hash::hash(req, self.hash_cache.clone(), CancellationToken::new()).await?;
+                Ok(FileIOResponse::Hash(result))
+            }
+            FileIORequest::Symlink(req) => {
+                let link = paths::to_path_buf(&req.path)?;
+                let target = link.parent().map_or_else(|| req.target.clone().into(), |parent| parent.join(&req.target));
+                self.authorize_path(None, "Symlink", &target.to_string_lossy(), PathAccess::Read)?;
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                operations::symlink(req).await?;
+                Ok(FileIOResponse::Symlink(()))
+            }
+            FileIORequest::ReadLink(req) => {
+                let result = operations::readlink(req).await?;
+                Ok(FileIOResponse::ReadLink(result))
             }
-            FileIORequest::MkDir(req) => {
-                operations::mkdir(req).await?;
+            FileIORequest::HardLink(req) => {
+                let _locks = self
+                    .lock_manager
+                    .acquire_many(&[(&req.target, LockMode::Shared), (&req.path, LockMode::Exclusive)])
+                    .await?;
+                operations::hard_link(req).await?;
+                Ok(FileIOResponse::HardLink(()))
+            }
+            FileIORequest::ListXattr(req) => {
+                let result = operations::list_xattr(req).await?;
+                Ok(FileIOResponse::ListXattr(result))
+            }
+            FileIORequest::GetXattr(req) => {
+                let _lock = self.lock_manager.acquire_shared(&req.path).await?;
+                let result = operations::get_xattr(req).await?;
+                Ok(FileIOResponse::GetXattr(result))
+            }
+            FileIORequest::SetXattr(req) => {
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                operations::set_xattr(req).await?;
+                Ok(FileIOResponse::SetXattr(()))
+            }
+            FileIORequest::RemoveXattr(req) => {
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                operations::remove_xattr(req).await?;
+                Ok(FileIOResponse::RemoveXattr(()))
+            }
+            FileIORequest::StatFs(req) => {
+                let result = usage::statfs(req).await?;
+                Ok(FileIOResponse::StatFs(result))
+            }
+            FileIORequest::RealPath(mut req) => {
+                let uri = req.path.clone();
+                let result = self.route(&mut req.path)?.realpath(req).await?;
+                Ok(FileIOResponse::RealPath(backend::to_request_uri(&uri, result)))
+            }
+            FileIORequest::MkDir(mut req) => {
+                self.route(&mut req.path)?.mkdir(req).await?;
                 Ok(FileIOResponse::MkDir(()))
             }
-            FileIORequest::Rename(req) => {
+            FileIORequest::Rename(mut req) => {
                 // Acquire locks for both paths
-                let mut locks = self.lock_manager.lock().await;
-                let _old_lock = locks.acquire_lock(&req.old_path).await?;
-                let _new_lock = locks.acquire_lock(&req.new_path).await?;
-                operations::rename(req).await?;
+                let _locks = self
+                    .lock_manager
+                    .acquire_many(&[(&req.old_path, LockMode::Exclusive), (&req.new_path, LockMode::Exclusive)])
+                    .await?;
+                self.route_pair(&mut req.old_path, &mut req.new_path)?.rename(req).await?;
                 Ok(FileIOResponse::Rename(()))
             }
             FileIORequest::OpenFile(req) => {
                 // Acquire lock for the file being opened
-                let _lock = self.lock_manager.lock().await.acquire_lock(&req.path).await?;
-                let result = operations::open_file(req).await?;
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                let result = operations::open_file(&self.handles, req).await?;
                 Ok(FileIOResponse::OpenFile(result))
             }
             FileIORequest::CloseFile(req) => {
-                operations::close_file(req).await?;
+                operations::close_file(&self.handles, req).await?;
                 Ok(FileIOResponse::CloseFile(()))
             }
             FileIORequest::ReadFileHandle(req) => {
-                let result = operations::read_file_handle(req).await?;
+                let result = operations::read_file_handle(&self.handles, req).await?;
                 Ok(FileIOResponse::ReadFileHandle(result))
             }
             FileIORequest::WriteFileHandle(req) => {
-                let result = operations::write_file_handle(req).await?;
+                let result = operations::write_file_handle(&self.handles, req).await?;
                 Ok(FileIOResponse::WriteFileHandle(result))
             }
-            FileIORequest::ReadFileStream(req) => {
-                let result = operations::read_file_stream(req).await?;
-                Ok(FileIOResponse::ReadFileStream(result))
+            FileIORequest::FsyncFileHandle(req) => {
+                operations::fsync_file_handle(&self.handles, req).await?;
+                Ok(FileIOResponse::FsyncFileHandle(()))
+            }
+            FileIORequest::ListHandles => {
+                Ok(FileIOResponse::ListHandles(self.handles.list()))
+            }
+            FileIORequest::ReadFileStream(_)
+            | FileIORequest::CopyTree(_)
+            | FileIORequest::MoveTree(_)
+            | FileIORequest::Walk(_)
+            | FileIORequest::DirSize(_) => {
+                Err(FileIOError::InvalidArgument("Streamed requests must go through handle_request".to_string()))
             }
             FileIORequest::Clone(req) => {
                 // Acquire locks for both source and destination
-                let mut locks = self.lock_manager.lock().await;
-                let _source_lock = locks.acquire_lock(&req.source).await?;
-                let _dest_lock = locks.acquire_lock(&req.destination).await?;
-                operations::clone_file(req).await?;
-                Ok(FileIOResponse::Clone(()))
-            }
-        }
-    }
-
-    fn map_error_to_code(e: &Box<dyn std::error::Error + Send + Sync>) -> String {
-        let error_string = e.to_string().to_lowercase();
-
-        if error_string.contains("permission denied") {
-            "EPERM".to_string()
-        } else if error_string.contains("no such file or directory") {
-            "ENOENT".to_string()
-        } else if error_string.contains("is a directory") {
-            "EISDIR".to_string()
-        } else if error_string.contains("not a directory") {
-            "ENOTDIR".to_string()
-        } else if error_string.contains("file exists") {
-            "EEXIST".to_string()
-        } else if error_string.contains("disk") && error_string.contains("full") {
-            "ENOSPC".to_string()
-        } else if error_string.contains("too many") {
-            "EMFILE".to_string()
-        } else if error_string.contains("invalid") {
-            "EINVAL".to_string()
-        } else {
-            "EIO".to_string()
+                let _locks = self
+                    .lock_manager
+                    .acquire_many(&[(&req.source, LockMode::Shared), (&req.destination, LockMode::Exclusive)])
+                    .await?;
+                let result = operations::clone_file(req).await?;
+                Ok(FileIOResponse::Clone(result))
+            }
+            FileIORequest::CancelStream(req) => {
+                Ok(FileIOResponse::CancelStream(self.cancel_stream(req.stream_id).await))
+            }
+            FileIORequest::Cancel(req) => {
+                let cancellation = self.cancellable.lock().unwrap().remove(&req.id);
+                Ok(FileIOResponse::Cancel(cancellation.map(|c| c.cancel()).is_some()))
+            }
+            FileIORequest::Chmod(req) => {
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                operations::chmod(req).await?;
+                Ok(FileIOResponse::Chmod(()))
+            }
+            FileIORequest::Chown(req) => {
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                operations::chown(req).await?;
+                Ok(FileIOResponse::Chown(()))
+            }
+            FileIORequest::SetTimes(req) => {
+                let _lock = self.lock_manager.acquire_lock(&req.path).await?;
+                operations::set_times(req).await?;
+                Ok(FileIOResponse::SetTimes(()))
+            }
+            FileIORequest::Batch(req) => Ok(FileIOResponse::Batch(self.run_batch(req).await?)),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,232 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
+use crate::services::fileio::archive::{ArchiveBackend, MAX_CACHED_ARCHIVES, MAX_READ_SIZE};
+use crate::services::fileio::backend::FileSystemBackend;
+use crate::services::fileio::types::{
+    MkDirRequest, ReadDirRequest, ReadFileRequest, ReadFileStreamOptions, ReadFileStreamRequest, StatRequest,
+};
+
+fn write_zip(path: &Path, files: &[(&str, &str)]) {
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    for (name, content) in files {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn write_tar<W: Write>(writer: W, files: &[(&str, &str)]) -> W {
+    let mut tar = tar::Builder::new(writer);
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(1_700_000_000);
+        header.set_cksum();
+        tar.append_data(&mut header, name, content.as_bytes()).unwrap();
+    }
+    tar.into_inner().unwrap()
+}
+
+fn entry(archive: &Path, path: &str) -> String {
+    format!("{}!/{}", archive.display(), path)
+}
+
+async fn read(backend: &ArchiveBackend, path: String) -> String {
+    backend.read_file(ReadFileRequest { path, encoding: None }).await.unwrap().content
+}
+
+#[tokio::test]
+async fn test_browse_zip() {
+    let temp_dir = TempDir::new().unwrap();
+    let vsix = temp_dir.path().join("extension.vsix");
+    write_zip(
+        &vsix,
+        &[
+            ("extension.vsixmanifest", "<manifest/>"),
+            ("extension/package.json", "{\"name\":\"test\"}"),
+            ("extension/out/main.js", "console.log(1)"),
+        ],
+    );
+
+    let backend = ArchiveBackend::new();
+    assert_eq!(read(&backend, entry(&vsix, "extension/package.json")).await, "{\"name\":\"test\"}");
+
+    let root = backend.stat(StatRequest { path: entry(&vsix, "") }).await.unwrap();
+    assert!(root.is_directory);
+    let stat = backend.stat(StatRequest { path: entry(&vsix, "extension/out/main.js") }).await.unwrap();
+    assert!(stat.is_file && stat.readonly);
+    assert_eq!(stat.size, 14);
+
+    // Directories without entries of their own are implied by their files
+    let entries = backend.readdir(ReadDirRequest { path: entry(&vsix, "extension") }).await.unwrap();
+    let names: Vec<(&str, bool)> = entries.iter().map(|e| (e.name.as_str(), e.is_directory)).collect();
+    assert_eq!(names, vec![("out", true), ("package.json", false)]);
+    assert_eq!(entries[1].path, entry(&vsix, "extension/package.json"));
+
+    let missing = backend.stat(StatRequest { path: entry(&vsix, "extension/missing.js") }).await.unwrap_err();
+    assert_eq!(missing.code(), "ENOENT");
+    let write = backend.mkdir(MkDirRequest { path: entry(&vsix, "new"), recursive: None }).await.unwrap_err();
+    assert_eq!(write.code(), "EROFS");
+
+    // A rewritten archive is indexed again
+    write_zip(&vsix, &[("extension/package.json", "{\"name\":\"updated\",\"version\":\"2\"}")]);
+    assert_eq!(read(&backend, entry(&vsix, "extension/package.json")).await, "{\"name\":\"updated\",\"version\":\"2\"}");
+    assert!(backend.stat(StatRequest { path: entry(&vsix, "extension.vsixmanifest") }).await.is_err());
+}
+
+#[tokio::test]
+async fn test_browse_tar_and_tar_gz() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = [("pkg/README.md", "readme"), ("pkg/lib/index.js", "module.exports = 1")];
+
+    let tar = temp_dir.path().join("pkg.tar");
+    write_tar(File::create(&tar).unwrap(), &files);
+    let tar_gz = temp_dir.path().join("pkg.tar.gz");
+    let encoder = flate2::write::GzEncoder::new(File::create(&tar_gz).unwrap(), flate2::Compression::default());
+    write_tar(encoder, &files).finish().unwrap();
+
+    let backend = ArchiveBackend::new();
+    for archive in [&tar, &tar_gz] {
+        assert_eq!(read(&backend, entry(archive, "pkg/lib/index.js")).await, "module.exports = 1");
+        assert_eq!(read(&backend, entry(archive, "pkg/./lib/../README.md")).await, "readme");
+
+        let stat = backend.stat(StatRequest { path: entry(archive, "pkg/README.md") }).await.unwrap();
+        assert_eq!(stat.mtime, 1_700_000_000_000);
+        let entries = backend.readdir(ReadDirRequest { path: entry(archive, "pkg") }).await.unwrap();
+        assert_eq!(entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["README.md", "lib"]);
+    }
+}
+
+#[tokio::test]
+async fn test_read_large_tar_gz_entry() {
+    let temp_dir = TempDir::new().unwrap();
+    // Too large to be kept in the index, so it is read from its offset
+    let large: String = (0..20_000).map(|i| i.to_string()).collect();
+    let files = [("a.txt", "first"), ("large.txt", large.as_str()), ("z.txt", "last")];
+    let tar_gz = temp_dir.path().join("pkg.tar.gz");
+    let encoder = flate2::write::GzEncoder::new(File::create(&tar_gz).unwrap(), flate2::Compression::default());
+    write_tar(encoder, &files).finish().unwrap();
+
+    let backend = ArchiveBackend::new();
+    assert_eq!(read(&backend, entry(&tar_gz, "large.txt")).await, large);
+    assert_eq!(read(&backend, entry(&tar_gz, "z.txt")).await, "last");
+    assert_eq!(read(&backend, entry(&tar_gz, "a.txt")).await, "first");
+}
+
+#[tokio::test]
+async fn test_read_rejects_oversized_entry() {
+    let temp_dir = TempDir::new().unwrap();
+    let zip = temp_dir.path().join("bomb.zip");
+    write_zip(&zip, &[("bomb.txt", "small")]);
+
+    // Claim a huge uncompressed size in the central directory
+    let mut bytes = std::fs::read(&zip).unwrap();
+    let header = bytes.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+    bytes[header + 24..header + 28].copy_from_slice(&(MAX_READ_SIZE as u32 + 1).to_le_bytes());
+    std::fs::write(&zip, bytes).unwrap();
+
+    let backend = ArchiveBackend::new();
+    let stat = backend.stat(StatRequest { path: entry(&zip, "bomb.txt") }).await.unwrap();
+    assert_eq!(stat.size, MAX_READ_SIZE + 1);
+    let err = backend.read_file(ReadFileRequest { path: entry(&zip, "bomb.txt"), encoding: None }).await.unwrap_err();
+    assert_eq!(err.code(), "EFBIG");
+}
+
+#[tokio::test]
+async fn test_index_cache_evicts_least_recently_used() {
+    let temp_dir = TempDir::new().unwrap();
+    let archives: Vec<_> = (0..=MAX_CACHED_ARCHIVES).map(|i| temp_dir.path().join(format!("{}.tar", i))).collect();
+    for archive in &archives {
+        write_tar(File::create(archive).unwrap(), &[("a.txt", "a")]);
+    }
+
+    let backend = ArchiveBackend::new();
+    let stat = |archive: &Path| backend.stat(StatRequest { path: entry(archive, "a.txt") });
+    for archive in &archives[..MAX_CACHED_ARCHIVES] {
+        stat(archive).await.unwrap();
+    }
+    // Using the first again leaves the second as the least recently used
+    stat(&archives[0]).await.unwrap();
+    stat(&archives[MAX_CACHED_ARCHIVES]).await.unwrap();
+
+    // Rewritten with the same size and mtime, only an evicted index notices
+    for archive in &archives[..2] {
+        let mtime = std::fs::metadata(archive).unwrap().modified().unwrap();
+        let file = write_tar(File::create(archive).unwrap(), &[("b.txt", "b")]);
+        file.set_modified(mtime).unwrap();
+    }
+    assert!(stat(&archives[0]).await.is_ok());
+    assert!(stat(&archives[1]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_stream_archive_entry() {
+    let temp_dir = TempDir::new().unwrap();
+    let zip = temp_dir.path().join("data.zip");
+    write_zip(&zip, &[("data.txt", "0123456789")]);
+
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+    let sink = chunks.clone();
+    let request = ReadFileStreamRequest {
+        path: entry(&zip, "data.txt"),
+        options: Some(ReadFileStreamOptions { start: Some(2), length: Some(6), buffer_size: Some(4) }),
+        stream_id: Some(1),
+    };
+    ArchiveBackend::new()
+        .read_file_stream(request, CancellationToken::new(), Box::new(move |chunk| sink.lock().unwrap().push(chunk)))
+        .await
+        .unwrap();
+
+    let chunks = chunks.lock().unwrap();
+    let content: Vec<u8> = chunks.iter().flat_map(|chunk| chunk.chunk.clone()).collect();
+    assert_eq!(content, b"234567");
+    assert_eq!(chunks.iter().map(|chunk| chunk.position).collect::<Vec<_>>(), vec![2, 6, 8]);
+    assert!(chunks.last().unwrap().done);
+}
+
+#[tokio::test]
+async fn test_stream_large_tar_gz_entry() {
+    let temp_dir = TempDir::new().unwrap();
+    let large: String = (0..100_000).map(|i| i.to_string()).collect();
+    let tar_gz = temp_dir.path().join("pkg.tar.gz");
+    let encoder = flate2::write::GzEncoder::new(File::create(&tar_gz).unwrap(), flate2::Compression::default());
+    write_tar(encoder, &[("a.txt", "first"), ("large.txt", large.as_str())]).finish().unwrap();
+
+    let stream = |start, cancellation| {
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let sink = chunks.clone();
+        let request = ReadFileStreamRequest {
+            path: entry(&tar_gz, "large.txt"),
+            options: Some(ReadFileStreamOptions { start: Some(start), length: None, buffer_size: Some(64 * 1024) }),
+            stream_id: Some(1),
+        };
+        async move {
+            ArchiveBackend::new()
+                .read_file_stream(request, cancellation, Box::new(move |chunk| sink.lock().unwrap().push(chunk)))
+                .await
+                .unwrap();
+            Arc::try_unwrap(chunks).unwrap().into_inner().unwrap()
+        }
+    };
+
+    let chunks = stream(10, CancellationToken::new()).await;
+    let content: Vec<u8> = chunks.iter().flat_map(|chunk| chunk.chunk.clone()).collect();
+    assert_eq!(content, large.as_bytes()[10..]);
+    assert!(chunks.len() > 2);
+
+    let cancellation = CancellationToken::new();
+    cancellation.cancel();
+    let chunks = stream(0, cancellation).await;
+    assert_eq!(chunks.len(), 1);
+    assert!(chunks[0].done && chunks[0].cancelled);
+}
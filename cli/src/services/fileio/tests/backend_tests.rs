@@ -0,0 +1,145 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::sync::Arc;
+use crate::services::fileio::backend::{local_path, split_scheme, to_request_uri, BackendRegistry, FileSystemBackend};
+use crate::services::fileio::errors::FileIOError;
+use crate::services::fileio::memory::MemoryBackend;
+use crate::services::fileio::types::{
+    CopyRequest, DeleteRequest, MkDirRequest, ReadDirRequest, ReadFileRequest, RealPathRequest, RenameRequest,
+    StatRequest, WriteFileRequest,
+};
+
+fn write(path: &str, content: &str) -> WriteFileRequest {
+    WriteFileRequest {
+        path: path.to_string(),
+        content: content.to_string(),
+        encoding: None,
+        create_dirs: Some(true),
+        atomic: None,
+        preconditions: Default::default(),
+    }
+}
+
+async fn read(backend: &MemoryBackend, path: &str) -> String {
+    let request = ReadFileRequest { path: path.to_string(), encoding: None };
+    backend.read_file(request).await.unwrap().content
+}
+
+#[tokio::test]
+async fn test_memory_backend_files_and_directories() {
+    let backend = MemoryBackend::new();
+    backend.write_file(write("/docs/notes.txt", "hello")).await.unwrap();
+    assert_eq!(read(&backend, "/docs/notes.txt").await, "hello");
+    assert_eq!(read(&backend, "docs/./../docs/notes.txt").await, "hello");
+
+    let stat = backend.stat(StatRequest { path: "/docs/notes.txt".to_string() }).await.unwrap();
+    assert!(stat.is_file);
+    assert_eq!(stat.size, 5);
+
+    // Rewrites always move the mtime forward, so mtime preconditions see them
+    let mut rewrite = write("/docs/notes.txt", "hello again");
+    rewrite.preconditions.expected_mtime = Some(stat.mtime);
+    backend.write_file(rewrite).await.unwrap();
+    let mut stale = write("/docs/notes.txt", "lost");
+    stale.preconditions.expected_mtime = Some(stat.mtime);
+    assert!(matches!(backend.write_file(stale).await, Err(FileIOError::PreconditionFailed { .. })));
+
+    let missing = backend.stat(StatRequest { path: "/missing".to_string() }).await.unwrap_err();
+    assert_eq!(missing.code(), "ENOENT");
+    let mut no_parent = write("/nowhere/file.txt", "x");
+    no_parent.create_dirs = Some(false);
+    assert_eq!(backend.write_file(no_parent).await.unwrap_err().code(), "ENOENT");
+
+    backend.mkdir(MkDirRequest { path: "/docs/sub".to_string(), recursive: None }).await.unwrap();
+    let exists = backend.mkdir(MkDirRequest { path: "/docs/sub".to_string(), recursive: None }).await.unwrap_err();
+    assert_eq!(exists.code(), "EEXIST");
+
+    let entries = backend.readdir(ReadDirRequest { path: "/docs".to_string() }).await.unwrap();
+    let names: Vec<(&str, &str, bool)> = entries.iter().map(|e| (e.name.as_str(), e.path.as_str(), e.is_directory)).collect();
+    assert_eq!(names, vec![("notes.txt", "/docs/notes.txt", false), ("sub", "/docs/sub", true)]);
+
+    let realpath = backend.realpath(RealPathRequest { path: "/docs/sub/..".to_string() }).await.unwrap();
+    assert_eq!(realpath, "/docs");
+}
+
+#[tokio::test]
+async fn test_memory_backend_copy_rename_delete() {
+    let backend = MemoryBackend::new();
+    backend.write_file(write("/a/one.txt", "1")).await.unwrap();
+    backend.write_file(write("/b.txt", "b")).await.unwrap();
+
+    let copy = |destination: &str, overwrite| CopyRequest {
+        source: "/a".to_string(),
+        destination: destination.to_string(),
+        overwrite: Some(overwrite),
+        preserve_xattrs: None,
+    };
+    backend.copy(copy("/c", false)).await.unwrap();
+    assert_eq!(read(&backend, "/c/one.txt").await, "1");
+    assert_eq!(backend.copy(copy("/c", false)).await.unwrap_err().code(), "EEXIST");
+
+    let rename = |old_path: &str, new_path: &str| RenameRequest {
+        old_path: old_path.to_string(),
+        new_path: new_path.to_string(),
+        preconditions: Default::default(),
+    };
+    assert_eq!(backend.rename(rename("/a", "/b.txt")).await.unwrap_err().code(), "ENOTDIR");
+    assert_eq!(backend.rename(rename("/a", "/c")).await.unwrap_err().code(), "ENOTEMPTY");
+    assert_eq!(backend.rename(rename("/a", "/a/inner")).await.unwrap_err().code(), "EINVAL");
+    backend.rename(rename("/b.txt", "/a/b.txt")).await.unwrap();
+    assert_eq!(read(&backend, "/a/b.txt").await, "b");
+    assert!(backend.stat(StatRequest { path: "/b.txt".to_string() }).await.is_err());
+
+    let delete = |path: &str, recursive| DeleteRequest {
+        path: path.to_string(),
+        recursive: Some(recursive),
+        atomic: None,
+        use_trash: None,
+        preconditions: Default::default(),
+    };
+    assert_eq!(backend.delete(delete("/a", false)).await.unwrap_err().code(), "ENOTEMPTY");
+    backend.delete(delete("/a", true)).await.unwrap();
+    let entries = backend.readdir(ReadDirRequest { path: "/".to_string() }).await.unwrap();
+    assert_eq!(entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["c"]);
+}
+
+#[tokio::test]
+async fn test_registry_routes_by_scheme() {
+    assert_eq!(split_scheme("untitled:Untitled-1"), Some(("untitled".to_string(), "Untitled-1")));
+    assert_eq!(split_scheme("/home/user/file.txt"), None);
+    assert_eq!(split_scheme("C:\\Users\\file.txt"), None);
+
+    if cfg!(unix) {
+        assert_eq!(local_path("file:///tmp/a%20b.txt").unwrap(), Some("/tmp/a b.txt".to_string()));
+    }
+    assert_eq!(local_path("/tmp/a.txt").unwrap(), Some("/tmp/a.txt".to_string()));
+    assert_eq!(local_path("memfs:/a.txt").unwrap(), None);
+    assert_eq!(to_request_uri("memfs:/docs", "/docs/a.txt".to_string()), "memfs:/docs/a.txt");
+    assert_eq!(to_request_uri("/docs", "/docs/a.txt".to_string()), "/docs/a.txt");
+
+    let registry = BackendRegistry::new();
+    let memory = Arc::new(MemoryBackend::new());
+    registry.register("MemFS", memory.clone());
+
+    let (backend, path) = registry.resolve("memfs:///notes/today.txt").unwrap();
+    assert_eq!(path, "/notes/today.txt");
+    backend.write_file(write(&path, "scratch")).await.unwrap();
+    assert_eq!(read(&memory, "/notes/today.txt").await, "scratch");
+
+    let (_, path) = registry.resolve("/tmp/file.txt").unwrap();
+    assert_eq!(path, "/tmp/file.txt");
+    assert!(matches!(registry.resolve("remote:/file.txt"), Err(FileIOError::Unsupported(_))));
+
+    assert!(registry.unregister("memfs"));
+    assert!(registry.resolve("memfs:/notes/today.txt").is_err());
+
+    // Untitled files are served from memory without any registration
+    let (backend, path) = registry.resolve("untitled:Untitled-1").unwrap();
+    backend.write_file(write(&path, "unsaved")).await.unwrap();
+    let (backend, path) = registry.resolve("untitled:Untitled-1").unwrap();
+    let content = backend.read_file(ReadFileRequest { path, encoding: None }).await.unwrap().content;
+    assert_eq!(content, "unsaved");
+}
@@ -0,0 +1,145 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+use crate::services::fileio::batch::run_transaction;
+use crate::services::fileio::locks::ResourceLockManager;
+use crate::services::fileio::types::{
+    CopyRequest, DeleteRequest, FileIORequest, FileIOResponse, MkDirRequest, RenameRequest, WriteFileRequest,
+};
+
+fn write(path: &Path, content: &str, create_dirs: bool) -> FileIORequest {
+    FileIORequest::WriteFile(WriteFileRequest {
+        path: path.to_string_lossy().to_string(),
+        content: content.to_string(),
+        encoding: None,
+        create_dirs: Some(create_dirs),
+        atomic: None,
+        preconditions: Default::default(),
+    })
+}
+
+fn delete(path: &Path) -> FileIORequest {
+    FileIORequest::Delete(DeleteRequest {
+        path: path.to_string_lossy().to_string(),
+        recursive: Some(true),
+        atomic: None,
+        use_trash: None,
+        preconditions: Default::default(),
+    })
+}
+
+fn rename(old_path: &Path, new_path: &Path) -> FileIORequest {
+    FileIORequest::Rename(RenameRequest {
+        old_path: old_path.to_string_lossy().to_string(),
+        new_path: new_path.to_string_lossy().to_string(),
+        preconditions: Default::default(),
+    })
+}
+
+/// Names in `dir`, to check that no backups or temp files are left behind.
+fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn test_transaction_commits() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::write(root.join("a.txt"), "old a").unwrap();
+    fs::write(root.join("b.txt"), "b").unwrap();
+    fs::write(root.join("c.txt"), "c").unwrap();
+
+    let operations = vec![
+        write(&root.join("a.txt"), "new a", false),
+        delete(&root.join("b.txt")),
+        rename(&root.join("c.txt"), &root.join("d.txt")),
+        FileIORequest::MkDir(MkDirRequest { path: root.join("x/y").to_string_lossy().to_string(), recursive: Some(true) }),
+        FileIORequest::Copy(CopyRequest {
+            source: root.join("a.txt").to_string_lossy().to_string(),
+            destination: root.join("x/y/a.txt").to_string_lossy().to_string(),
+            overwrite: None,
+            preserve_xattrs: None,
+        }),
+    ];
+    let outcome = run_transaction(&ResourceLockManager::new(), operations).await.unwrap();
+
+    assert_eq!(outcome.results.len(), 5);
+    assert!(outcome.results.iter().all(Result::is_ok));
+    assert!(matches!(outcome.results[0], Ok(FileIOResponse::WriteFile(()))));
+    assert!(!outcome.rolled_back);
+    assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "new a");
+    assert_eq!(fs::read_to_string(root.join("d.txt")).unwrap(), "c");
+    assert_eq!(fs::read_to_string(root.join("x/y/a.txt")).unwrap(), "new a");
+    assert_eq!(names(root), vec!["a.txt", "d.txt", "x"]);
+}
+
+#[tokio::test]
+async fn test_transaction_rolls_back_on_failure() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::write(root.join("a.txt"), "old a").unwrap();
+    fs::write(root.join("b.txt"), "b").unwrap();
+    fs::write(root.join("c.txt"), "c").unwrap();
+    fs::write(root.join("d.txt"), "d").unwrap();
+
+    let operations = vec![
+        write(&root.join("a.txt"), "new a", false),
+        write(&root.join("new/dirs/e.txt"), "e", true),
+        delete(&root.join("b.txt")),
+        // Replaces d.txt, which must come back
+        rename(&root.join("c.txt"), &root.join("d.txt")),
+        delete(&root.join("missing.txt")),
+        write(&root.join("never.txt"), "never", false),
+    ];
+    let outcome = run_transaction(&ResourceLockManager::new(), operations).await.unwrap();
+
+    assert_eq!(outcome.results.len(), 5);
+    assert!(outcome.results[..4].iter().all(Result::is_ok));
+    assert!(outcome.results[4].is_err());
+    assert!(outcome.rolled_back);
+    assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "old a");
+    assert_eq!(fs::read_to_string(root.join("b.txt")).unwrap(), "b");
+    assert_eq!(fs::read_to_string(root.join("c.txt")).unwrap(), "c");
+    assert_eq!(fs::read_to_string(root.join("d.txt")).unwrap(), "d");
+    assert_eq!(names(root), vec!["a.txt", "b.txt", "c.txt", "d.txt"]);
+}
+
+#[tokio::test]
+async fn test_transaction_rolls_back_recursive_mkdir() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+
+    let operations = vec![
+        FileIORequest::MkDir(MkDirRequest { path: root.join("x/y/z").to_string_lossy().to_string(), recursive: Some(true) }),
+        write(&root.join("x/y/z/a.txt"), "a", false),
+        delete(&root.join("missing.txt")),
+    ];
+    let outcome = run_transaction(&ResourceLockManager::new(), operations).await.unwrap();
+
+    assert_eq!(outcome.results.len(), 3);
+    assert!(outcome.results[2].is_err());
+    // The innermost directory has to go first, or removing the others fails
+    assert!(outcome.rolled_back);
+    assert!(names(root).is_empty());
+}
+
+#[tokio::test]
+async fn test_transaction_rejects_unsupported_operations() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("a.txt");
+
+    let operations = vec![write(&path, "a", false), FileIORequest::ListTrash];
+    assert!(run_transaction(&ResourceLockManager::new(), operations).await.is_err());
+    // Validation happens before anything is applied
+    assert!(!path.exists());
+}
@@ -4,7 +4,6 @@
  *--------------------------------------------------------------------------------------------*/
 
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tempfile::TempDir;
 use std::time::Duration;
 
@@ -19,7 +18,7 @@ async fn test_concurrent_writes_to_same_file() {
     let test_file = temp_dir.path().join("concurrent.txt");
 
     let ipc_sink = Arc::new(|_| {});
-    let service = Arc::new(Mutex::new(FileIOService::new(ipc_sink)));
+    let service = Arc::new(FileIOService::new(ipc_sink));
 
     let mut handles = vec![];
 
@@ -35,10 +34,11 @@ async fn test_concurrent_writes_to_same_file() {
                 create_dirs: Some(false),
                 atomic: Some(crate::services::fileio::types::AtomicWriteOptions {
                     postfix: ".tmp".to_string(),
+                    preserve_xattrs: None,
                 }),
+                preconditions: Default::default(),
             });
 
-            let service = service.lock().await;
             service.process_request(request).await
         });
         handles.push(handle);
@@ -64,7 +64,7 @@ async fn test_concurrent_reads_from_same_file() {
     std::fs::write(&test_file, test_content).unwrap();
 
     let ipc_sink = Arc::new(|_| {});
-    let service = Arc::new(Mutex::new(FileIOService::new(ipc_sink)));
+    let service = Arc::new(FileIOService::new(ipc_sink));
 
     let mut handles = vec![];
 
@@ -78,7 +78,6 @@ async fn test_concurrent_reads_from_same_file() {
                 encoding: Some("utf8".to_string()),
             });
 
-            let service = service.lock().await;
             service.process_request(request).await
         });
         handles.push(handle);
@@ -103,33 +102,25 @@ async fn test_concurrent_operations_mixed() {
     let test_file = temp_dir.path().join("mixed_operations.txt");
 
     let ipc_sink = Arc::new(|_| {});
-    let service = Arc::new(Mutex::new(FileIOService::new(ipc_sink)));
+    let service = Arc::new(FileIOService::new(ipc_sink));
+
+    // Write initial content before any reader can run
+    let request = FileIORequest::WriteFile(WriteFileRequest {
+        path: test_file.to_string_lossy().to_string(),
+        content: "Initial content".to_string(),
+        encoding: Some("utf8".to_string()),
+        create_dirs: Some(false),
+        atomic: None,
+        preconditions: Default::default(),
+    });
+    service.process_request(request).await.unwrap();
 
     let mut handles = vec![];
 
-    // Write initial content
-    {
-        let service = service.clone();
-        let file_path = test_file.to_string_lossy().to_string();
-        let handle = tokio::spawn(async move {
-            let request = FileIORequest::WriteFile(WriteFileRequest {
-                path: file_path.clone(),
-                content: "Initial content".to_string(),
-                encoding: Some("utf8".to_string()),
-                create_dirs: Some(false),
-                atomic: None,
-            });
-
-            let service = service.lock().await;
-            service.process_request(request).await
-        });
-        handles.push(handle);
-    }
-
     // Concurrent reads and writes
     for i in 0..5 {
         // Reader
-        let service = service.clone();
+        let reader = service.clone();
         let file_path = test_file.to_string_lossy().to_string();
         let handle = tokio::spawn(async move {
             tokio::time::sleep(Duration::from_millis(10)).await; // Small delay
@@ -138,8 +129,7 @@ async fn test_concurrent_operations_mixed() {
                 encoding: Some("utf8".to_string()),
             });
 
-            let service = service.lock().await;
-            service.process_request(request).await
+            reader.process_request(request).await
         });
         handles.push(handle);
 
@@ -155,10 +145,11 @@ async fn test_concurrent_operations_mixed() {
                 create_dirs: Some(false),
                 atomic: Some(crate::services::fileio::types::AtomicWriteOptions {
                     postfix: ".tmp".to_string(),
+                    preserve_xattrs: None,
                 }),
+                preconditions: Default::default(),
             });
 
-            let service = service.lock().await;
             service.process_request(request).await
         });
         handles.push(handle);
@@ -183,12 +174,12 @@ async fn test_file_handle_operations_concurrent() {
     std::fs::write(&test_file, "Handle test").unwrap();
 
     let ipc_sink = Arc::new(|_| {});
-    let service = Arc::new(Mutex::new(FileIOService::new(ipc_sink)));
+    let service = Arc::new(FileIOService::new(ipc_sink));
 
     let mut handles = vec![];
 
     // Test concurrent handle operations
-    for i in 0..3 {
+    for _ in 0..3 {
         let service = service.clone();
         let file_path = test_file.to_string_lossy().to_string();
         let handle = tokio::spawn(async move {
@@ -203,13 +194,14 @@ async fn test_file_handle_operations_concurrent() {
                 unlock: None,
             });
 
-            let service = service.lock().await;
             let open_result = service.process_request(open_request).await;
+            let opened = match &open_result {
+                Ok(crate::services::fileio::types::FileIOResponse::OpenFile(open_response)) => Some(open_response.handle),
+                _ => None,
+            };
             results.push(open_result);
 
-            if let Ok(crate::services::fileio::types::FileIOResponse::OpenFile(open_response)) = open_result {
-                let handle_id = open_response.handle;
-
+            if let Some(handle_id) = opened {
                 // Close file
                 let close_request = FileIORequest::CloseFile(CloseFileRequest {
                     handle: handle_id,
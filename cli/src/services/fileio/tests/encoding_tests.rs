@@ -0,0 +1,82 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::fs;
+use tempfile::TempDir;
+use crate::services::fileio::encoding::{self, TextEncoding};
+use crate::services::fileio::operations::{read_file, write_file};
+use crate::services::fileio::types::{ReadFileRequest, WriteFileRequest};
+
+#[test]
+fn test_utf16_bom_round_trip() {
+    for label in ["utf16le", "utf16be"] {
+        let bytes = encoding::encode("héllo", label, true).unwrap();
+        let decoded = encoding::decode(&bytes, encoding::AUTO).unwrap();
+        assert_eq!(decoded.content, "héllo");
+        assert_eq!(decoded.encoding.id(), label);
+    }
+
+    let le = encoding::encode("A", "utf16le", true).unwrap();
+    assert_eq!(le, vec![0xFF, 0xFE, 0x41, 0x00]);
+}
+
+#[test]
+fn test_legacy_encodings() {
+    let latin1 = encoding::decode(&[0x63, 0x61, 0x66, 0xE9], "latin1").unwrap();
+    assert_eq!(latin1.content, "café");
+
+    // 0x80 is the euro sign in Windows-1252 but a C1 control in Latin-1
+    assert_eq!(encoding::decode(&[0x80], "windows1252").unwrap().content, "€");
+    assert_eq!(encoding::decode(&[0x80], "latin1").unwrap().content, "\u{80}");
+
+    let sjis = encoding::encode("日本語", "shiftjis", false).unwrap();
+    assert_eq!(encoding::decode(&sjis, "shiftjis").unwrap().content, "日本語");
+    let gbk = encoding::encode("中文", "gbk", false).unwrap();
+    assert_eq!(encoding::decode(&gbk, "gbk").unwrap().content, "中文");
+
+    // BOM bytes are plain text in a legacy encoding
+    let latin1_text = encoding::decode(&[0xFF, 0xFE, 0x41], "latin1").unwrap();
+    assert_eq!(latin1_text.content, "ÿþA");
+    assert_eq!(latin1_text.encoding, TextEncoding::Latin1);
+    assert_eq!(encoding::decode(&[0xFF, 0xFE, 0x41, 0x00], "utf8").unwrap().encoding, TextEncoding::Utf16Le);
+
+    assert!(encoding::encode("日本", "latin1", false).is_err());
+    assert!(encoding::encode("x", "klingon", false).is_err());
+}
+
+#[test]
+fn test_auto_detection_and_lossy_utf8() {
+    let detected = encoding::decode(&encoding::encode("これは日本語のテキストです。設定ファイル", "shiftjis", false).unwrap(), encoding::AUTO).unwrap();
+    assert_eq!(detected.encoding, TextEncoding::ShiftJis);
+    assert!(!detected.lossy);
+
+    let lossy = encoding::decode(b"ok \xFF end", "utf8").unwrap();
+    assert_eq!(lossy.content, "ok \u{FFFD} end");
+    assert!(lossy.lossy);
+}
+
+#[tokio::test]
+async fn test_read_write_preserve_utf8_bom() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = temp_dir.path().join("bom.cfg");
+    fs::write(&test_file, b"\xEF\xBB\xBFkey=value").unwrap();
+    let path = test_file.to_string_lossy().to_string();
+
+    let read = read_file(ReadFileRequest { path: path.clone(), encoding: Some("utf8".to_string()) }).await.unwrap();
+    assert_eq!(read.content, "key=value");
+    assert_eq!(read.encoding, "utf8bom");
+
+    write_file(WriteFileRequest {
+        path,
+        content: "key=other".to_string(),
+        encoding: Some(read.encoding),
+        create_dirs: None,
+        atomic: None,
+        preconditions: Default::default(),
+    })
+    .await
+    .unwrap();
+    assert_eq!(fs::read(&test_file).unwrap(), b"\xEF\xBB\xBFkey=other");
+}
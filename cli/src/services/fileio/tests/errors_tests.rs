@@ -0,0 +1,74 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::io::{Error, ErrorKind};
+
+use crate::services::fileio::errors::{FileIOError, FileSystemProviderErrorCode};
+use crate::services::fileio::operations::*;
+use crate::services::fileio::types::*;
+
+#[test]
+fn test_raw_os_errors_map_to_posix_codes() {
+    let cases = [
+        (libc::ENOENT, "ENOENT", FileSystemProviderErrorCode::FileNotFound),
+        (libc::EEXIST, "EEXIST", FileSystemProviderErrorCode::FileExists),
+        (libc::ENOTEMPTY, "ENOTEMPTY", FileSystemProviderErrorCode::Unknown),
+        (libc::EACCES, "EACCES", FileSystemProviderErrorCode::NoPermissions),
+        (libc::ELOOP, "ELOOP", FileSystemProviderErrorCode::Unknown),
+        (libc::EXDEV, "EXDEV", FileSystemProviderErrorCode::Unknown),
+        (libc::ETIMEDOUT, "ETIMEDOUT", FileSystemProviderErrorCode::Unavailable),
+        (libc::ENOSPC, "ENOSPC", FileSystemProviderErrorCode::FileExceedsStorageQuota),
+    ];
+
+    for (errno, code, category) in cases {
+        let error = FileIOError::from(Error::from_raw_os_error(errno));
+        assert_eq!(error.code(), code);
+        assert_eq!(error.category(), category);
+    }
+}
+
+#[test]
+fn test_error_kinds_map_without_os_error() {
+    assert_eq!(FileIOError::from(Error::from(ErrorKind::NotFound)).code(), "ENOENT");
+    assert_eq!(FileIOError::from(Error::from(ErrorKind::PermissionDenied)).code(), "EACCES");
+    assert_eq!(FileIOError::from(Error::from(ErrorKind::DirectoryNotEmpty)).code(), "ENOTEMPTY");
+    assert_eq!(FileIOError::from(Error::other("disk is full")).code(), "EIO");
+}
+
+#[test]
+fn test_service_errors_have_precise_codes() {
+    assert_eq!(FileIOError::InvalidHandle(3).code(), "EBADF");
+    assert_eq!(FileIOError::AlreadyExists("Destination already exists".to_string()).code(), "EEXIST");
+
+    let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(Error::from_raw_os_error(libc::EISDIR));
+    assert_eq!(FileIOError::from(boxed).code(), "EISDIR");
+}
+
+#[tokio::test]
+async fn test_operations_return_typed_errors() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let source = temp_dir.path().join("source.txt");
+    let dest = temp_dir.path().join("dest.txt");
+    std::fs::write(&source, "source").unwrap();
+    std::fs::write(&dest, "dest").unwrap();
+
+    let err = copy(CopyRequest {
+        source: source.to_string_lossy().to_string(),
+        destination: dest.to_string_lossy().to_string(),
+        overwrite: Some(false),
+        preserve_xattrs: None,
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(err.code(), "EEXIST");
+
+    let err = stat(StatRequest {
+        path: temp_dir.path().join("missing").to_string_lossy().to_string(),
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(err.code(), "ENOENT");
+    assert_eq!(err.category(), FileSystemProviderErrorCode::FileNotFound);
+}
@@ -0,0 +1,57 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::time::Duration;
+use tempfile::TempDir;
+use crate::services::fileio::handles::{HandleTable, HandleTableConfig};
+use crate::services::fileio::FileIOError;
+
+fn open_temp(temp_dir: &TempDir, name: &str) -> (std::fs::File, String) {
+    let path = temp_dir.path().join(name);
+    std::fs::write(&path, name).unwrap();
+    (std::fs::File::open(&path).unwrap(), path.to_string_lossy().to_string())
+}
+
+#[test]
+fn test_handle_limit_returns_emfile() {
+    let temp_dir = TempDir::new().unwrap();
+    let table = HandleTable::new(HandleTableConfig { max_open: 2, idle_timeout: None });
+
+    let (file, path) = open_temp(&temp_dir, "a");
+    let first = table.insert(file, path, false).unwrap();
+    let (file, path) = open_temp(&temp_dir, "b");
+    table.insert(file, path, true).unwrap();
+
+    let (file, path) = open_temp(&temp_dir, "c");
+    let err = table.insert(file, path, false).unwrap_err();
+    assert!(matches!(err, FileIOError::TooManyOpenFiles(2)));
+    assert_eq!(err.code(), "EMFILE");
+
+    // Closing a handle frees a slot
+    table.remove(first).unwrap();
+    let (file, path) = open_temp(&temp_dir, "c");
+    table.insert(file, path, false).unwrap();
+
+    let listed = table.list();
+    assert_eq!(listed.len(), 2);
+    assert!(listed.iter().any(|info| info.path.ends_with("b") && info.writable));
+}
+
+#[test]
+fn test_idle_handles_are_reaped() {
+    let temp_dir = TempDir::new().unwrap();
+    let table = HandleTable::new(HandleTableConfig {
+        max_open: 16,
+        idle_timeout: Some(Duration::from_millis(50)),
+    });
+
+    let (file, path) = open_temp(&temp_dir, "idle");
+    let handle = table.insert(file, path, false).unwrap();
+    assert!(table.get(handle).is_ok());
+
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(matches!(table.get(handle), Err(FileIOError::InvalidHandle(_))));
+    assert!(table.is_empty());
+}
@@ -0,0 +1,118 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
+use crate::services::fileio::hash::{self, HashCache};
+use crate::services::fileio::types::{HashAlgorithm, HashRequest};
+
+fn hash_request(path: &std::path::Path, algorithm: HashAlgorithm) -> HashRequest {
+    HashRequest {
+        path: path.to_string_lossy().to_string(),
+        algorithm: Some(algorithm),
+    }
+}
+
+/// Backdates the mtime so the digest is old enough to be cached.
+fn backdate(path: &std::path::Path) {
+    let file = fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() - Duration::from_secs(60)).unwrap();
+}
+
+#[tokio::test]
+async fn test_hash_file_and_cache() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = temp_dir.path().join("data.txt");
+    fs::write(&file, "hello world").unwrap();
+    backdate(&file);
+    let cache = Arc::new(HashCache::new());
+
+    let sha = hash::hash(hash_request(&file, HashAlgorithm::Sha256), cache.clone(), CancellationToken::new()).await.unwrap();
+    assert_eq!(sha.digest, "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9");
+    assert!(!sha.is_directory);
+    assert_eq!((sha.files, sha.cached), (1, 0));
+
+    let again = hash::hash(hash_request(&file, HashAlgorithm::Sha256), cache.clone(), CancellationToken::new()).await.unwrap();
+    assert_eq!(again.digest, sha.digest);
+    assert_eq!(again.cached, 1);
+
+    let fast = hash::hash(hash_request(&file, HashAlgorithm::Xxh3), cache.clone(), CancellationToken::new()).await.unwrap();
+    assert_eq!(fast.digest.len(), 32);
+    assert_eq!(fast.cached, 0);
+
+    // A changed file is hashed again even though the path is cached
+    fs::write(&file, "hello there").unwrap();
+    let changed = hash::hash(hash_request(&file, HashAlgorithm::Sha256), cache.clone(), CancellationToken::new()).await.unwrap();
+    assert_ne!(changed.digest, sha.digest);
+    assert_eq!(changed.cached, 0);
+}
+
+#[tokio::test]
+async fn test_hash_directory_tree() {
+    let temp_dir = TempDir::new().unwrap();
+    for tree in ["a", "b"] {
+        let root = temp_dir.path().join(tree);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("README.md"), "readme").unwrap();
+    }
+    let cache = Arc::new(HashCache::new());
+    let digest = |tree: &str| {
+        let request = hash_request(&temp_dir.path().join(tree), HashAlgorithm::Sha256);
+        hash::hash(request, cache.clone(), CancellationToken::new())
+    };
+
+    let a = digest("a").await.unwrap();
+    assert!(a.is_directory);
+    assert_eq!(a.files, 2);
+    assert_eq!(digest("b").await.unwrap().digest, a.digest);
+
+    // Renaming a file changes the digest even though the contents are the same
+    fs::rename(temp_dir.path().join("b/README.md"), temp_dir.path().join("b/README.txt")).unwrap();
+    assert_ne!(digest("b").await.unwrap().digest, a.digest);
+    fs::rename(temp_dir.path().join("b/README.txt"), temp_dir.path().join("b/README.md")).unwrap();
+
+    fs::write(temp_dir.path().join("b/src/main.rs"), "fn main() { }").unwrap();
+    assert_ne!(digest("b").await.unwrap().digest, a.digest);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_hash_directory_with_fifo() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("data.txt"), "data").unwrap();
+    let fifo = temp_dir.path().join("pipe");
+    let fifo_path = std::ffi::CString::new(fifo.to_string_lossy().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo_path.as_ptr(), 0o644) }, 0);
+    let cache = Arc::new(HashCache::new());
+
+    // The FIFO is recorded without being opened, which would block
+    let tree = hash::hash(hash_request(temp_dir.path(), HashAlgorithm::Sha256), cache.clone(), CancellationToken::new()).await.unwrap();
+    assert_eq!(tree.files, 1);
+    fs::remove_file(&fifo).unwrap();
+    let without = hash::hash(hash_request(temp_dir.path(), HashAlgorithm::Sha256), cache.clone(), CancellationToken::new()).await.unwrap();
+    assert_ne!(tree.digest, without.digest);
+
+    assert_eq!(unsafe { libc::mkfifo(fifo_path.as_ptr(), 0o644) }, 0);
+    let direct = hash::hash(hash_request(&fifo, HashAlgorithm::Sha256), cache.clone(), CancellationToken::new()).await;
+    assert_eq!(direct.unwrap_err().code(), "EINVAL");
+}
+
+#[tokio::test]
+async fn test_hash_cancelled() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("data.txt"), "data").unwrap();
+    let cancellation = CancellationToken::new();
+    cancellation.cancel();
+
+    let response = hash::hash(hash_request(temp_dir.path(), HashAlgorithm::Sha256), Arc::new(HashCache::new()), cancellation)
+        .await
+        .unwrap();
+    assert!(response.cancelled);
+    assert!(response.digest.is_empty());
+}
@@ -3,56 +3,130 @@
  *  Licensed under the MIT License. See License.txt in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
 
-use std::io::Write;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::{Arc, Mutex, OnceLock};
 use tempfile::TempDir;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
+use tokio::sync::mpsc;
+
+/// Builds the `fileio` binary once and returns its path. These tests run in
+/// the library's test binary, which cargo builds no binaries for.
+fn fileio_binary() -> &'static Path {
+    static BINARY: OnceLock<PathBuf> = OnceLock::new();
+    BINARY.get_or_init(|| {
+        let output = std::process::Command::new(env!("CARGO"))
+            .args(["build", "--bin", "fileio", "--message-format=json"])
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .stderr(Stdio::inherit())
+            .output()
+            .expect("Failed to run cargo build");
+        assert!(output.status.success(), "Failed to build the fileio binary");
+
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter(|message| message["target"]["name"] == "fileio")
+            .find_map(|message| message["executable"].as_str().map(PathBuf::from))
+            .expect("cargo did not report the fileio executable")
+    })
+}
 
-use crate::services::fileio::types::{FileIORequest, FileIOResponse, ReadFileRequest, WriteFileRequest, AtomicWriteOptions};
+type Responses = Arc<Mutex<HashMap<u64, mpsc::UnboundedSender<serde_json::Value>>>>;
 
+/// A running `fileio` process. Clones share the process, so requests can be
+/// sent from several tasks at once.
 #[derive(Clone)]
 struct ChildProcess {
+    connection: Arc<Connection>,
+}
+
+struct Connection {
     child: tokio::process::Child,
+    stdin: tokio::sync::Mutex<tokio::process::ChildStdin>,
+    /// Where the responses to each request id go.
+    responses: Responses,
+    receivers: Mutex<HashMap<u64, mpsc::UnboundedReceiver<serde_json::Value>>>,
 }
 
 impl ChildProcess {
     async fn new() -> Self {
-        let child = Command::new("./target/debug/fileio")
+        let mut child = Command::new(fileio_binary())
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
+            .stderr(Stdio::null())
             .spawn()
             .expect("Failed to spawn fileio process");
 
-        Self { child }
-    }
+        let stdin = child.stdin.take().expect("Failed to get stdin");
+        let stdout = child.stdout.take().expect("Failed to get stdout");
+        let responses = Responses::default();
+        tokio::spawn(Self::read_responses(BufReader::new(stdout), responses.clone()));
 
-    fn stdin(&mut self) -> &mut tokio::process::ChildStdin {
-        self.child.stdin.as_mut().expect("Failed to get stdin")
+        let connection = Connection { child, stdin: tokio::sync::Mutex::new(stdin), responses, receivers: Default::default() };
+        Self { connection: Arc::new(connection) }
     }
 
-    fn stdout(&mut self) -> BufReader<&mut tokio::process::ChildStdout> {
-        let stdout = self.child.stdout.as_mut().expect("Failed to get stdout");
-        BufReader::new(stdout)
+    /// Hands each FileIO response to the request it answers, as `result` or
+    /// `error` like a JSON-RPC reply. The JSON-RPC replies themselves only
+    /// matter when the request never reached the service.
+    async fn read_responses(mut stdout: BufReader<tokio::process::ChildStdout>, responses: Responses) {
+        let mut line = String::new();
+        while stdout.read_line(&mut line).await.unwrap_or(0) > 0 {
+            let message: serde_json::Value = serde_json::from_str(line.trim()).expect("Invalid JSON from fileio");
+            line.clear();
+
+            let (id, response) = if message["method"] == "onFileIOResponse" {
+                let params = &message["params"];
+                let (operation, response) = params["response"].as_object().and_then(|r| r.iter().next()).expect("Empty response");
+                let key = if operation == "Error" { "error" } else { "result" };
+                (params["id"].as_u64(), serde_json::json!({ key: response }))
+            } else if message["error"].is_object() {
+                (message["id"].as_u64(), serde_json::json!({ "error": message["error"] }))
+            } else {
+                continue;
+            };
+            if let Some(sender) = id.and_then(|id| responses.lock().unwrap().get(&id).cloned()) {
+                let _ = sender.send(response);
+            }
+        }
+        // Wakes requests still waiting, which fail instead of hanging
+        responses.lock().unwrap().clear();
     }
 
     async fn send_request(&mut self, request: &serde_json::Value) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
-        let request_str = serde_json::to_string(request)? + "\n";
-        self.stdin().write_all(request_str.as_bytes()).await?;
-        self.stdin().flush().await?;
-
-        let mut response_line = String::new();
-        self.stdout().read_line(&mut response_line).await?;
-        let response: serde_json::Value = serde_json::from_str(&response_line.trim())?;
-        Ok(response)
+        // The FileIO request carries the call's id, which its responses echo
+        let id = request["id"].as_u64().ok_or("Request without an id")?;
+        let mut request = request.clone();
+        request["params"]["id"] = id.into();
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.connection.responses.lock().unwrap().insert(id, sender);
+        self.connection.receivers.lock().unwrap().insert(id, receiver);
+
+        let request_str = serde_json::to_string(&request)? + "\n";
+        let mut stdin = self.connection.stdin.lock().await;
+        stdin.write_all(request_str.as_bytes()).await?;
+        stdin.flush().await?;
+        drop(stdin);
+
+        self.next_response(id).await
+    }
+
+    /// The next response to request `id`, for requests answered with several.
+    async fn next_response(&mut self, id: u64) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let mut receiver = self.connection.receivers.lock().unwrap().remove(&id).ok_or("No such request")?;
+        let response = receiver.recv().await.ok_or("fileio exited");
+        self.connection.receivers.lock().unwrap().insert(id, receiver);
+        Ok(response?)
     }
 }
 
-impl Drop for ChildProcess {
+impl Drop for Connection {
     fn drop(&mut self) {
-        let _ = self.child.kill();
-        let _ = self.child.wait();
+        let _ = self.child.start_kill();
     }
 }
 
@@ -175,7 +249,7 @@ async fn test_error_propagation() {
 async fn test_concurrent_requests() {
     let temp_dir = TempDir::new().unwrap();
 
-    let mut process = ChildProcess::new().await;
+    let process = ChildProcess::new().await;
 
     let mut handles = vec![];
 
@@ -254,18 +328,16 @@ async fn test_streaming_read() {
 
     let mut chunks = vec![];
 
-    loop {
-        let response = process.send_request(&request).await.unwrap();
-        if let Some(result) = response["result"].as_object() {
-            if let Some(chunk) = result["chunk"].as_array() {
-                chunks.extend(chunk.iter().map(|b| b.as_u64().unwrap() as u8));
-            }
-            if result["done"].as_bool().unwrap_or(false) {
-                break;
-            }
-        } else {
+    // The stream answers the one request with a response per chunk
+    let mut response = process.send_request(&request).await.unwrap();
+    while let Some(result) = response["result"].as_object() {
+        if let Some(chunk) = result["chunk"].as_array() {
+            chunks.extend(chunk.iter().map(|b| b.as_u64().unwrap() as u8));
+        }
+        if result["done"].as_bool().unwrap_or(false) {
             break;
         }
+        response = process.next_response(1).await.unwrap();
     }
 
     let received_content = String::from_utf8(chunks).unwrap();
@@ -324,6 +396,7 @@ async fn test_handle_lifecycle() {
             "method": "ReadFileHandle",
             "params": {
                 "handle": handle,
+                "position": 0,
                 "length": content.len() as u32
             }
         }
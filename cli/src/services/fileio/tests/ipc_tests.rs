@@ -4,11 +4,7 @@
  *--------------------------------------------------------------------------------------------*/
 
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tempfile::TempDir;
-use tokio::process::Command;
-use std::io::{BufRead, BufReader, Write};
-use std::process::Stdio;
 
 use crate::services::fileio::types::{FileIORequest, FileIOResponse, ReadFileRequest};
 
@@ -78,7 +74,7 @@ async fn test_ipc_concurrent_requests() {
     }
 
     let ipc_sink = Arc::new(|_| {});
-    let service = Arc::new(Mutex::new(crate::services::fileio::service::FileIOService::new(ipc_sink)));
+    let service = Arc::new(crate::services::fileio::service::FileIOService::new(ipc_sink));
 
     let mut handles = vec![];
 
@@ -94,7 +90,6 @@ async fn test_ipc_concurrent_requests() {
                 encoding: Some("utf8".to_string()),
             });
 
-            let service = service.lock().await;
             let response = service.process_request(request).await.unwrap();
 
             match response {
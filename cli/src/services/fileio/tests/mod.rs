@@ -3,10 +3,27 @@
  *  Licensed under the MIT License. See License.txt in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
 
+#[cfg(unix)]
 pub mod operations_tests;
 pub mod locks_tests;
 pub mod integration_tests;
 pub mod platform_tests;
+pub mod errors_tests;
+pub mod handles_tests;
+pub mod encoding_tests;
+#[cfg(unix)]
+pub mod sandbox_tests;
+#[cfg(unix)]
+pub mod walk_tests;
+pub mod hash_tests;
+pub mod trash_tests;
+pub mod usage_tests;
+pub mod batch_tests;
+pub mod backend_tests;
+pub mod archive_tests;
+pub mod unit_tests;
+pub mod concurrency_tests;
+pub mod ipc_tests;
 
 use std::path::Path;
 use tempfile::NamedTempFile;
@@ -22,4 +39,4 @@ pub fn create_temp_file(content: &[u8]) -> NamedTempFile {
 pub async fn assert_file_eq(path: &Path, expected: &[u8]) {
     let actual = fs::read(path).await.expect("Failed to read file");
     assert_eq!(actual, expected);
-}
\ No newline at end of file
+}
@@ -0,0 +1,64 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::fs;
+use std::os::unix::fs as unix_fs;
+use tempfile::TempDir;
+use crate::services::fileio::sandbox::{AccessMode, PathAccess, Sandbox, SandboxRoot};
+use crate::services::fileio::FileIOError;
+
+fn sandbox(temp_dir: &TempDir) -> Sandbox {
+    fs::create_dir_all(temp_dir.path().join("workspace/vendor")).unwrap();
+    Sandbox::new(vec![
+        SandboxRoot { path: temp_dir.path().join("workspace"), mode: AccessMode::ReadWrite },
+        SandboxRoot { path: temp_dir.path().join("workspace/vendor"), mode: AccessMode::ReadOnly },
+    ])
+    .unwrap()
+}
+
+fn is_denied(result: Result<std::path::PathBuf, FileIOError>) -> bool {
+    matches!(&result, Err(e @ FileIOError::AccessDenied(_)) if e.code() == "EACCES")
+}
+
+#[test]
+fn test_sandbox_roots_and_modes() {
+    let temp_dir = TempDir::new().unwrap();
+    let sandbox = sandbox(&temp_dir);
+    let workspace = temp_dir.path().join("workspace");
+
+    // New files inside a read-write root are allowed
+    let new_file = workspace.join("src/new.rs");
+    let resolved = sandbox.check(&new_file.to_string_lossy(), PathAccess::Write).unwrap();
+    assert!(resolved.ends_with("workspace/src/new.rs"));
+
+    // The nested read-only root wins over its read-write parent
+    let vendored = workspace.join("vendor/lib.rs").to_string_lossy().to_string();
+    assert!(sandbox.check(&vendored, PathAccess::Read).is_ok());
+    assert!(is_denied(sandbox.check(&vendored, PathAccess::Write)));
+
+    let escaped = format!("{}/../secret.txt", workspace.to_string_lossy());
+    assert!(is_denied(sandbox.check(&escaped, PathAccess::Read)));
+    assert!(is_denied(sandbox.check("relative/path.txt", PathAccess::Read)));
+}
+
+#[test]
+fn test_sandbox_rejects_escaping_symlinks() {
+    let temp_dir = TempDir::new().unwrap();
+    let sandbox = sandbox(&temp_dir);
+    let workspace = temp_dir.path().join("workspace");
+    fs::write(temp_dir.path().join("secret.txt"), "secret").unwrap();
+
+    unix_fs::symlink(temp_dir.path().join("secret.txt"), workspace.join("link")).unwrap();
+    assert!(is_denied(sandbox.check(&workspace.join("link").to_string_lossy(), PathAccess::Read)));
+
+    // Writing through a dangling link would create its target outside the root
+    unix_fs::symlink(temp_dir.path().join("created.txt"), workspace.join("dangling")).unwrap();
+    assert!(is_denied(sandbox.check(&workspace.join("dangling").to_string_lossy(), PathAccess::Write)));
+
+    // Links that stay inside the root are fine
+    fs::write(workspace.join("real.txt"), "ok").unwrap();
+    unix_fs::symlink("real.txt", workspace.join("inner")).unwrap();
+    assert!(sandbox.check(&workspace.join("inner").to_string_lossy(), PathAccess::Write).is_ok());
+}
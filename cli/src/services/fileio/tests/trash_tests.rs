@@ -0,0 +1,122 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::fs;
+use tempfile::TempDir;
+use std::sync::Arc;
+use crate::services::fileio::service::{FileIOService, FileIOServiceConfig};
+use crate::services::fileio::trash::Trash;
+use crate::services::fileio::types::{DeleteRequest, FileIORequest, FileIOResponse};
+use crate::services::fileio::FileIOError;
+
+#[tokio::test]
+async fn test_trash_and_restore() {
+    let temp_dir = TempDir::new().unwrap();
+    let trash = Trash::new(Some(temp_dir.path().join("Trash")));
+    let workspace = temp_dir.path().join("my workspace");
+    fs::create_dir_all(workspace.join("src")).unwrap();
+    let file = workspace.join("src/main.rs");
+
+    fs::write(&file, "first").unwrap();
+    let first = trash.trash(&file.to_string_lossy()).await.unwrap();
+    fs::write(&file, "second").unwrap();
+    let second = trash.trash(&file.to_string_lossy()).await.unwrap();
+    assert!(!file.exists());
+    assert!(first.id.ends_with("files/main.rs"));
+    assert!(second.id.ends_with("files/main.rs.2"));
+
+    // The original path is recorded percent-encoded
+    let info = fs::read_to_string(temp_dir.path().join("Trash/info/main.rs.trashinfo")).unwrap();
+    assert!(info.starts_with("[Trash Info]\nPath="));
+    assert!(info.contains("my%20workspace/src/main.rs\nDeletionDate="));
+
+    let items = trash.list().await.unwrap();
+    let ours: Vec<_> = items.iter().filter(|item| item.id == first.id || item.id == second.id).collect();
+    assert_eq!(ours.len(), 2);
+    assert!(ours.iter().all(|item| item.original_path == file.to_string_lossy() && item.deletion_date.is_some()));
+
+    let restored = trash.restore(&second.id, false, None).await.unwrap();
+    assert_eq!(restored.original_path, file.to_string_lossy());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "second");
+
+    // Restoring over an existing file needs overwrite
+    let conflict = trash.restore(&first.id, false, None).await;
+    assert!(matches!(conflict, Err(FileIOError::AlreadyExists(_))));
+    trash.restore(&first.id, true, None).await.unwrap();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "first");
+    assert!(!temp_dir.path().join("Trash/info/main.rs.trashinfo").exists());
+}
+
+#[tokio::test]
+async fn test_empty_trash() {
+    let temp_dir = TempDir::new().unwrap();
+    let trash = Trash::new(Some(temp_dir.path().join("Trash")));
+    let dir = temp_dir.path().join("build");
+    fs::create_dir_all(dir.join("out")).unwrap();
+    fs::write(dir.join("out/app"), "binary").unwrap();
+
+    let item = trash.trash(&dir.to_string_lossy()).await.unwrap();
+    assert!(item.is_directory);
+
+    // Paths outside the trash cannot be used as ids
+    let outside = trash.empty(Some(vec![temp_dir.path().to_string_lossy().to_string()])).await;
+    assert!(matches!(outside, Err(FileIOError::NotFound(_))));
+
+    assert_eq!(trash.empty(Some(vec![item.id.clone()])).await.unwrap(), 1);
+    assert!(fs::read_dir(temp_dir.path().join("Trash/files")).unwrap().next().is_none());
+    assert!(fs::read_dir(temp_dir.path().join("Trash/info")).unwrap().next().is_none());
+}
+
+#[tokio::test]
+async fn test_restore_checks_sandbox() {
+    use crate::services::fileio::sandbox::{AccessMode, Sandbox, SandboxRoot};
+
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    let trash = Trash::new(Some(root.join("Trash")));
+    let workspace = root.join("workspace");
+    fs::create_dir_all(&workspace).unwrap();
+    let file = workspace.join("notes.txt");
+    fs::write(&file, "notes").unwrap();
+    let item = trash.trash(&file.to_string_lossy()).await.unwrap();
+
+    // Anyone who can write the trash can point an item elsewhere
+    let info = root.join("Trash/info/notes.txt.trashinfo");
+    let edited = fs::read_to_string(&info).unwrap().replace("workspace/notes.txt", "elsewhere/notes.txt");
+    fs::write(&info, edited).unwrap();
+
+    let sandbox = Sandbox::new(vec![SandboxRoot { path: workspace.clone(), mode: AccessMode::ReadWrite }]).unwrap();
+    let denied = trash.restore(&item.id, false, Some(sandbox)).await;
+    assert!(matches!(denied, Err(FileIOError::AccessDenied(_))));
+    assert!(!root.join("elsewhere").exists());
+    assert!(fs::symlink_metadata(&item.id).is_ok());
+}
+
+#[tokio::test]
+async fn test_trash_non_empty_directory_needs_recursive() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = FileIOServiceConfig { trash_dir: Some(temp_dir.path().join("Trash")), ..Default::default() };
+    let service = FileIOService::with_config(Arc::new(|_| {}), config);
+    let folder = temp_dir.path().join("folder");
+    fs::create_dir(&folder).unwrap();
+    fs::write(folder.join("file.txt"), "data").unwrap();
+    let delete = |recursive| {
+        FileIORequest::Delete(DeleteRequest {
+            path: folder.to_string_lossy().to_string(),
+            recursive: Some(recursive),
+            atomic: None,
+            use_trash: Some(true),
+            preconditions: Default::default(),
+        })
+    };
+
+    let err = service.process_request(delete(false)).await.unwrap_err();
+    assert_eq!(err.code(), "ENOTEMPTY");
+    assert!(folder.join("file.txt").exists());
+
+    let response = service.process_request(delete(true)).await.unwrap();
+    assert!(matches!(response, FileIOResponse::Trash(_)));
+    assert!(!folder.exists());
+}
@@ -4,12 +4,16 @@
  *--------------------------------------------------------------------------------------------*/
 
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tempfile::TempDir;
 
+use crate::rpc::RequestParams;
 use crate::services::fileio::{
-    service::FileIOService,
-    types::{FileIORequest, FileIOResponse, ReadFileRequest, WriteFileRequest, StatRequest},
+    handles::HandleTableConfig,
+    service::{FileIOService, FileIOServiceConfig},
+    types::{
+        FileIORequest, FileIORequestMessage, FileIOResponse, FileIOResponseMessage, ReadFileRequest, StatRequest,
+        WriteFileRequest, WriteFileStreamRequest,
+    },
 };
 
 #[tokio::test]
@@ -52,6 +56,7 @@ async fn test_write_file() {
         encoding: Some("utf8".to_string()),
         create_dirs: Some(false),
         atomic: None,
+        preconditions: Default::default(),
     });
 
     let response = service.process_request(request).await.unwrap();
@@ -147,7 +152,9 @@ async fn test_write_atomic() {
         create_dirs: Some(false),
         atomic: Some(crate::services::fileio::types::AtomicWriteOptions {
             postfix: ".tmp".to_string(),
+            preserve_xattrs: None,
         }),
+        preconditions: Default::default(),
     });
 
     let response = service.process_request(request).await.unwrap();
@@ -162,4 +169,111 @@ async fn test_write_atomic() {
         }
         _ => panic!("Expected WriteFile response"),
     }
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_responses_echo_request_id_and_operation() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = temp_dir.path().join("correlated.txt");
+
+    let messages = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink_messages = messages.clone();
+    let ipc_sink = Arc::new(move |message: String| sink_messages.lock().unwrap().push(message));
+    let service = FileIOService::new(ipc_sink);
+
+    let write: RequestParams<FileIORequestMessage> = serde_json::from_value(serde_json::json!({
+        "params": {
+            "id": 41,
+            "method": "WriteFile",
+            "params": { "path": test_file.to_string_lossy(), "content": "x" }
+        }
+    }))
+    .unwrap();
+    let stat: RequestParams<FileIORequestMessage> = serde_json::from_value(serde_json::json!({
+        "params": {
+            "id": 42,
+            "method": "Stat",
+            "params": { "path": temp_dir.path().join("missing").to_string_lossy() }
+        }
+    }))
+    .unwrap();
+
+    service.handle_request(&write).await.unwrap();
+    service.handle_request(&stat).await.unwrap();
+
+    let messages = messages.lock().unwrap();
+    let responses: Vec<FileIOResponseMessage> = messages
+        .iter()
+        .map(|m| serde_json::from_value(serde_json::from_str::<serde_json::Value>(m).unwrap()["params"].clone()).unwrap())
+        .collect();
+
+    assert_eq!(responses[0].id, Some(41));
+    assert_eq!(responses[0].operation, "WriteFile");
+    assert!(matches!(responses[0].response, FileIOResponse::WriteFile(())));
+
+    assert_eq!(responses[1].id, Some(42));
+    assert_eq!(responses[1].operation, "Stat");
+    assert!(matches!(responses[1].response, FileIOResponse::Error(_)));
+}
+
+#[tokio::test]
+async fn test_write_stream_chunks_stay_on_their_stream() {
+    let temp_dir = TempDir::new().unwrap();
+    let target = temp_dir.path().join("streamed.txt");
+    let other = temp_dir.path().join("other.txt");
+    let service = FileIOService::new(Arc::new(|_| {}));
+    let chunk = |stream_id: u32, path: &std::path::Path, sequence: u32, last: bool| {
+        FileIORequest::WriteFileStream(WriteFileStreamRequest {
+            stream_id,
+            path: path.to_string_lossy().to_string(),
+            sequence,
+            chunk: "data".to_string(),
+            encoding: None,
+            create_dirs: None,
+            last,
+        })
+    };
+
+    service.process_request(chunk(1, &target, 0, false)).await.unwrap();
+    // A second stream cannot take over the id
+    assert!(service.process_request(chunk(1, &other, 0, false)).await.is_err());
+    // Nor can a chunk redirect the stream to another file
+    assert!(service.process_request(chunk(1, &other, 1, true)).await.is_err());
+
+    assert!(!other.exists());
+    assert!(!target.exists());
+    // The rejected chunk discarded the stream and its temp file
+    assert!(service.process_request(chunk(1, &target, 1, true)).await.is_err());
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn test_idle_write_streams_are_discarded() {
+    let temp_dir = TempDir::new().unwrap();
+    let target = temp_dir.path().join("streamed.txt");
+    let config = FileIOServiceConfig {
+        handles: HandleTableConfig { max_open: 1024, idle_timeout: Some(std::time::Duration::from_millis(50)) },
+        ..Default::default()
+    };
+    let service = FileIOService::with_config(Arc::new(|_| {}), config);
+    let chunk = |stream_id: u32, sequence: u32, last: bool| {
+        FileIORequest::WriteFileStream(WriteFileStreamRequest {
+            stream_id,
+            path: target.to_string_lossy().to_string(),
+            sequence,
+            chunk: "data".to_string(),
+            encoding: None,
+            create_dirs: None,
+            last,
+        })
+    };
+
+    service.process_request(chunk(1, 0, false)).await.unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    service.process_request(chunk(2, 0, false)).await.unwrap();
+    service.process_request(chunk(2, 1, true)).await.unwrap();
+
+    // The first stream was reaped with its temp file, the second committed
+    assert!(service.process_request(chunk(1, 1, true)).await.is_err());
+    assert_eq!(std::fs::read_to_string(&target).unwrap(), "datadata");
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+}
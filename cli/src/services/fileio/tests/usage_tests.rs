@@ -0,0 +1,69 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
+use crate::services::fileio::types::{DirSizeRequest, StatFsRequest};
+use crate::services::fileio::usage::{self, is_remote_fs_type};
+
+#[tokio::test]
+async fn test_statfs_reports_capacity() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("Probe.txt"), "").unwrap();
+
+    let result = usage::statfs(StatFsRequest { path: temp_dir.path().join("Probe.txt").to_string_lossy().to_string() }).await;
+    if cfg!(not(any(target_os = "linux", target_os = "macos"))) {
+        assert!(result.is_err());
+        return;
+    }
+
+    let stat = result.unwrap();
+    assert!(stat.total_bytes > 0);
+    assert!(stat.free_bytes <= stat.total_bytes);
+    assert!(stat.available_bytes <= stat.free_bytes);
+    assert!(!stat.filesystem_type.is_empty());
+    assert!(temp_dir.path().canonicalize().unwrap().starts_with(&stat.mount_point));
+    if cfg!(target_os = "linux") {
+        assert!(stat.case_sensitive);
+    }
+
+    assert!(is_remote_fs_type("nfs4"));
+    assert!(is_remote_fs_type("fuse.sshfs"));
+    assert!(!is_remote_fs_type("ext4"));
+    assert!(!is_remote_fs_type("fuseblk"));
+    assert!(!is_remote_fs_type("fuse"));
+}
+
+#[tokio::test]
+async fn test_dir_size_counts_tree() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("a/one.bin"), vec![0u8; 1000]).unwrap();
+    fs::write(root.join("a/b/two.bin"), vec![0u8; 24]).unwrap();
+    // A second link to the same file is not counted twice
+    fs::hard_link(root.join("a/one.bin"), root.join("one-again.bin")).unwrap();
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let sink = progress.clone();
+    let request = DirSizeRequest { path: root.to_string_lossy().to_string() };
+    let result = usage::dir_size(request, CancellationToken::new(), move |p| sink.lock().unwrap().push(p))
+        .await
+        .unwrap();
+    assert_eq!(result.bytes, 1024);
+    assert_eq!(result.files, 2);
+    assert_eq!(result.directories, 3);
+    assert!(!result.cancelled);
+
+    let cancellation = CancellationToken::new();
+    cancellation.cancel();
+    let request = DirSizeRequest { path: root.to_string_lossy().to_string() };
+    assert!(usage::dir_size(request, cancellation, |_| {}).await.unwrap().cancelled);
+
+    let request = DirSizeRequest { path: root.join("a/one.bin").to_string_lossy().to_string() };
+    assert!(usage::dir_size(request, CancellationToken::new(), |_| {}).await.is_err());
+}
@@ -0,0 +1,143 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use std::fs;
+use std::os::unix::fs as unix_fs;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
+use crate::services::fileio::sandbox::{AccessMode, Sandbox, SandboxRoot};
+use crate::services::fileio::types::{WalkRequest, WalkResponse};
+use crate::services::fileio::walk;
+
+fn walk_request(path: &std::path::Path) -> WalkRequest {
+    WalkRequest {
+        path: path.to_string_lossy().to_string(),
+        max_depth: None,
+        includes: None,
+        excludes: None,
+        follow_symlinks: None,
+        use_gitignore: None,
+        batch_size: None,
+    }
+}
+
+async fn collect(request: WalkRequest) -> Vec<WalkResponse> {
+    collect_in(request, None).await
+}
+
+async fn collect_in(request: WalkRequest, sandbox: Option<Sandbox>) -> Vec<WalkResponse> {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let sink = batches.clone();
+    walk::walk(request, sandbox, CancellationToken::new(), move |batch| sink.lock().unwrap().push(batch))
+        .await
+        .unwrap();
+    Arc::try_unwrap(batches).unwrap().into_inner().unwrap()
+}
+
+fn relative_paths(root: &std::path::Path, batches: &[WalkResponse]) -> Vec<String> {
+    let mut paths: Vec<String> = batches
+        .iter()
+        .flat_map(|batch| &batch.entries)
+        .map(|entry| std::path::Path::new(&entry.path).strip_prefix(root).unwrap().to_string_lossy().to_string())
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[tokio::test]
+async fn test_walk_filters_and_batches() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("src/nested")).unwrap();
+    fs::create_dir_all(root.join("node_modules/dep")).unwrap();
+    fs::write(root.join("README.md"), "readme").unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(root.join("src/nested/lib.rs"), "").unwrap();
+    fs::write(root.join("node_modules/dep/index.js"), "").unwrap();
+
+    let mut request = walk_request(root);
+    request.includes = Some(vec!["**/*.rs".to_string()]);
+    request.excludes = Some(vec!["node_modules".to_string()]);
+    request.batch_size = Some(1);
+    let batches = collect(request).await;
+
+    assert_eq!(relative_paths(root, &batches), vec!["src/main.rs", "src/nested/lib.rs"]);
+    assert!(batches.last().unwrap().done);
+    assert!(batches.iter().rev().skip(1).all(|batch| !batch.done && batch.entries.len() == 1));
+
+    let main = batches.iter().flat_map(|b| &b.entries).find(|e| e.name == "main.rs").unwrap();
+    assert!(main.is_file);
+    assert_eq!(main.size, Some(12));
+    assert!(main.mtime.is_some());
+
+    let mut request = walk_request(root);
+    request.max_depth = Some(1);
+    let batches = collect(request).await;
+    assert_eq!(relative_paths(root, &batches), vec!["README.md", "node_modules", "src"]);
+
+    let mut request = walk_request(root);
+    request.max_depth = Some(0);
+    let batches = collect(request).await;
+    assert_eq!(relative_paths(root, &batches), vec![""]);
+    assert!(batches[0].entries[0].is_directory);
+}
+
+#[tokio::test]
+async fn test_walk_gitignore_and_symlinks() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join(".git")).unwrap();
+    fs::create_dir_all(root.join("target/debug")).unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(root.join(".gitignore"), "# build output\n/target/\n*.log\n!keep.log\n").unwrap();
+    fs::write(root.join("src/.gitignore"), "generated.rs\n").unwrap();
+    fs::write(root.join(".git/HEAD"), "").unwrap();
+    fs::write(root.join("target/debug/app"), "").unwrap();
+    fs::write(root.join("src/lib.rs"), "").unwrap();
+    fs::write(root.join("src/generated.rs"), "").unwrap();
+    fs::write(root.join("debug.log"), "").unwrap();
+    fs::write(root.join("keep.log"), "").unwrap();
+    // A link back to the root would loop forever if followed blindly
+    unix_fs::symlink(root, root.join("src/loop")).unwrap();
+
+    let mut request = walk_request(root);
+    request.use_gitignore = Some(true);
+    let batches = collect(request).await;
+    assert_eq!(
+        relative_paths(root, &batches),
+        vec![".gitignore", "keep.log", "src", "src/.gitignore", "src/lib.rs", "src/loop"]
+    );
+    let link = batches.iter().flat_map(|b| &b.entries).find(|e| e.name == "loop").unwrap();
+    assert!(link.is_symlink && !link.is_directory);
+
+    let mut request = walk_request(root);
+    request.use_gitignore = Some(true);
+    request.follow_symlinks = Some(true);
+    let batches = collect(request).await;
+    let link = batches.iter().flat_map(|b| &b.entries).find(|e| e.name == "loop").unwrap();
+    assert!(link.is_symlink && link.is_directory);
+    // The link is reported but the root is not walked a second time through it
+    assert!(!relative_paths(root, &batches).iter().any(|path| path.starts_with("src/loop/")));
+}
+
+#[tokio::test]
+async fn test_walk_hides_links_outside_sandbox() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap().join("workspace");
+    let outside = temp_dir.path().canonicalize().unwrap().join("outside");
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    fs::write(outside.join("secret.txt"), "").unwrap();
+    unix_fs::symlink(&outside, root.join("escape")).unwrap();
+    unix_fs::symlink(outside.join("missing.txt"), root.join("dangling")).unwrap();
+    unix_fs::symlink(root.join("src"), root.join("inside")).unwrap();
+    let sandbox = Sandbox::new(vec![SandboxRoot { path: root.clone(), mode: AccessMode::ReadOnly }]).unwrap();
+
+    let mut request = walk_request(&root);
+    request.follow_symlinks = Some(true);
+    let batches = collect_in(request, Some(sandbox)).await;
+    assert_eq!(relative_paths(&root, &batches), vec!["inside", "src"]);
+}
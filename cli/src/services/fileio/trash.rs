@@ -0,0 +1,555 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! Trash per the freedesktop.org Trash specification: a home trash under
+//! `$XDG_DATA_HOME/Trash`, and per-mount `$topdir/.Trash/$uid` or
+//! `$topdir/.Trash-$uid` directories for files on other filesystems. Each
+//! trashed entry lives in `files/` next to a `.trashinfo` file in `info/`
+//! recording where it came from. Files on a mount whose trash cannot be
+//! created go to the home trash instead, copied across filesystems.
+
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::platform;
+use crate::services::fileio::sandbox::{PathAccess, Sandbox};
+use crate::services::fileio::types::TrashItem;
+use crate::services::paths;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const TRASHINFO_EXTENSION: &str = ".trashinfo";
+const DELETION_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// A trash directory and, for per-mount trashes, the top directory of the mount
+/// that relative `Path=` entries are resolved against.
+#[derive(Debug, Clone, PartialEq)]
+struct TrashDir {
+    path: PathBuf,
+    topdir: Option<PathBuf>,
+}
+
+impl TrashDir {
+    fn files(&self) -> PathBuf {
+        self.path.join("files")
+    }
+
+    fn info(&self) -> PathBuf {
+        self.path.join("info")
+    }
+
+    fn create(&self) -> FileIOResult<()> {
+        for dir in [self.path.clone(), self.files(), self.info()] {
+            match std::fs::create_dir(&dir) {
+                Ok(()) => set_private(&dir)?,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Trash {
+    home: Option<PathBuf>,
+}
+
+impl Default for Trash {
+    /// Uses `$XDG_DATA_HOME/Trash`, falling back to `~/.local/share/Trash`.
+    fn default() -> Self {
+        Trash::new(dirs_next::data_dir().map(|data| data.join("Trash")))
+    }
+}
+
+impl Trash {
+    pub fn new(home: Option<PathBuf>) -> Self {
+        Trash { home }
+    }
+
+    /// Moves `path` into the trash of the filesystem it lives on.
+    pub async fn trash(&self, path: &str) -> FileIOResult<TrashItem> {
+        let trash = self.clone();
+        let path = paths::to_path_buf(path)?;
+        run_blocking(move || trash.trash_blocking(&path)).await
+    }
+
+    /// Items in the home trash and in the trash of every mounted filesystem.
+    pub async fn list(&self) -> FileIOResult<Vec<TrashItem>> {
+        let trash = self.clone();
+        run_blocking(move || {
+            let mut items = Vec::new();
+            for dir in trash.trash_dirs() {
+                items.extend(list_dir(&dir));
+            }
+            items.sort_by_key(|item| std::cmp::Reverse(item.deletion_date));
+            Ok(items)
+        })
+        .await
+    }
+
+    /// Looks up a trashed item by the id reported in `list`.
+    pub async fn item(&self, id: &str) -> FileIOResult<TrashItem> {
+        let trash = self.clone();
+        let id = PathBuf::from(id);
+        run_blocking(move || {
+            let (dir, name) = trash.locate(&id)?;
+            read_item(&dir, &name)
+        })
+        .await
+    }
+
+    /// Moves a trashed item back to its original path, recreating missing
+    /// parent directories. The path comes from a `.trashinfo` file anyone
+    /// with access to the trash can edit, so with a `sandbox` it is checked
+    /// here, right before it is written.
+    pub async fn restore(&self, id: &str, overwrite: bool, sandbox: Option<Sandbox>) -> FileIOResult<TrashItem> {
+        let trash = self.clone();
+        let id = PathBuf::from(id);
+        run_blocking(move || {
+            let (dir, name) = trash.locate(&id)?;
+            let item = read_item(&dir, &name)?;
+            let original = match &sandbox {
+                Some(sandbox) => sandbox.check(&item.original_path, PathAccess::Write)?,
+                None => PathBuf::from(&item.original_path),
+            };
+
+            if std::fs::symlink_metadata(&original).is_ok() {
+                if !overwrite {
+                    return Err(FileIOError::AlreadyExists(format!("{} already exists", original.display())));
+                }
+                remove_entry(&original)?;
+            }
+            if let Some(parent) = original.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            move_entry(&id, &original)?;
+            remove_if_exists(&info_path(&dir, &name))?;
+            Ok(item)
+        })
+        .await
+    }
+
+    /// Permanently deletes `ids`, or everything in every trash when `None`,
+    /// returning how many items were removed.
+    pub async fn empty(&self, ids: Option<Vec<String>>) -> FileIOResult<u64> {
+        let trash = self.clone();
+        run_blocking(move || {
+            let targets = match ids {
+                Some(ids) => ids
+                    .iter()
+                    .map(|id| trash.locate(Path::new(id)))
+                    .collect::<FileIOResult<Vec<_>>>()?,
+                None => trash
+                    .trash_dirs()
+                    .into_iter()
+                    .flat_map(|dir| list_names(&dir).into_iter().map(move |name| (dir.clone(), name)))
+                    .collect(),
+            };
+
+            let mut removed = 0;
+            for (dir, name) in targets {
+                remove_entry(&dir.files().join(&name))?;
+                remove_if_exists(&info_path(&dir, &name))?;
+                removed += 1;
+            }
+            Ok(removed)
+        })
+        .await
+    }
+
+    fn trash_blocking(&self, path: &Path) -> FileIOResult<TrashItem> {
+        // Resolve the parent but not the entry itself, so a symlink is
+        // trashed rather than its target
+        let name = path
+            .file_name()
+            .ok_or_else(|| FileIOError::InvalidArgument(format!("Cannot trash {}", path.display())))?;
+        let parent = std::fs::canonicalize(path.parent().unwrap_or(Path::new("/")))?;
+        let path = parent.join(name);
+        let metadata = std::fs::symlink_metadata(&path)?;
+
+        let dir = match self.trash_dir_for(&path, device_id(&metadata)).and_then(|dir| dir.create().map(|()| dir)) {
+            Ok(dir) => dir,
+            // The spec allows the home trash for mounts whose trash cannot be used
+            Err(e) => {
+                let home = self.home.clone().ok_or_else(|| {
+                    FileIOError::Unsupported(format!("No usable trash directory for {}: {}", path.display(), e))
+                })?;
+                let dir = TrashDir { path: home, topdir: None };
+                dir.create()?;
+                dir
+            }
+        };
+        if path.starts_with(&dir.path) {
+            return Err(FileIOError::InvalidArgument(format!("{} is already in the trash", path.display())));
+        }
+
+        let recorded_path = match &dir.topdir {
+            Some(topdir) => path.strip_prefix(topdir).unwrap_or(&path),
+            None => &path,
+        };
+        let deletion_date = Local::now();
+        let (trashed_name, info) = reserve_info(&dir, name)?;
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            escape_path(recorded_path),
+            deletion_date.format(DELETION_DATE_FORMAT)
+        );
+
+        let trashed = dir.files().join(&trashed_name);
+        let moved = std::fs::write(&info, contents).map_err(FileIOError::from).and_then(|_| move_entry(&path, &trashed));
+        if let Err(e) = moved {
+            let _ = std::fs::remove_file(&info);
+            return Err(e);
+        }
+
+        Ok(TrashItem {
+            id: trashed.to_string_lossy().to_string(),
+            original_path: path.to_string_lossy().to_string(),
+            deletion_date: Some(deletion_date.timestamp_millis()),
+            is_directory: metadata.is_dir(),
+        })
+    }
+
+    /// The home trash if it is on the same filesystem as the path, and
+    /// otherwise the trash at the top of the path's mount.
+    fn trash_dir_for(&self, path: &Path, device: u64) -> FileIOResult<TrashDir> {
+        if let Some(home) = &self.home {
+            // The home trash may not exist yet, so compare its closest existing ancestor
+            let home_device = home.ancestors().find_map(|dir| std::fs::metadata(dir).ok()).map(|m| device_id(&m));
+            if home_device == Some(device) {
+                return Ok(TrashDir { path: home.clone(), topdir: None });
+            }
+        }
+
+        let mut topdir = path;
+        while let Some(parent) = topdir.parent() {
+            match std::fs::metadata(parent) {
+                Ok(metadata) if device_id(&metadata) == device => topdir = parent,
+                _ => break,
+            }
+        }
+        if topdir == path {
+            return Err(FileIOError::Unsupported(format!("No trash directory for {}", path.display())));
+        }
+
+        let uid = current_uid();
+        let shared = topdir.join(".Trash");
+        let path = if is_shared_trash(&shared) {
+            shared.join(uid.to_string())
+        } else {
+            topdir.join(format!(".Trash-{}", uid))
+        };
+        Ok(TrashDir { path, topdir: Some(topdir.to_path_buf()) })
+    }
+
+    fn trash_dirs(&self) -> Vec<TrashDir> {
+        let mut dirs: Vec<TrashDir> = self.home.iter().map(|home| TrashDir { path: home.clone(), topdir: None }).collect();
+
+        let uid = current_uid();
+        for topdir in platform::mounts().into_iter().map(|mount| mount.mount_point) {
+            let candidates = [topdir.join(".Trash").join(uid.to_string()), topdir.join(format!(".Trash-{}", uid))];
+            for path in candidates {
+                let is_dir = std::fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+                if is_dir && !dirs.iter().any(|dir| dir.path == path) {
+                    dirs.push(TrashDir { path, topdir: Some(topdir.clone()) });
+                }
+            }
+        }
+        dirs.retain(|dir| dir.files().is_dir());
+        dirs
+    }
+
+    /// Maps an item id back to its trash directory, rejecting paths that are
+    /// not directly inside the `files/` directory of a known trash.
+    fn locate(&self, id: &Path) -> FileIOResult<(TrashDir, OsString)> {
+        let not_in_trash = || FileIOError::NotFound(format!("{} is not in the trash", id.display()));
+        let name = id.file_name().ok_or_else(not_in_trash)?;
+        let dir = self
+            .trash_dirs()
+            .into_iter()
+            .find(|dir| id.parent() == Some(dir.files().as_path()))
+            .ok_or_else(not_in_trash)?;
+
+        if std::fs::symlink_metadata(id).is_err() {
+            return Err(not_in_trash());
+        }
+        Ok((dir, name.to_os_string()))
+    }
+}
+
+async fn run_blocking<T, F>(f: F) -> FileIOResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> FileIOResult<T> + Send + 'static,
+{
+    if !cfg!(all(unix, not(target_os = "macos"))) {
+        return Err(FileIOError::Unsupported("The freedesktop trash is not available on this platform".to_string()));
+    }
+    tokio::task::spawn_blocking(f).await.map_err(|e| FileIOError::Other(Box::new(e)))?
+}
+
+/// `$topdir/.Trash` may only be used if it is a real directory with the sticky
+/// bit set, so that users cannot remove each other's trash.
+fn is_shared_trash(path: &Path) -> bool {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.is_dir() && is_sticky(&metadata),
+        Err(_) => false,
+    }
+}
+
+/// Creates the `.trashinfo` file under a name that is free in both `info/`
+/// and `files/`, adding a counter to the name on collisions.
+fn reserve_info(dir: &TrashDir, name: &std::ffi::OsStr) -> FileIOResult<(OsString, PathBuf)> {
+    for attempt in 1u32.. {
+        let mut candidate = name.to_os_string();
+        if attempt > 1 {
+            candidate.push(format!(".{}", attempt));
+        }
+        if std::fs::symlink_metadata(dir.files().join(&candidate)).is_ok() {
+            continue;
+        }
+
+        let info = info_path(dir, &candidate);
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&info) {
+            Ok(mut file) => {
+                file.flush()?;
+                return Ok((candidate, info));
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    unreachable!("ran out of trash names")
+}
+
+fn info_path(dir: &TrashDir, name: &std::ffi::OsStr) -> PathBuf {
+    let mut info_name = name.to_os_string();
+    info_name.push(TRASHINFO_EXTENSION);
+    dir.info().join(info_name)
+}
+
+fn list_names(dir: &TrashDir) -> Vec<OsString> {
+    match std::fs::read_dir(dir.files()) {
+        Ok(entries) => entries.flatten().map(|entry| entry.file_name()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Items whose `.trashinfo` is missing or unreadable are left out, as the spec
+/// requires.
+fn list_dir(dir: &TrashDir) -> Vec<TrashItem> {
+    list_names(dir).into_iter().filter_map(|name| read_item(dir, &name).ok()).collect()
+}
+
+fn read_item(dir: &TrashDir, name: &std::ffi::OsStr) -> FileIOResult<TrashItem> {
+    let info = std::fs::read_to_string(info_path(dir, name))?;
+    let mut original = None;
+    let mut deletion_date = None;
+    let mut in_section = false;
+    for line in info.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_section = line == "[Trash Info]";
+        } else if in_section {
+            if let Some(value) = line.strip_prefix("Path=") {
+                original = Some(unescape_path(value));
+            } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+                deletion_date = NaiveDateTime::parse_from_str(value, DELETION_DATE_FORMAT)
+                    .ok()
+                    .and_then(|date| Local.from_local_datetime(&date).earliest())
+                    .map(|date| date.timestamp_millis());
+            }
+        }
+    }
+
+    let original = original.ok_or_else(|| FileIOError::InvalidArgument(format!("Invalid trash info for {:?}", name)))?;
+    let original = match &dir.topdir {
+        Some(topdir) if original.is_relative() => topdir.join(original),
+        _ => original,
+    };
+    let trashed = dir.files().join(name);
+
+    Ok(TrashItem {
+        is_directory: std::fs::symlink_metadata(&trashed).is_ok_and(|m| m.is_dir()),
+        id: trashed.to_string_lossy().to_string(),
+        original_path: original.to_string_lossy().to_string(),
+        deletion_date,
+    })
+}
+
+/// Renames `from` to `to`, or copies and then deletes it when they are on
+/// different filesystems. A failed copy leaves `from` as it was.
+fn move_entry(from: &Path, to: &Path) -> FileIOResult<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if is_cross_device(&e) => {}
+        result => return Ok(result?),
+    }
+
+    if let Err(e) = copy_entry(from, to) {
+        let _ = remove_entry(to);
+        return Err(e);
+    }
+    if let Err(e) = remove_entry(from) {
+        // Keep a single copy, where it was
+        let _ = remove_entry(to);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Copies a file, symlink or tree, keeping permissions and not following links.
+fn copy_entry(from: &Path, to: &Path) -> FileIOResult<()> {
+    let metadata = std::fs::symlink_metadata(from)?;
+    if metadata.file_type().is_symlink() {
+        symlink(&std::fs::read_link(from)?, to)?;
+    } else if metadata.is_dir() {
+        std::fs::create_dir(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_entry(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        std::fs::set_permissions(to, metadata.permissions())?;
+    } else {
+        std::fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+fn remove_entry(path: &Path) -> FileIOResult<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> FileIOResult<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Percent-encodes a path for `Path=`, keeping `/` and unreserved characters.
+fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for &byte in path_bytes(path).iter() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+fn unescape_path(escaped: &str) -> PathBuf {
+    let bytes = escaped.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (bytes[i], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    path_from_bytes(decoded)
+}
+
+#[cfg(unix)]
+fn device_id(metadata: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.dev()
+}
+
+#[cfg(not(unix))]
+fn device_id(_metadata: &std::fs::Metadata) -> u64 {
+    0
+}
+
+#[cfg(unix)]
+fn is_cross_device(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EXDEV)
+}
+
+#[cfg(not(unix))]
+fn is_cross_device(_e: &io::Error) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(unix)]
+fn is_sticky(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o1000 != 0
+}
+
+#[cfg(not(unix))]
+fn is_sticky(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// Trash directories are only readable by their owner.
+#[cfg(unix)]
+fn set_private(path: &Path) -> FileIOResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o700))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_private(_path: &Path) -> FileIOResult<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+fn current_uid() -> u32 {
+    0
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    std::borrow::Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    std::borrow::Cow::Owned(path.to_string_lossy().into_owned().into_bytes())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
@@ -3,6 +3,8 @@
  *  Licensed under the MIT License. See License.txt in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
 
+use crate::services::fileio::errors::FileSystemProviderErrorCode;
+use crate::services::fileio::sandbox::PathAccess;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,7 +15,19 @@ pub struct FileStat {
     pub is_directory: bool,
     pub is_file: bool,
     pub is_symlink: bool,
-    pub permissions: u32,
+    pub permissions: u32, // permission bits, e.g. 0o644
+    pub readonly: bool,
+    // The fields below are only reported on Unix
+    pub mode: Option<u32>, // full st_mode, including the file type bits
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub ino: Option<u64>,
+    pub dev: Option<u64>,
+    pub nlink: Option<u64>,
+    /// Target of a symlink as stored in the link, possibly relative.
+    pub symlink_target: Option<String>,
+    /// The symlink's target does not exist; the other fields describe the link.
+    pub dangling: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,23 +39,46 @@ pub struct DirEntry {
     pub is_symlink: bool,
     pub size: Option<u64>,
     pub mtime: Option<i64>,
+    pub symlink_target: Option<String>,
+    pub dangling: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadFileRequest {
     pub path: String,
-    pub encoding: Option<String>, // "utf8", "base64", etc.
+    pub encoding: Option<String>, // "utf8", "base64", "utf16le", "auto", etc.
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadFileResponse {
     pub content: String,
     pub stat: FileStat,
+    /// Encoding the content was decoded with, e.g. the one detected for "auto".
+    pub encoding: String,
+    /// Invalid byte sequences were replaced with U+FFFD.
+    pub lossy: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AtomicWriteOptions {
     pub postfix: String,
+    /// Carry the target's extended attributes over to the new contents.
+    pub preserve_xattrs: Option<bool>,
+}
+
+/// Conditions the target must still meet for a mutation to go ahead, so that
+/// a change made since the client last read the file is not clobbered.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilePreconditions {
+    pub expected_mtime: Option<i64>, // milliseconds since epoch
+    pub expected_size: Option<u64>,
+    pub expected_hash: Option<String>, // hex sha256 of the contents
+}
+
+impl FilePreconditions {
+    pub fn is_empty(&self) -> bool {
+        self.expected_mtime.is_none() && self.expected_size.is_none() && self.expected_hash.is_none()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +88,26 @@ pub struct WriteFileRequest {
     pub encoding: Option<String>,
     pub create_dirs: Option<bool>,
     pub atomic: Option<AtomicWriteOptions>,
+    #[serde(flatten, default)]
+    pub preconditions: FilePreconditions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteFileStreamRequest {
+    pub stream_id: u32,
+    pub path: String,
+    pub sequence: u32, // 0 opens the stream, then increments by one per chunk
+    pub chunk: String,
+    pub encoding: Option<String>,
+    pub create_dirs: Option<bool>,
+    pub last: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteFileStreamResponse {
+    pub stream_id: u32,
+    pub bytes_written: u64,
+    pub committed: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,10 +154,27 @@ pub struct WriteFileHandleResponse {
     pub bytes_written: u32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FsyncFileHandleRequest {
+    pub handle: u32,
+    pub data_only: Option<bool>, // fdatasync instead of fsync
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandleInfo {
+    pub handle: u32,
+    pub path: String,
+    pub writable: bool,
+    pub opened_at: i64, // milliseconds since epoch
+    pub idle_ms: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadFileStreamRequest {
     pub path: String,
     pub options: Option<ReadFileStreamOptions>,
+    /// Client-chosen id echoed on every chunk; required to cancel the stream.
+    pub stream_id: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -112,14 +186,77 @@ pub struct ReadFileStreamOptions {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadFileStreamResponse {
+    pub stream_id: Option<u32>,
     pub chunk: Vec<u8>,
+    pub position: u64, // file offset of the first byte in `chunk`
     pub done: bool,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelStreamRequest {
+    pub stream_id: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelRequest {
+    /// Id of the request message to cancel.
+    pub id: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopyTreeRequest {
+    pub source: String,
+    pub destination: String,
+    pub overwrite: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CopyTreeProgress {
+    pub files_done: u64,
+    pub files_total: u64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub current: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopyTreeFailure {
+    pub path: String,
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CopyTreeResponse {
+    pub files_done: u64,
+    pub bytes_done: u64,
+    /// Entries that could not be copied; the rest of the tree is still copied.
+    pub failures: Vec<CopyTreeFailure>,
+    pub cancelled: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CloneRequest {
     pub source: String,
     pub destination: String,
+    pub preserve_xattrs: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CloneStrategy {
+    /// Copy-on-write clone sharing the source's blocks (FICLONE, clonefile).
+    Reflink,
+    /// In-kernel copy without a round trip through userspace.
+    CopyFileRange,
+    Copy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloneResponse {
+    /// `None` when source and destination are the same file.
+    pub strategy: Option<CloneStrategy>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -127,6 +264,7 @@ pub struct CopyRequest {
     pub source: String,
     pub destination: String,
     pub overwrite: Option<bool>,
+    pub preserve_xattrs: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -134,6 +272,32 @@ pub struct DeleteRequest {
     pub path: String,
     pub recursive: Option<bool>,
     pub atomic: Option<bool>,
+    /// Move to the trash instead of deleting permanently.
+    pub use_trash: Option<bool>,
+    #[serde(flatten, default)]
+    pub preconditions: FilePreconditions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashItem {
+    /// Path of the item inside the trash, used to restore or delete it.
+    pub id: String,
+    pub original_path: String,
+    pub deletion_date: Option<i64>, // milliseconds since epoch
+    pub is_directory: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreTrashRequest {
+    pub id: String,
+    /// Replace whatever now exists at the original path.
+    pub overwrite: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmptyTrashRequest {
+    /// Items to delete permanently; every trashed item when omitted.
+    pub ids: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -146,6 +310,153 @@ pub struct ReadDirRequest {
     pub path: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkRequest {
+    pub path: String,
+    /// 0 reports only `path` itself and 1 lists only its direct children;
+    /// unlimited when omitted.
+    pub max_depth: Option<u32>,
+    /// Globs matched against paths relative to `path`. Only matching entries
+    /// are reported, but every directory is still descended into.
+    pub includes: Option<Vec<String>>,
+    /// Globs matched against paths relative to `path`. Matching entries are
+    /// neither reported nor descended into.
+    pub excludes: Option<Vec<String>>,
+    pub follow_symlinks: Option<bool>,
+    /// Skip entries ignored by `.gitignore` files inside the tree, and `.git`.
+    pub use_gitignore: Option<bool>,
+    /// Entries per response message, 512 by default.
+    pub batch_size: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkResponse {
+    pub entries: Vec<DirEntry>,
+    pub done: bool,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    /// 128-bit XXH3, much faster than sha256 but not collision resistant
+    /// against deliberate tampering.
+    Xxh3,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashRequest {
+    /// A file, or a directory to digest as a whole.
+    pub path: String,
+    pub algorithm: Option<HashAlgorithm>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashResponse {
+    pub digest: String, // hex
+    pub algorithm: HashAlgorithm,
+    pub is_directory: bool,
+    /// Files covered by the digest, and how many of them came from the cache.
+    pub files: u64,
+    pub cached: u64,
+    /// Set when the hash was cancelled, which leaves `digest` empty.
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymlinkRequest {
+    /// Stored in the link as given, so a relative target stays relative to
+    /// the link's directory.
+    pub target: String,
+    pub path: String,
+    /// Whether the target is a directory, which Windows needs to know when the
+    /// target does not exist yet. Detected from the target when omitted.
+    pub is_directory: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadLinkRequest {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HardLinkRequest {
+    /// The existing file.
+    pub target: String,
+    pub path: String,
+}
+
+/// Names the attribute with its namespace, e.g. `user.origin` on Linux.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XattrRequest {
+    /// Followed if it is a symlink, so the attribute is the target's.
+    pub path: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListXattrRequest {
+    /// Followed if it is a symlink, like in `XattrRequest`.
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetXattrRequest {
+    /// Followed if it is a symlink, like in `XattrRequest`.
+    pub path: String,
+    pub name: String,
+    pub value: String, // base64, since values are arbitrary bytes
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatFsRequest {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatFsResponse {
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    /// Free bytes usable without root privileges.
+    pub available_bytes: u64,
+    /// Zero on filesystems without a fixed inode table.
+    pub total_inodes: u64,
+    pub free_inodes: u64,
+    pub filesystem_type: String, // e.g. "ext4", "apfs", "fuse.sshfs"
+    pub mount_point: String,
+    pub case_sensitive: bool,
+    /// Network (NFS, SMB, ...) or FUSE filesystem.
+    pub remote: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirSizeRequest {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirSizeProgress {
+    pub bytes: u64,
+    pub files: u64,
+    pub directories: u64,
+    pub current: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirSizeResponse {
+    /// Sum of file lengths.
+    pub bytes: u64,
+    /// Space used on disk, which is smaller for sparse files.
+    pub allocated_bytes: u64,
+    pub files: u64,
+    pub directories: u64,
+    /// Entries that could not be read.
+    pub skipped: u64,
+    pub cancelled: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RealPathRequest {
     pub path: String,
@@ -161,6 +472,56 @@ pub struct MkDirRequest {
 pub struct RenameRequest {
     pub old_path: String,
     pub new_path: String,
+    /// Checked against `old_path`.
+    #[serde(flatten, default)]
+    pub preconditions: FilePreconditions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChmodRequest {
+    pub path: String,
+    pub mode: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChownRequest {
+    pub path: String,
+    pub uid: Option<u32>, // None leaves the owner unchanged
+    pub gid: Option<u32>, // None leaves the group unchanged
+    pub follow_symlinks: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetTimesRequest {
+    pub path: String,
+    pub atime: Option<i64>, // milliseconds since epoch, None leaves it unchanged
+    pub mtime: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub operations: Vec<FileIORequest>,
+    /// Applies all operations or none: paths are locked for the whole batch
+    /// and everything is rolled back if an operation fails.
+    pub transactional: Option<bool>,
+    /// Keeps going after a failed operation. Ignored for transactional batches.
+    pub continue_on_error: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub operation: String,
+    /// Absent for operations that did not run after an earlier failure.
+    pub response: Option<FileIOResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub results: Vec<BatchResult>,
+    /// Whether every operation succeeded and its changes were kept.
+    pub committed: bool,
+    /// Whether a failed transaction was undone completely.
+    pub rolled_back: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -168,10 +529,27 @@ pub struct RenameRequest {
 pub enum FileIORequest {
     ReadFile(ReadFileRequest),
     WriteFile(WriteFileRequest),
+    WriteFileStream(WriteFileStreamRequest),
     Copy(CopyRequest),
+    CopyTree(CopyTreeRequest),
+    MoveTree(CopyTreeRequest),
     Delete(DeleteRequest),
     Stat(StatRequest),
     ReadDir(ReadDirRequest),
+    Walk(WalkRequest),
+    Hash(HashRequest),
+    Symlink(SymlinkRequest),
+    ReadLink(ReadLinkRequest),
+    HardLink(HardLinkRequest),
+    ListXattr(ListXattrRequest),
+    GetXattr(XattrRequest),
+    SetXattr(SetXattrRequest),
+    RemoveXattr(XattrRequest),
+    StatFs(StatFsRequest),
+    DirSize(DirSizeRequest),
+    ListTrash,
+    RestoreTrash(RestoreTrashRequest),
+    EmptyTrash(EmptyTrashRequest),
     RealPath(RealPathRequest),
     MkDir(MkDirRequest),
     Rename(RenameRequest),
@@ -179,25 +557,167 @@ pub enum FileIORequest {
     CloseFile(CloseFileRequest),
     ReadFileHandle(ReadFileHandleRequest),
     WriteFileHandle(WriteFileHandleRequest),
+    FsyncFileHandle(FsyncFileHandleRequest),
+    ListHandles,
     ReadFileStream(ReadFileStreamRequest),
     Clone(CloneRequest),
+    CancelStream(CancelStreamRequest),
+    Cancel(CancelRequest),
+    Chmod(ChmodRequest),
+    Chown(ChownRequest),
+    SetTimes(SetTimesRequest),
+    Batch(BatchRequest),
 }
 
+impl FileIORequest {
+    /// The operation tag, matching the serialized `method` of the request.
+    pub fn operation(&self) -> &'static str {
+        match self {
+            FileIORequest::ReadFile(_) => "ReadFile",
+            FileIORequest::WriteFile(_) => "WriteFile",
+            FileIORequest::WriteFileStream(_) => "WriteFileStream",
+            FileIORequest::Copy(_) => "Copy",
+            FileIORequest::CopyTree(_) => "CopyTree",
+            FileIORequest::MoveTree(_) => "MoveTree",
+            FileIORequest::Delete(_) => "Delete",
+            FileIORequest::Stat(_) => "Stat",
+            FileIORequest::ReadDir(_) => "ReadDir",
+            FileIORequest::Walk(_) => "Walk",
+            FileIORequest::Hash(_) => "Hash",
+            FileIORequest::Symlink(_) => "Symlink",
+            FileIORequest::ReadLink(_) => "ReadLink",
+            FileIORequest::HardLink(_) => "HardLink",
+            FileIORequest::ListXattr(_) => "ListXattr",
+            FileIORequest::GetXattr(_) => "GetXattr",
+            FileIORequest::SetXattr(_) => "SetXattr",
+            FileIORequest::RemoveXattr(_) => "RemoveXattr",
+            FileIORequest::StatFs(_) => "StatFs",
+            FileIORequest::DirSize(_) => "DirSize",
+            FileIORequest::ListTrash => "ListTrash",
+            FileIORequest::RestoreTrash(_) => "RestoreTrash",
+            FileIORequest::EmptyTrash(_) => "EmptyTrash",
+            FileIORequest::RealPath(_) => "RealPath",
+            FileIORequest::MkDir(_) => "MkDir",
+            FileIORequest::Rename(_) => "Rename",
+            FileIORequest::OpenFile(_) => "OpenFile",
+            FileIORequest::CloseFile(_) => "CloseFile",
+            FileIORequest::ReadFileHandle(_) => "ReadFileHandle",
+            FileIORequest::WriteFileHandle(_) => "WriteFileHandle",
+            FileIORequest::FsyncFileHandle(_) => "FsyncFileHandle",
+            FileIORequest::ListHandles => "ListHandles",
+            FileIORequest::ReadFileStream(_) => "ReadFileStream",
+            FileIORequest::Clone(_) => "Clone",
+            FileIORequest::CancelStream(_) => "CancelStream",
+            FileIORequest::Cancel(_) => "Cancel",
+            FileIORequest::Chmod(_) => "Chmod",
+            FileIORequest::Chown(_) => "Chown",
+            FileIORequest::SetTimes(_) => "SetTimes",
+            FileIORequest::Batch(_) => "Batch",
+        }
+    }
+
+    /// The paths the request touches and whether it modifies them. Handle
+    /// requests are covered by the check made when the handle was opened.
+    pub fn accessed_paths(&self) -> Vec<(&str, PathAccess)> {
+        use PathAccess::{Read, Write};
+        match self {
+            FileIORequest::ReadFile(req) => vec![(&req.path, Read)],
+            FileIORequest::WriteFile(req) => vec![(&req.path, Write)],
+            FileIORequest::WriteFileStream(req) => vec![(&req.path, Write)],
+            FileIORequest::Copy(req) => vec![(&req.source, Read), (&req.destination, Write)],
+            FileIORequest::CopyTree(req) => vec![(&req.source, Read), (&req.destination, Write)],
+            FileIORequest::MoveTree(req) => vec![(&req.source, Write), (&req.destination, Write)],
+            FileIORequest::Delete(req) => vec![(&req.path, Write)],
+            FileIORequest::Stat(req) => vec![(&req.path, Read)],
+            FileIORequest::ReadDir(req) => vec![(&req.path, Read)],
+            FileIORequest::Walk(req) => vec![(&req.path, Read)],
+            FileIORequest::Hash(req) => vec![(&req.path, Read)],
+            // The target is checked once it is resolved against the link's directory
+            FileIORequest::Symlink(req) => vec![(&req.path, Write)],
+            FileIORequest::ReadLink(req) => vec![(&req.path, Read)],
+            // Writes through a hard link change the target, so both need write access
+            FileIORequest::HardLink(req) => vec![(&req.target, Write), (&req.path, Write)],
+            FileIORequest::ListXattr(req) => vec![(&req.path, Read)],
+            FileIORequest::GetXattr(req) => vec![(&req.path, Read)],
+            FileIORequest::SetXattr(req) => vec![(&req.path, Write)],
+            FileIORequest::RemoveXattr(req) => vec![(&req.path, Write)],
+            FileIORequest::StatFs(req) => vec![(&req.path, Read)],
+            FileIORequest::DirSize(req) => vec![(&req.path, Read)],
+            FileIORequest::RealPath(req) => vec![(&req.path, Read)],
+            FileIORequest::MkDir(req) => vec![(&req.path, Write)],
+            FileIORequest::Rename(req) => vec![(&req.old_path, Write), (&req.new_path, Write)],
+            FileIORequest::OpenFile(req) => {
+                let access = if req.create.unwrap_or(true) || req.unlock.unwrap_or(false) { Write } else { Read };
+                vec![(&req.path, access)]
+            }
+            FileIORequest::ReadFileStream(req) => vec![(&req.path, Read)],
+            FileIORequest::Clone(req) => vec![(&req.source, Read), (&req.destination, Write)],
+            FileIORequest::Chmod(req) => vec![(&req.path, Write)],
+            FileIORequest::Chown(req) => vec![(&req.path, Write)],
+            FileIORequest::SetTimes(req) => vec![(&req.path, Write)],
+            FileIORequest::Batch(req) => req.operations.iter().flat_map(|operation| operation.accessed_paths()).collect(),
+            FileIORequest::CloseFile(_)
+            | FileIORequest::ReadFileHandle(_)
+            | FileIORequest::WriteFileHandle(_)
+            | FileIORequest::FsyncFileHandle(_)
+            | FileIORequest::ListHandles
+            | FileIORequest::CancelStream(_)
+            | FileIORequest::Cancel(_) => Vec::new(),
+            // Trashed items are checked against their original path once it
+            // has been read from the trash
+            FileIORequest::ListTrash | FileIORequest::RestoreTrash(_) | FileIORequest::EmptyTrash(_) => Vec::new(),
+        }
+    }
+}
+
+/// A request as received over IPC. The client-chosen `id` is echoed on every
+/// response produced for the request.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FileIOError {
+pub struct FileIORequestMessage {
+    pub id: Option<u32>,
+    #[serde(flatten)]
+    pub request: FileIORequest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileIOErrorResponse {
     pub message: String,
-    pub code: String,
+    pub code: String, // POSIX-style code, e.g. "ENOENT"
+    pub category: FileSystemProviderErrorCode,
+    /// Current state of the file when a precondition failed; absent if it no longer exists.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<FileStat>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
 pub enum FileIOResponse {
     ReadFile(ReadFileResponse),
     WriteFile(()),
+    WriteFileStream(WriteFileStreamResponse),
     Copy(()),
+    CopyTree(CopyTreeResponse),
+    MoveTree(CopyTreeResponse),
+    CopyTreeProgress(CopyTreeProgress),
     Delete(()),
     Stat(FileStat),
     ReadDir(Vec<DirEntry>),
+    Walk(WalkResponse),
+    Hash(HashResponse),
+    Symlink(()),
+    ReadLink(String),
+    HardLink(()),
+    ListXattr(Vec<String>),
+    GetXattr(String), // base64
+    SetXattr(()),
+    RemoveXattr(()),
+    StatFs(StatFsResponse),
+    DirSize(DirSizeResponse),
+    DirSizeProgress(DirSizeProgress),
+    /// Answers a `Delete` with `use_trash`.
+    Trash(TrashItem),
+    ListTrash(Vec<TrashItem>),
+    RestoreTrash(TrashItem),
+    EmptyTrash(u64),
     RealPath(String),
     MkDir(()),
     Rename(()),
@@ -205,7 +725,24 @@ pub enum FileIOResponse {
     CloseFile(()),
     ReadFileHandle(ReadFileHandleResponse),
     WriteFileHandle(WriteFileHandleResponse),
+    FsyncFileHandle(()),
+    ListHandles(Vec<HandleInfo>),
     ReadFileStream(ReadFileStreamResponse),
-    Clone(()),
-    Error(FileIOError),
-}
\ No newline at end of file
+    Clone(CloneResponse),
+    CancelStream(bool),
+    Cancel(bool),
+    Chmod(()),
+    Chown(()),
+    SetTimes(()),
+    Batch(BatchResponse),
+    Error(FileIOErrorResponse),
+}
+
+/// A response as sent over IPC, tagged with the id and operation of the
+/// request that produced it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileIOResponseMessage {
+    pub id: Option<u32>,
+    pub operation: String,
+    pub response: FileIOResponse,
+}
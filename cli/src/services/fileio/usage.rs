@@ -0,0 +1,215 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! Disk usage: the capacity of the filesystem holding a path and the size of
+//! a directory tree.
+
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::platform;
+use crate::services::fileio::types::{DirSizeProgress, DirSizeRequest, DirSizeResponse, StatFsRequest, StatFsResponse};
+use crate::services::paths;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+const DIR_SIZE_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Filesystem types served over the network, where change notifications are
+/// unreliable and every access may be slow. FUSE mounts are matched by their
+/// `fuse.<name>` type instead; `fuseblk` is a local disk such as NTFS, and a
+/// bare `fuse` says nothing about where the data lives.
+const REMOTE_FS_TYPES: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb", "smb2", "smb3", "smbfs", "afpfs", "webdav", "davfs", "9p", "ceph", "glusterfs", "afs",
+    "lustre", "gpfs", "sshfs",
+];
+
+/// Filesystem types that compare names case-insensitively by default.
+const CASE_INSENSITIVE_FS_TYPES: &[&str] = &["vfat", "msdos", "exfat", "ntfs", "ntfs3", "apfs", "hfs", "smbfs", "cifs"];
+
+/// Whether a filesystem type, as reported by `StatFs`, is remote or FUSE.
+pub fn is_remote_fs_type(fs_type: &str) -> bool {
+    let fs_type = fs_type.to_ascii_lowercase();
+    fs_type.starts_with("fuse.") || REMOTE_FS_TYPES.contains(&fs_type.as_str())
+}
+
+pub async fn statfs(request: StatFsRequest) -> FileIOResult<StatFsResponse> {
+    let path = paths::to_path_buf(&request.path)?;
+    tokio::task::spawn_blocking(move || {
+        let usage = platform::fs_usage(&path)?;
+        let case_sensitive = probe_case_sensitivity(&path)
+            .unwrap_or_else(|| !CASE_INSENSITIVE_FS_TYPES.contains(&usage.fs_type.to_ascii_lowercase().as_str()));
+
+        Ok(StatFsResponse {
+            total_bytes: usage.total_bytes,
+            free_bytes: usage.free_bytes,
+            available_bytes: usage.available_bytes,
+            total_inodes: usage.total_inodes,
+            free_inodes: usage.free_inodes,
+            remote: is_remote_fs_type(&usage.fs_type),
+            filesystem_type: usage.fs_type,
+            mount_point: usage.mount_point.to_string_lossy().to_string(),
+            case_sensitive,
+        })
+    })
+    .await
+    .map_err(|e| FileIOError::Other(Box::new(e)))?
+}
+
+/// Looks up the closest entry of `path` with a cased name under its name with
+/// the case flipped: finding the same file means the directory ignores case.
+/// Only names looked up on the filesystem of `path` are probed, so `None` is
+/// returned when none of them has a name to flip, e.g. for `/` or a mount
+/// point whose own name lives on the parent filesystem.
+fn probe_case_sensitivity(path: &Path) -> Option<bool> {
+    let canonical = std::fs::canonicalize(path).ok()?;
+    let target_device = device(&std::fs::metadata(&canonical).ok()?);
+    for entry in canonical.ancestors() {
+        // Names are looked up in the parent directory
+        if device(&std::fs::metadata(entry.parent()?).ok()?) != target_device {
+            return None;
+        }
+        let name = entry.file_name()?.to_string_lossy();
+        let flipped: String = name
+            .chars()
+            .map(|c| if c.is_lowercase() { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() })
+            .collect();
+        if flipped == name {
+            continue;
+        }
+
+        let original = std::fs::symlink_metadata(entry).ok()?;
+        return Some(match std::fs::symlink_metadata(entry.with_file_name(&flipped)) {
+            Ok(other) => !same_file(&original, &other),
+            Err(_) => true,
+        });
+    }
+    None
+}
+
+#[cfg(unix)]
+fn device(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    a.len() == b.len() && a.modified().ok() == b.modified().ok() && a.created().ok() == b.created().ok()
+}
+
+/// Adds up the sizes of everything under `request.path` on a blocking thread,
+/// reporting progress at most every 100ms. Symlinks are counted but not
+/// followed, hard-linked files are counted once, and entries that cannot be
+/// read are skipped and counted.
+pub async fn dir_size<F>(request: DirSizeRequest, cancellation: CancellationToken, on_progress: F) -> FileIOResult<DirSizeResponse>
+where
+    F: FnMut(DirSizeProgress) + Send + 'static,
+{
+    let root = paths::to_path_buf(&request.path)?;
+    tokio::task::spawn_blocking(move || dir_size_blocking(&root, cancellation, on_progress))
+        .await
+        .map_err(|e| FileIOError::Other(Box::new(e)))?
+}
+
+fn dir_size_blocking<F>(root: &Path, cancellation: CancellationToken, mut on_progress: F) -> FileIOResult<DirSizeResponse>
+where
+    F: FnMut(DirSizeProgress),
+{
+    if !std::fs::metadata(root)?.is_dir() {
+        return Err(FileIOError::InvalidArgument(format!("{} is not a directory", root.display())));
+    }
+
+    let mut result = DirSizeResponse::default();
+    let mut seen_inodes = HashSet::new();
+    let mut pending: Vec<PathBuf> = vec![root.to_path_buf()];
+    let mut last_report = Instant::now();
+
+    while let Some(dir) = pending.pop() {
+        if cancellation.is_cancelled() {
+            result.cancelled = true;
+            return Ok(result);
+        }
+
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            result.skipped += 1;
+            continue;
+        };
+        result.directories += 1;
+
+        for entry in entries {
+            let Ok(metadata) = entry.and_then(|entry| {
+                let metadata = entry.metadata()?;
+                if metadata.is_dir() {
+                    pending.push(entry.path());
+                }
+                Ok(metadata)
+            }) else {
+                result.skipped += 1;
+                continue;
+            };
+            if metadata.is_dir() {
+                continue;
+            }
+
+            if let Some(inode) = hard_link_key(&metadata) {
+                if !seen_inodes.insert(inode) {
+                    continue;
+                }
+            }
+            result.files += 1;
+            result.bytes += metadata.len();
+            result.allocated_bytes += allocated_size(&metadata);
+        }
+
+        if last_report.elapsed() >= DIR_SIZE_PROGRESS_INTERVAL {
+            last_report = Instant::now();
+            on_progress(DirSizeProgress {
+                bytes: result.bytes,
+                files: result.files,
+                directories: result.directories,
+                current: Some(dir.to_string_lossy().to_string()),
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+/// Identifies files with more than one link, which must only be counted once.
+#[cfg(unix)]
+fn hard_link_key(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn hard_link_key(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Space taken on disk, which differs from the length for sparse and
+/// compressed files.
+#[cfg(unix)]
+fn allocated_size(metadata: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(metadata: &std::fs::Metadata) -> u64 {
+    metadata.len()
+}
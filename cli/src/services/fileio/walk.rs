@@ -0,0 +1,261 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use crate::services::fileio::errors::{FileIOError, FileIOResult};
+use crate::services::fileio::operations::to_millis;
+use crate::services::fileio::sandbox::{PathAccess, Sandbox};
+use crate::services::fileio::types::{DirEntry, WalkRequest, WalkResponse};
+use crate::services::paths;
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tokio_util::sync::CancellationToken;
+
+const DEFAULT_WALK_BATCH_SIZE: usize = 512;
+
+/// Walks the tree under `request.path` on a blocking thread, sending entries to
+/// `on_batch` in batches and finishing with a batch marked `done`. Directories
+/// that cannot be read are skipped. With a `sandbox`, symlinks that lead
+/// outside the allowed roots are neither reported nor followed.
+pub async fn walk<F>(
+    request: WalkRequest,
+    sandbox: Option<Sandbox>,
+    cancellation: CancellationToken,
+    on_batch: F,
+) -> FileIOResult<()>
+where
+    F: FnMut(WalkResponse) + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || walk_blocking(request, sandbox, cancellation, on_batch))
+        .await
+        .map_err(|e| FileIOError::Other(Box::new(e)))?
+}
+
+struct WalkFilter {
+    includes: Option<GlobSet>,
+    excludes: GlobSet,
+}
+
+impl WalkFilter {
+    fn new(includes: &[String], excludes: &[String]) -> FileIOResult<Self> {
+        let build = |patterns: &[String]| -> FileIOResult<GlobSet> {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(Glob::new(pattern).map_err(|e| FileIOError::InvalidArgument(e.to_string()))?);
+            }
+            builder.build().map_err(|e| FileIOError::InvalidArgument(e.to_string()))
+        };
+
+        Ok(WalkFilter {
+            includes: if includes.is_empty() { None } else { Some(build(includes)?) },
+            excludes: build(excludes)?,
+        })
+    }
+}
+
+struct PendingDir {
+    path: PathBuf,
+    depth: u32,
+    ignores: Vec<Rc<IgnoreRules>>,
+}
+
+fn walk_blocking<F>(request: WalkRequest, sandbox: Option<Sandbox>, cancellation: CancellationToken, mut on_batch: F) -> FileIOResult<()>
+where
+    F: FnMut(WalkResponse),
+{
+    let root = paths::to_path_buf(&request.path)?;
+    let max_depth = request.max_depth.unwrap_or(u32::MAX);
+    let follow_symlinks = request.follow_symlinks.unwrap_or(false);
+    let use_gitignore = request.use_gitignore.unwrap_or(false);
+    let batch_size = request.batch_size.map_or(DEFAULT_WALK_BATCH_SIZE, |size| size.max(1) as usize);
+    let filter = WalkFilter::new(
+        request.includes.as_deref().unwrap_or_default(),
+        request.excludes.as_deref().unwrap_or_default(),
+    )?;
+
+    // Fail the request up front if the root itself is unreadable
+    std::fs::read_dir(&root)?;
+
+    if max_depth == 0 {
+        let entries = describe(&root, follow_symlinks, None).into_iter().collect();
+        on_batch(WalkResponse { entries, done: true, cancelled: false });
+        return Ok(());
+    }
+
+    // Directories already entered, so that followed symlinks cannot loop
+    let mut visited = HashSet::new();
+    if follow_symlinks {
+        visited.insert(std::fs::canonicalize(&root)?);
+    }
+
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut pending = vec![PendingDir { path: root.clone(), depth: 0, ignores: Vec::new() }];
+
+    while let Some(dir) = pending.pop() {
+        if cancellation.is_cancelled() {
+            on_batch(WalkResponse { entries: batch, done: true, cancelled: true });
+            return Ok(());
+        }
+
+        let mut ignores = dir.ignores;
+        if use_gitignore {
+            if let Some(rules) = IgnoreRules::load(&root, &dir.path) {
+                ignores.push(Rc::new(rules));
+            }
+        }
+
+        let Ok(entries) = std::fs::read_dir(&dir.path) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(relative) = path.strip_prefix(&root) else { continue };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            // Excluded trees are skipped without touching them
+            if filter.excludes.is_match(&relative) {
+                continue;
+            }
+
+            let Some(dir_entry) = describe(&path, follow_symlinks, sandbox.as_ref()) else { continue };
+            let (is_dir, is_symlink) = (dir_entry.is_directory, dir_entry.is_symlink);
+            if use_gitignore && ((is_dir && entry.file_name() == ".git") || is_ignored(&ignores, &relative, is_dir)) {
+                continue;
+            }
+
+            if filter.includes.as_ref().is_none_or(|includes| includes.is_match(&relative)) {
+                batch.push(dir_entry);
+                if batch.len() >= batch_size {
+                    on_batch(WalkResponse {
+                        entries: std::mem::replace(&mut batch, Vec::with_capacity(batch_size)),
+                        done: false,
+                        cancelled: false,
+                    });
+                }
+            }
+
+            if !is_dir || dir.depth + 1 >= max_depth || (is_symlink && !follow_symlinks) {
+                continue;
+            }
+            if is_symlink {
+                let Ok(target) = std::fs::canonicalize(&path) else { continue };
+                if !visited.insert(target) {
+                    continue;
+                }
+            } else if follow_symlinks {
+                if let Ok(canonical) = std::fs::canonicalize(&path) {
+                    visited.insert(canonical);
+                }
+            }
+
+            pending.push(PendingDir { path, depth: dir.depth + 1, ignores: ignores.clone() });
+        }
+    }
+
+    on_batch(WalkResponse { entries: batch, done: true, cancelled: false });
+    Ok(())
+}
+
+/// Describes `path`, or its target for a symlink that is followed. `None` if
+/// it vanished, or if it is a symlink leading outside the `sandbox`, whose
+/// target is not even looked at.
+fn describe(path: &Path, follow_symlinks: bool, sandbox: Option<&Sandbox>) -> Option<DirEntry> {
+    let link_metadata = std::fs::symlink_metadata(path).ok()?;
+    let is_symlink = link_metadata.file_type().is_symlink();
+    if is_symlink && sandbox.is_some_and(|sandbox| sandbox.check(&path.to_string_lossy(), PathAccess::Read).is_err()) {
+        return None;
+    }
+
+    let target_metadata = if is_symlink { std::fs::metadata(path).ok() } else { None };
+    let dangling = is_symlink && target_metadata.is_none();
+    let metadata = match target_metadata {
+        Some(target_metadata) if follow_symlinks => target_metadata,
+        _ => link_metadata,
+    };
+    let symlink_target = if is_symlink { std::fs::read_link(path).ok() } else { None };
+
+    Some(DirEntry {
+        name: path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string(),
+        path: path.to_string_lossy().to_string(),
+        is_directory: metadata.is_dir(),
+        is_file: metadata.is_file(),
+        is_symlink,
+        size: Some(metadata.len()),
+        mtime: metadata.modified().ok().map(to_millis),
+        symlink_target: symlink_target.map(|target| target.to_string_lossy().to_string()),
+        dangling,
+    })
+}
+
+struct IgnoreRule {
+    matcher: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The rules of one `.gitignore`, matched against paths relative to the
+/// directory that contains it.
+struct IgnoreRules {
+    /// Directory of the `.gitignore`, relative to the walk root.
+    base: String,
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    fn load(root: &Path, dir: &Path) -> Option<IgnoreRules> {
+        let contents = std::fs::read_to_string(dir.join(".gitignore")).ok()?;
+        let base = dir.strip_prefix(root).ok()?.to_string_lossy().replace('\\', "/");
+        let rules: Vec<IgnoreRule> = contents.lines().filter_map(IgnoreRule::parse).collect();
+        (!rules.is_empty()).then_some(IgnoreRules { base, rules })
+    }
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<IgnoreRule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        // A slash anywhere but the end anchors the pattern to the .gitignore's directory
+        let glob = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{}", pattern),
+        };
+
+        let matcher = GlobBuilder::new(&glob).literal_separator(true).build().ok()?.compile_matcher();
+        Some(IgnoreRule { matcher, negated, dir_only })
+    }
+}
+
+/// Applies the rules from the root down, so that the last matching rule of the
+/// deepest `.gitignore` decides, as git does.
+fn is_ignored(ignores: &[Rc<IgnoreRules>], relative: &str, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for rules in ignores {
+        let local = if rules.base.is_empty() {
+            relative
+        } else {
+            match relative.strip_prefix(&rules.base).and_then(|rest| rest.strip_prefix('/')) {
+                Some(local) => local,
+                None => continue,
+            }
+        };
+
+        for rule in &rules.rules {
+            if (!rule.dir_only || is_dir) && rule.matcher.is_match(local) {
+                ignored = !rule.negated;
+            }
+        }
+    }
+    ignored
+}
@@ -38,11 +38,13 @@ impl PathNormalizer {
         let mut dots = 0;
         let mut i = 0;
         let len = path.len();
+        let mut code: Option<u32> = None;
         while i <= len {
-            let code = if i < len {
+            code = if i < len {
                 Some(path.as_bytes()[i] as u32)
             } else {
-                if is_path_sep(Some(Self::CHAR_FORWARD_SLASH)) {
+                // Flush the last segment unless the path ended with a separator
+                if is_path_sep(code) {
                     break;
                 }
                 Some(Self::CHAR_FORWARD_SLASH)
@@ -86,13 +88,15 @@ impl PathNormalizer {
                         last_segment_length = 2;
                     }
                 } else {
+                    // `last_slash` is -1 before the first separator of a relative path
+                    let segment_start = (last_slash + 1) as usize;
                     if res.is_empty() {
-                        res = path[last_slash as usize + 1..i].to_string();
+                        res = path[segment_start..i].to_string();
                     } else {
                         res.push(separator);
-                        res.push_str(&path[last_slash as usize + 1..i]);
+                        res.push_str(&path[segment_start..i]);
                     }
-                    last_segment_length = i - (last_slash as usize + 1);
+                    last_segment_length = i - segment_start;
                 }
                 last_slash = i as i32;
                 dots = 0;
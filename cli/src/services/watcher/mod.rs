@@ -5,6 +5,7 @@
 
 pub mod coalescer;
 pub mod non_recursive;
+pub mod polling;
 pub mod recursive;
 pub mod service;
 pub mod suspend;
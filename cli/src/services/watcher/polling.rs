@@ -0,0 +1,295 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use crate::services::fileio::{platform, usage};
+use crate::services::watcher::types::{FileChange, FileChangeType, normalize_path, pathbuf_to_file_uri};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Interval used when polling is chosen because the filesystem is remote
+pub const DEFAULT_POLLING_INTERVAL: Duration = Duration::from_millis(5000);
+
+/// What we remember about a path between two scans
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EntryState {
+    mtime: Option<i64>,
+    size: u64,
+    is_dir: bool,
+}
+
+type Snapshot = BTreeMap<PathBuf, EntryState>;
+
+/// Watches a path by periodically comparing mtime/size snapshots, for
+/// filesystems where native notifications never arrive (NFS, SMB, sshfs and
+/// other FUSE mounts).
+pub struct PollingWatcher {
+    receiver: Receiver<Vec<FileChange>>,
+    base_path: PathBuf,
+    // Dropping the sender stops the polling thread
+    _stop: Sender<()>,
+    _handle: thread::JoinHandle<()>,
+}
+
+/// Whether `path` lives on a remote or FUSE filesystem, where native watchers
+/// miss changes and polling has to be used instead
+pub fn requires_polling(path: &Path) -> bool {
+    platform::fs_usage(path)
+        .map(|fs_usage| usage::is_remote_fs_type(&fs_usage.fs_type))
+        .unwrap_or(false)
+}
+
+fn entry_state(metadata: &fs::Metadata) -> EntryState {
+    EntryState {
+        mtime: metadata
+            .modified()
+            .ok()
+            .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+            .map(|mtime| mtime.as_millis() as i64),
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        is_dir: metadata.is_dir(),
+    }
+}
+
+/// Stat the watched path and everything below it (or only its direct
+/// children when not recursive). Symlinks are recorded but not followed.
+/// Excluded entries are left out, and directories whose contents are all
+/// excluded, such as `node_modules` for `**/node_modules/**`, are not read.
+fn scan(root: &Path, recursive: bool, excludes: &GlobSet) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    let root_metadata = match fs::metadata(root) {
+        Ok(metadata) => metadata,
+        Err(_) => return snapshot,
+    };
+    snapshot.insert(root.to_path_buf(), entry_state(&root_metadata));
+    if !root_metadata.is_dir() {
+        return snapshot;
+    }
+
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue, // Unreadable or vanished mid-scan
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if excludes.is_match(&path) {
+                continue;
+            }
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if recursive && metadata.is_dir() && !excludes.is_match(format!("{}{}", path.display(), MAIN_SEPARATOR)) {
+                pending.push(path.clone());
+            }
+            snapshot.insert(path, entry_state(&metadata));
+        }
+    }
+    snapshot
+}
+
+/// Changes between two snapshots. Directories only report being added or
+/// removed, since their mtime moves whenever a child does.
+fn diff(previous: &Snapshot, current: &Snapshot) -> Vec<FileChange> {
+    let mut changes = Vec::new();
+    let mut push = |path: &Path, change_type: FileChangeType, mtime: Option<i64>| {
+        if let Ok(resource) = pathbuf_to_file_uri(path.to_path_buf()) {
+            changes.push(FileChange {
+                resource,
+                change_type,
+                correlation_id: None,
+                mtime,
+            });
+        }
+    };
+
+    for (path, state) in current {
+        match previous.get(path) {
+            None => push(path, FileChangeType::Added, state.mtime),
+            Some(old) if old.is_dir != state.is_dir => {
+                push(path, FileChangeType::Deleted, None);
+                push(path, FileChangeType::Added, state.mtime);
+            }
+            Some(old) if !state.is_dir && (old.mtime != state.mtime || old.size != state.size) => {
+                push(path, FileChangeType::Updated, state.mtime)
+            }
+            Some(_) => {}
+        }
+    }
+    for path in previous.keys() {
+        if !current.contains_key(path) {
+            push(path, FileChangeType::Deleted, None);
+        }
+    }
+    changes
+}
+
+impl PollingWatcher {
+    pub fn new(path: PathBuf, recursive: bool, interval: Duration, excludes: &[String]) -> std::io::Result<Self> {
+        let base_path = normalize_path(path);
+        // Fail like the native watchers do when there is nothing to watch
+        fs::metadata(&base_path)?;
+
+        let mut builder = GlobSetBuilder::new();
+        for pattern in excludes {
+            builder.add(Glob::new(pattern).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?);
+        }
+        let excludes = builder.build().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+        // The initial scan happens before returning so that any change made
+        // after the watcher is created is reported
+        let mut snapshot = scan(&base_path, recursive, &excludes);
+        let (tx, rx) = channel();
+        let (stop_tx, stop_rx) = channel::<()>();
+
+        let root = base_path.clone();
+        let handle = thread::spawn(move || {
+            // Anything but a timeout means the watcher was dropped
+            while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                let current = scan(&root, recursive, &excludes);
+                let changes = diff(&snapshot, &current);
+                snapshot = current;
+                if !changes.is_empty() && tx.send(changes).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(PollingWatcher {
+            receiver: rx,
+            base_path,
+            _stop: stop_tx,
+            _handle: handle,
+        })
+    }
+
+    pub fn try_recv(&self) -> Result<Vec<FileChange>, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    pub fn path_exists(&self) -> bool {
+        self.base_path.exists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn collect_until(watcher: &PollingWatcher, done: impl Fn(&[FileChange]) -> bool) -> Vec<FileChange> {
+        let mut changes = Vec::new();
+        for _ in 0..100 {
+            while let Ok(batch) = watcher.try_recv() {
+                changes.extend(batch);
+            }
+            if done(&changes) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        changes
+    }
+
+    fn has(changes: &[FileChange], suffix: &str, change_type: FileChangeType) -> bool {
+        changes.iter().any(|change| change.resource.ends_with(suffix) && change.change_type == change_type)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_diff_snapshots() {
+        let state = |mtime, size, is_dir| EntryState { mtime: Some(mtime), size, is_dir };
+        let previous: Snapshot = [
+            (PathBuf::from("/root/kept.txt"), state(1, 1, false)),
+            (PathBuf::from("/root/changed.txt"), state(1, 1, false)),
+            (PathBuf::from("/root/removed.txt"), state(1, 1, false)),
+            (PathBuf::from("/root/dir"), state(1, 0, true)),
+        ]
+        .into_iter()
+        .collect();
+        let current: Snapshot = [
+            (PathBuf::from("/root/kept.txt"), state(1, 1, false)),
+            (PathBuf::from("/root/changed.txt"), state(1, 2, false)),
+            (PathBuf::from("/root/added.txt"), state(2, 1, false)),
+            (PathBuf::from("/root/dir"), state(2, 0, true)),
+        ]
+        .into_iter()
+        .collect();
+
+        let changes: Vec<(String, FileChangeType)> =
+            diff(&previous, &current).into_iter().map(|change| (change.resource, change.change_type)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("file:///root/added.txt".to_string(), FileChangeType::Added),
+                ("file:///root/changed.txt".to_string(), FileChangeType::Updated),
+                ("file:///root/removed.txt".to_string(), FileChangeType::Deleted),
+            ]
+        );
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_polling_watcher_reports_changes() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("nested")).unwrap();
+        fs::write(root.join("nested/existing.txt"), "a").unwrap();
+
+        let watcher = PollingWatcher::new(root.clone(), true, Duration::from_millis(20), &[]).unwrap();
+        fs::write(root.join("nested/created.txt"), "b").unwrap();
+        fs::write(root.join("nested/existing.txt"), "longer").unwrap();
+        let changes = collect_until(&watcher, |changes| changes.len() >= 2);
+        assert!(has(&changes, "/nested/created.txt", FileChangeType::Added));
+        assert!(has(&changes, "/nested/existing.txt", FileChangeType::Updated));
+
+        fs::remove_file(root.join("nested/created.txt")).unwrap();
+        let changes = collect_until(&watcher, |changes| !changes.is_empty());
+        assert!(has(&changes, "/nested/created.txt", FileChangeType::Deleted));
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_non_recursive_polling_ignores_nested_changes() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("nested")).unwrap();
+
+        let watcher = PollingWatcher::new(root.clone(), false, Duration::from_millis(20), &[]).unwrap();
+        fs::write(root.join("nested/ignored.txt"), "a").unwrap();
+        fs::write(root.join("top.txt"), "b").unwrap();
+        let changes = collect_until(&watcher, |changes| !changes.is_empty());
+        assert!(has(&changes, "/top.txt", FileChangeType::Added));
+        assert!(!changes.iter().any(|change| change.resource.ends_with("/ignored.txt")));
+
+        assert!(PollingWatcher::new(root.join("missing"), false, Duration::from_millis(20), &[]).is_err());
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_scan_skips_excluded_directories() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        fs::write(root.join("node_modules/pkg/index.js"), "a").unwrap();
+        fs::write(root.join("main.js"), "b").unwrap();
+        fs::write(root.join("debug.log"), "c").unwrap();
+
+        let mut builder = GlobSetBuilder::new();
+        builder.add(Glob::new("**/node_modules/**").unwrap());
+        builder.add(Glob::new("**/*.log").unwrap());
+        let snapshot = scan(&root, true, &builder.build().unwrap());
+
+        let mut paths: Vec<_> = snapshot.keys().map(|path| path.strip_prefix(&root).unwrap().to_path_buf()).collect();
+        paths.sort();
+        assert_eq!(paths, vec![PathBuf::new(), PathBuf::from("main.js"), PathBuf::from("node_modules")]);
+    }
+}
@@ -6,13 +6,15 @@
 use crate::services::watcher::{
     coalescer::EventCoalescer,
     non_recursive::NonRecursiveWatcher,
+    polling::{requires_polling, PollingWatcher, DEFAULT_POLLING_INTERVAL},
     recursive::RecursiveWatcher,
     suspend::WatcherSuspender,
-    throttler::{non_recursive_throttler, recursive_throttler, EventThrottler},
-    types::{FileChange, WatchRequest, WatchResponse, WatcherError},
+    throttler::{non_recursive_throttler, recursive_throttler, EventThrottler, ThrottledBatch},
+    types::{file_uri_to_pathbuf, FileChange, WatchRequest, WatcherError},
 };
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::mpsc::TryRecvError;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{RwLock, Mutex};
@@ -29,13 +31,26 @@ pub enum WatcherTypeEnum {
 pub enum WatcherType {
     Recursive(RecursiveWatcher),
     NonRecursive(NonRecursiveWatcher),
+    Polling(PollingWatcher),
+}
+
+impl WatcherType {
+    fn try_recv(&self) -> Result<Vec<FileChange>, TryRecvError> {
+        match self {
+            WatcherType::Recursive(w) => w.try_recv(),
+            WatcherType::NonRecursive(w) => w.try_recv(),
+            WatcherType::Polling(w) => w.try_recv(),
+        }
+    }
 }
 
 pub struct WatcherInstance {
     pub request: WatchRequest,
     pub watcher_type: WatcherTypeEnum,
+    /// Whether changes are detected by polling instead of native events
+    pub polling: bool,
     pub coalescer: EventCoalescer,
-    pub throttler: EventThrottler,
+    pub throttler: Arc<EventThrottler>,
     pub drops_count: Arc<RwLock<u64>>,
     pub throttled_batches_count: Arc<RwLock<u64>>,
 }
@@ -49,18 +64,76 @@ pub struct UniversalWatcher {
     resurrection_check_interval: Duration,
 }
 
+/// Send a JSON-RPC notification to the client. Like responses, notifications
+/// travel as one base64-encoded JSON message per line.
+fn emit_notification(ipc_sink: &(dyn Fn(String) + Send + Sync), method: &str, params: serde_json::Value) {
+    let notification = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+    });
+    ipc_sink(BASE64_STANDARD.encode(notification.to_string()));
+}
+
+/// Send a batch of changes to the client as an `onDidChangeFile` notification
+fn emit_changes(ipc_sink: &(dyn Fn(String) + Send + Sync), changes: Vec<FileChange>) {
+    emit_notification(ipc_sink, "onDidChangeFile", serde_json::json!(changes));
+}
+
+/// Send an `onDidLogMessage` notification to the client
+fn emit_log_message(ipc_sink: &(dyn Fn(String) + Send + Sync), log_type: &str, message: &str) {
+    emit_notification(ipc_sink, "onDidLogMessage", serde_json::json!({"type": log_type, "message": message}));
+}
+
+/// Forward a throttled batch for the watcher registered under `id`: stamp the
+/// request's correlation id onto every change and account for dropped events.
+async fn emit_batch(
+    ipc_sink: &(dyn Fn(String) + Send + Sync),
+    watchers_instances: &RwLock<HashMap<String, WatcherInstance>>,
+    id: &str,
+    batch: ThrottledBatch,
+) {
+    let watchers_instances = watchers_instances.read().await;
+    let instance = match watchers_instances.get(id) {
+        Some(instance) => instance,
+        None => return, // Watcher removed
+    };
+
+    if batch.dropped > 0 {
+        *instance.drops_count.write().await += batch.dropped;
+        emit_log_message(ipc_sink, "warn", &format!(
+            "dropped {} file change events for {} because too many changes arrived at once. Use 'files.watcherExclude' setting to exclude folders with lots of changing files (e.g. compilation output).",
+            batch.dropped, id
+        ));
+    }
+    let (_, _, throttled_batches) = instance.throttler.get_stats();
+    *instance.throttled_batches_count.write().await = throttled_batches;
+
+    if batch.changes.is_empty() {
+        return;
+    }
+    let correlation_id = instance.request.correlation_id;
+    let changes = batch.changes.into_iter().map(|mut change| {
+        change.correlation_id = correlation_id;
+        change
+    }).collect();
+    emit_changes(ipc_sink, changes);
+}
+
+fn create_coalescer(request: &WatchRequest) -> Result<EventCoalescer, WatcherError> {
+    let excludes = request.excludes.clone();
+    let includes = request.includes.clone().unwrap_or_default();
+    EventCoalescer::new(excludes, includes).map_err(|e| WatcherError {
+        message: format!("Failed to create event coalescer: {}", e),
+        code: Some("COALESCER_ERROR".to_string()),
+    })
+}
+
 impl UniversalWatcher {
     pub fn new(ipc_sink: Arc<dyn Fn(String) + Send + Sync>) -> Self {
         let ipc_sink_clone = ipc_sink.clone();
         let suspender = WatcherSuspender::new(Arc::new(move |change: FileChange| {
-            let response = WatchResponse {
-                id: "suspension-monitor".to_string(),
-                changes: vec![change],
-            };
-            let json = serde_json::to_string(&response).unwrap();
-            let encoded = BASE64_STANDARD.encode(json);
-            let message = format!("{{\"method\":\"onDidChangeFile\",\"params\":{}}}", encoded);
-            ipc_sink_clone(message);
+            emit_changes(ipc_sink_clone.as_ref(), vec![change]);
         }));
 
         UniversalWatcher {
@@ -73,250 +146,266 @@ impl UniversalWatcher {
         }
     }
 
+    /// Bring the active watchers in line with `requests`. Watchers whose path
+    /// is still requested keep running and are updated in place, only watchers
+    /// that are no longer requested are stopped, and only new paths are crawled.
     pub async fn watch(&mut self, requests: Vec<WatchRequest>) -> Result<(), WatcherError> {
-        // Abort existing tasks and clear watchers
-        {
-            let mut tasks = self.tasks.write().await;
-            for (_id, handle) in tasks.drain() {
-                handle.abort();
+        // Use path as key for deduplication, the last request for a path wins
+        let mut requested: Vec<WatchRequest> = Vec::new();
+        for request in requests {
+            match requested.iter_mut().find(|r| r.path == request.path) {
+                Some(existing) => *existing = request,
+                None => requested.push(request),
             }
         }
-        self.watchers_instances.write().await.clear();
-
-        let mut watchers_to_spawn = Vec::new();
 
-        for request in requests {
-            let path_str = request.path.clone();
-
-            // Use path as key for deduplication
-            if self.watchers_instances.read().await.contains_key(&path_str) {
-                // Update existing watcher atomically
-                let mut watchers_instances = self.watchers_instances.write().await;
-                if let Some(existing_instance) = watchers_instances.get_mut(&path_str) {
-                    existing_instance.request = request.clone();
-                    // TODO: Update coalescer, throttler, and watcher if needed
-                    continue;
+        // Stop watchers that are no longer requested or need a different kind of watcher
+        let stale: Vec<String> = self.watchers_instances.read().await.iter()
+            .filter(|(path, instance)| match requested.iter().find(|r| &r.path == *path) {
+                Some(request) => {
+                    request.recursive != instance.request.recursive
+                        || request.polling_interval != instance.request.polling_interval
                 }
+                None => true,
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in stale {
+            self.stop_watcher(&path).await;
+        }
+
+        // A failing request does not keep the others from being applied
+        let mut first_error = None;
+        for request in requested {
+            let active = self.watchers_instances.read().await.contains_key(&request.path);
+            let result = if active {
+                self.update_watcher(request).await
+            } else {
+                self.start_watcher(request).await
+            };
+            if let Err(e) = result {
+                first_error.get_or_insert(e);
             }
+        }
 
-            let path_buf = crate::services::watcher::types::file_uri_to_pathbuf(&path_str)
-                .map_err(|e| WatcherError {
-                    message: format!("Invalid file URI: {}", e),
-                    code: Some("INVALID_URI".to_string()),
-                })?;
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
 
-            // Check if suspended
-            if self.suspender.is_suspended(&path_buf) {
-                // Try to resume if path exists
-                self.suspender.check_resurrection(&path_buf, request.correlation_id);
-
-                // If still suspended after check, return error
-                if self.suspender.is_suspended(&path_buf) {
-                    return Err(WatcherError {
-                        message: format!("Watcher for path {} is suspended due to failures", path_buf.display()),
-                        code: Some("SUSPENDED".to_string()),
-                    });
-                }
-            }
+    /// Apply a changed request to a running watcher without restarting it
+    async fn update_watcher(&mut self, request: WatchRequest) -> Result<(), WatcherError> {
+        let mut watchers_instances = self.watchers_instances.write().await;
+        let instance = match watchers_instances.get_mut(&request.path) {
+            Some(instance) => instance,
+            None => return Ok(()),
+        };
 
-            // Set correlation ID for this path if provided
-            if let Some(correlation_id) = request.correlation_id {
-                self.suspender.set_correlation_id(&path_buf, Some(correlation_id));
+        if instance.request.excludes != request.excludes || instance.request.includes != request.includes {
+            instance.coalescer = create_coalescer(&request)?;
+        }
+        if instance.request.correlation_id != request.correlation_id {
+            if let Ok(path_buf) = file_uri_to_pathbuf(&request.path) {
+                self.suspender.set_correlation_id(&path_buf, request.correlation_id);
             }
+        }
+        instance.request = request;
+        Ok(())
+    }
 
-            // Create coalescer
-            let excludes = request.excludes.clone();
-            let includes = request.includes.clone().unwrap_or_default();
-            let coalescer = EventCoalescer::new(excludes, includes)
-                .map_err(|e| WatcherError {
-                    message: format!("Failed to create event coalescer: {}", e),
-                    code: Some("COALESCER_ERROR".to_string()),
-                })?;
+    async fn start_watcher(&mut self, request: WatchRequest) -> Result<(), WatcherError> {
+        let path = request.path.clone();
 
-            // Create throttler based on recursive flag
-            // Use log_sink for IPC logging to onDidLogMessage
-            let ipc_sink_clone = self.ipc_sink.clone();
-            let log_sink: Option<Arc<dyn Fn(String) + Send + Sync>> = Some(Arc::new(move |msg: String| {
-                ipc_sink_clone(format!("{{\"method\":\"onDidLogMessage\",\"params\":{{\"type\":\"warn\",\"message\":\"{}\"}}}}", msg));
-            }));
-            let throttler = if request.recursive {
-                recursive_throttler(log_sink)
-            } else {
-                non_recursive_throttler(log_sink)
-            };
+        let path_buf = file_uri_to_pathbuf(&request.path)
+            .map_err(|e| WatcherError {
+                message: format!("Invalid file URI: {}", e),
+                code: Some("INVALID_URI".to_string()),
+            })?;
 
-            // Create watcher
-            let watcher = if request.recursive {
-                let watcher = RecursiveWatcher::new(path_buf.clone()).map_err(|e| WatcherError {
-                    message: format!("Failed to create recursive watcher: {:?}", e),
-                    code: Some("WATCHER_ERROR".to_string()),
-                })?;
-                WatcherType::Recursive(watcher)
-            } else {
-                let watcher = NonRecursiveWatcher::new(path_buf.clone()).map_err(|e| WatcherError {
-                    message: format!("Failed to create non-recursive watcher: {:?}", e),
-                    code: Some("WATCHER_ERROR".to_string()),
-                })?;
-                WatcherType::NonRecursive(watcher)
-            };
+        // Check if suspended
+        if self.suspender.is_suspended(&path_buf) {
+            // Try to resume if path exists
+            self.suspender.check_resurrection(&path_buf, request.correlation_id);
 
-            let drops_count = Arc::new(RwLock::new(0));
-            let throttled_batches_count = Arc::new(RwLock::new(0));
-
-            let watcher_type = if request.recursive {
-                WatcherTypeEnum::Recursive
-            } else {
-                WatcherTypeEnum::NonRecursive
-            };
+            // If still suspended after check, return error
+            if self.suspender.is_suspended(&path_buf) {
+                return Err(WatcherError {
+                    message: format!("Watcher for path {} is suspended due to failures", path_buf.display()),
+                    code: Some("SUSPENDED".to_string()),
+                });
+            }
+        }
 
-            let instance = WatcherInstance {
-                request: request.clone(),
-                watcher_type,
-                coalescer,
-                throttler,
-                drops_count: drops_count.clone(),
-                throttled_batches_count: throttled_batches_count.clone(),
-            };
+        // Set correlation ID for this path if provided
+        if let Some(correlation_id) = request.correlation_id {
+            self.suspender.set_correlation_id(&path_buf, Some(correlation_id));
+        }
 
-            // Store the instance using path as key
-            self.watchers_instances.write().await.insert(path_str.clone(), instance);
+        let coalescer = create_coalescer(&request)?;
 
-            // Collect watcher to spawn task
-            watchers_to_spawn.push((path_str.clone(), watcher));
-        }
+        // Create throttler based on recursive flag
+        // Use log_sink for IPC logging to onDidLogMessage
+        let ipc_sink_clone = self.ipc_sink.clone();
+        let log_sink: Option<Arc<dyn Fn(String) + Send + Sync>> = Some(Arc::new(move |msg: String| {
+            emit_log_message(ipc_sink_clone.as_ref(), "warn", &msg);
+        }));
+        let throttler = Arc::new(if request.recursive {
+            recursive_throttler(log_sink)
+        } else {
+            non_recursive_throttler(log_sink)
+        });
+
+        // Poll when asked to, or when native events would never arrive
+        let polling_interval = match request.polling_interval {
+            Some(interval) if interval > 0 => Some(Duration::from_millis(interval as u64)),
+            _ if requires_polling(&path_buf) => Some(DEFAULT_POLLING_INTERVAL),
+            _ => None,
+        };
+
+        // Create watcher
+        let watcher = if let Some(interval) = polling_interval {
+            // The initial scan of a large or remote tree can take a while, so
+            // it runs off the async runtime
+            let (root, recursive, excludes) = (path_buf.clone(), request.recursive, request.excludes.clone());
+            let watcher = tokio::task::spawn_blocking(move || PollingWatcher::new(root, recursive, interval, &excludes))
+                .await
+                .map_err(std::io::Error::other)
+                .and_then(|watcher| watcher)
+                .map_err(|e| WatcherError {
+                    message: format!("Failed to create polling watcher: {:?}", e),
+                    code: Some("WATCHER_ERROR".to_string()),
+                })?;
+            WatcherType::Polling(watcher)
+        } else if request.recursive {
+            let watcher = RecursiveWatcher::new(path_buf.clone()).map_err(|e| WatcherError {
+                message: format!("Failed to create recursive watcher: {:?}", e),
+                code: Some("WATCHER_ERROR".to_string()),
+            })?;
+            WatcherType::Recursive(watcher)
+        } else {
+            let watcher = NonRecursiveWatcher::new(path_buf.clone()).map_err(|e| WatcherError {
+                message: format!("Failed to create non-recursive watcher: {:?}", e),
+                code: Some("WATCHER_ERROR".to_string()),
+            })?;
+            WatcherType::NonRecursive(watcher)
+        };
+
+        let drops_count = Arc::new(RwLock::new(0));
+        let throttled_batches_count = Arc::new(RwLock::new(0));
+
+        let watcher_type = if request.recursive {
+            WatcherTypeEnum::Recursive
+        } else {
+            WatcherTypeEnum::NonRecursive
+        };
+
+        let instance = WatcherInstance {
+            request,
+            watcher_type,
+            polling: polling_interval.is_some(),
+            coalescer,
+            throttler: throttler.clone(),
+            drops_count: drops_count.clone(),
+            throttled_batches_count: throttled_batches_count.clone(),
+        };
+
+        // Store the instance using path as key
+        self.watchers_instances.write().await.insert(path.clone(), instance);
 
-        // Clone necessary data for tasks
         let ipc_sink = self.ipc_sink.clone();
-        let watchers_instances_clone = self.watchers_instances.clone();
-        let resurrection_check_interval_clone = self.resurrection_check_interval;
-        let suspender_clone = Arc::new(Mutex::new(self.suspender.clone()));
-
-        // Spawn background tasks for each watcher
-        for (path, watcher) in watchers_to_spawn {
-            let ipc_sink_clone = ipc_sink.clone();
-            let path_for_task = path.clone();
-
-            let resurrection_check_interval = resurrection_check_interval_clone.clone();
-            let suspender_clone = suspender_clone.clone();
-
-            let watchers_instances_clone_for_task = watchers_instances_clone.clone();
-
-            let task = tokio::task::spawn(async move {
-                let mut resurrection_check_timer = tokio::time::interval(resurrection_check_interval);
-
-                loop {
-                    tokio::select! {
-                        _ = tokio::time::sleep(Duration::from_millis(100)) => {
-                            // Normal polling
-                            let watchers_instances_read = watchers_instances_clone_for_task.read().await;
-                            let instance_opt = watchers_instances_read.get(&path_for_task);
-                            let instance = match instance_opt {
-                                Some(inst) => inst,
-                                None => return, // Watcher removed
-                            };
-
-                            let raw_events = match &watcher {
-                                WatcherType::Recursive(w) => match w.try_recv() {
-                                    Ok(events) => events,
-                                    Err(_) => {
-                                        // Record failure on receive error
-                                        let path = crate::services::watcher::types::file_uri_to_pathbuf(&instance.request.path).unwrap_or_default();
-                                        {
-                                            let mut suspender = suspender_clone.lock().await;
-                                            suspender.record_failure(&path);
-                                        }
-                                        continue;
-                                    },
-                                },
-                                WatcherType::NonRecursive(w) => match w.try_recv() {
-                                    Ok(events) => events,
-                                    Err(_) => {
-                                        // Record failure on receive error
-                                        let path = crate::services::watcher::types::file_uri_to_pathbuf(&instance.request.path).unwrap_or_default();
-                                        {
-                                            let mut suspender = suspender_clone.lock().await;
-                                            suspender.record_failure(&path);
-                                        }
-                                        continue;
-                                    },
-                                },
-                            };
-
-                            let coalesced = instance.coalescer.coalesce_events(raw_events);
-                            if !coalesced.is_empty() {
-                                // Send to throttler
-                                let mut drops = 0u64;
-                                for event in coalesced {
-                                    if let Err(_) = instance.throttler.send(event) {
-                                        drops += 1;
-                                    }
-                                }
-                                *instance.drops_count.write().await += drops;
-
-                                // Get throttled events - worker pulls from throttler, stamps cId, sends to IPC
-                                // Note: The worker in background tasks pulls from throttler and sends to IPC
-                                // So we don't need to pull events here anymore - the worker handles it
-                                let changes: Vec<FileChange> = Vec::new(); // Placeholder to fix compilation
-                                let _batch_count = 0u64;   // Placeholder to fix compilation
-
-                                if !changes.is_empty() {
-                                    // Stamp correlation ID onto all changes
-                                    let mut stamped_changes = Vec::new();
-                                    for mut change in changes {
-                                        change.correlation_id = instance.request.correlation_id;
-                                        stamped_changes.push(change);
-                                    }
-
-                                    let response = WatchResponse {
-                                        id: path_for_task.clone(),
-                                        changes: stamped_changes,
-                                    };
-
-                                    let json = serde_json::to_string(&response).unwrap();
-                                    let encoded = BASE64_STANDARD.encode(json);
-                                    let message = format!("{{\"method\":\"onDidChangeFile\",\"params\":{}}}", encoded);
-                                    ipc_sink_clone(message);
-                                }
+        let watchers_instances = self.watchers_instances.clone();
+        let resurrection_check_interval = self.resurrection_check_interval;
+        let suspender = Arc::new(Mutex::new(self.suspender.clone()));
+        let id = path.clone();
+
+        let task = tokio::task::spawn(async move {
+            let mut resurrection_check_timer = tokio::time::interval(resurrection_check_interval);
+
+            loop {
+                tokio::select! {
+                    Some(batch) = throttler.recv() => {
+                        emit_batch(ipc_sink.as_ref(), &watchers_instances, &id, batch).await;
+                    }
+                    _ = tokio::time::sleep(Duration::from_millis(100)) => {
+                        // Normal polling
+                        let watchers_instances_read = watchers_instances.read().await;
+                        let instance_opt = watchers_instances_read.get(&id);
+                        let instance = match instance_opt {
+                            Some(inst) => inst,
+                            None => return, // Watcher removed
+                        };
+
+                        // Drain everything the watcher produced since the last poll
+                        let mut raw_events = Vec::new();
+                        let disconnected = loop {
+                            match watcher.try_recv() {
+                                Ok(events) => raw_events.extend(events),
+                                Err(TryRecvError::Empty) => break false,
+                                Err(TryRecvError::Disconnected) => break true,
                             }
+                        };
+                        if disconnected {
+                            // Record failure when the underlying watcher went away
+                            let path = file_uri_to_pathbuf(&instance.request.path).unwrap_or_default();
+                            {
+                                let mut suspender = suspender.lock().await;
+                                suspender.record_failure(&path);
+                            }
+                            continue;
+                        }
+
+                        // Events the throttler cannot buffer are counted there and
+                        // reported with its next batch
+                        for event in instance.coalescer.coalesce_events(raw_events) {
+                            let _ = instance.throttler.send(event);
                         }
-                        _ = resurrection_check_timer.tick() => {
-                            // Check for path resurrection
-                            let watchers_instances_read = watchers_instances_clone_for_task.read().await;
-                            let instance_opt = watchers_instances_read.get(&path_for_task);
-                            if let Some(instance) = instance_opt {
-                                let path = crate::services::watcher::types::file_uri_to_pathbuf(&instance.request.path).unwrap_or_default();
-                                let mut suspender = suspender_clone.lock().await;
-                                let resurrected = suspender.check_resurrection(&path, instance.request.correlation_id);
-                                if resurrected {
-                                    // Path has been resurrected, restart watcher if needed
-                                    // The resurrection event has already been emitted
-                                }
+                    }
+                    _ = resurrection_check_timer.tick() => {
+                        // Check for path resurrection
+                        let watchers_instances_read = watchers_instances.read().await;
+                        let instance_opt = watchers_instances_read.get(&id);
+                        if let Some(instance) = instance_opt {
+                            let path = file_uri_to_pathbuf(&instance.request.path).unwrap_or_default();
+                            let mut suspender = suspender.lock().await;
+                            let resurrected = suspender.check_resurrection(&path, instance.request.correlation_id);
+                            if resurrected {
+                                // Path has been resurrected, restart watcher if needed
+                                // The resurrection event has already been emitted
                             }
                         }
                     }
                 }
-            });
+            }
+        });
 
-            self.tasks.write().await.insert(path, task);
+        if let Some(previous) = self.tasks.write().await.insert(path, task) {
+            previous.abort();
         }
 
         Ok(())
     }
 
+    /// Stop a watcher and forget about it. Returns whether it was active.
+    async fn stop_watcher(&self, id: &str) -> bool {
+        let removed = self.watchers_instances.write().await.remove(id).is_some();
+
+        // Abort the corresponding task
+        if let Some(task) = self.tasks.write().await.remove(id) {
+            task.abort();
+        }
+
+        removed
+    }
+
     pub async fn unwatch(&self, id: &str) -> Result<(), WatcherError> {
-        let mut watchers_instances = self.watchers_instances.write().await;
-        if watchers_instances.remove(id).is_none() {
+        if !self.stop_watcher(id).await {
             return Err(WatcherError {
                 message: format!("Watcher with id {} not found", id),
                 code: Some("NOT_FOUND".to_string()),
             });
         }
 
-        // Abort the corresponding task
-        if let Some(task) = self.tasks.write().await.remove(id) {
-            task.abort();
-        }
-
         Ok(())
     }
 
@@ -337,6 +426,9 @@ impl UniversalWatcher {
             .count();
         stats.insert("non_recursive_watchers".to_string(), serde_json::json!(non_recursive_count));
 
+        let polling_count = watchers.values().filter(|w| w.polling).count();
+        stats.insert("polling_watchers".to_string(), serde_json::json!(polling_count));
+
         let suspended_paths = self.suspender.suspended_paths();
         stats.insert("suspended_watchers".to_string(), serde_json::json!(suspended_paths.len()));
 
@@ -469,4 +561,100 @@ mod tests {
         assert_eq!(stats["recursive_watchers"], 1);
         assert_eq!(stats["non_recursive_watchers"], 0);
     }
-}
\ No newline at end of file
+
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn test_changes_reach_ipc_with_correlation_id() {
+        let messages = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink_messages = messages.clone();
+        let ipc_sink = Arc::new(move |msg: String| sink_messages.lock().unwrap().push(msg));
+        let mut service = UniversalWatcher::new(ipc_sink);
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let uri = crate::services::watcher::types::pathbuf_to_file_uri(root.clone()).unwrap();
+
+        let requests = vec![WatchRequest {
+            path: uri,
+            excludes: vec![],
+            includes: None,
+            recursive: true,
+            correlation_id: Some(7),
+            filter: None,
+            polling_interval: None,
+        }];
+        service.watch(requests).await.unwrap();
+        std::fs::write(root.join("created.txt"), "content").unwrap();
+
+        let mut changes = Vec::new();
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            for message in messages.lock().unwrap().drain(..) {
+                let decoded = BASE64_STANDARD.decode(message).unwrap();
+                let notification: serde_json::Value = serde_json::from_slice(&decoded).unwrap();
+                if notification["method"] == "onDidChangeFile" {
+                    let batch: Vec<FileChange> = serde_json::from_value(notification["params"].clone()).unwrap();
+                    changes.extend(batch);
+                }
+            }
+            if !changes.is_empty() {
+                break;
+            }
+        }
+
+        assert!(changes.iter().any(|change| change.resource.ends_with("/created.txt")));
+        assert!(changes.iter().all(|change| change.correlation_id == Some(7)));
+        service.stop().await.unwrap();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn test_incremental_watch() {
+        let ipc_sink = Arc::new(|_msg: String| {});
+        let mut service = UniversalWatcher::new(ipc_sink);
+        let first_dir = tempdir().unwrap();
+        let second_dir = tempdir().unwrap();
+        let request = |dir: &tempfile::TempDir, recursive: bool, correlation_id: Option<u32>| WatchRequest {
+            path: crate::services::watcher::types::pathbuf_to_file_uri(dir.path().to_path_buf()).unwrap(),
+            excludes: vec![],
+            includes: None,
+            recursive,
+            correlation_id,
+            filter: None,
+            polling_interval: None,
+        };
+        let first = request(&first_dir, true, None).path;
+        let throttler_of = |service: &UniversalWatcher, path: &str| {
+            service.watchers_instances.try_read().unwrap()[path].throttler.clone()
+        };
+
+        service.watch(vec![request(&first_dir, true, None)]).await.unwrap();
+        let original = throttler_of(&service, &first);
+
+        // Adding a folder keeps the running watcher and updates it in place
+        let mut updated = request(&first_dir, true, Some(3));
+        updated.excludes = vec!["**/node_modules/**".to_string()];
+        service.watch(vec![updated, request(&second_dir, true, None)]).await.unwrap();
+        assert!(Arc::ptr_eq(&original, &throttler_of(&service, &first)));
+        assert_eq!(service.watchers_instances.read().await[&first].request.correlation_id, Some(3));
+        assert_eq!(service.tasks.read().await.len(), 2);
+
+        // Switching to non-recursive needs a new watcher, dropped paths are stopped
+        service.watch(vec![request(&first_dir, false, Some(3))]).await.unwrap();
+        assert!(!Arc::ptr_eq(&original, &throttler_of(&service, &first)));
+        let stats = service.get_stats().await;
+        assert_eq!(stats["total_watchers"], 1);
+        assert_eq!(stats["non_recursive_watchers"], 1);
+        assert_eq!(service.tasks.read().await.len(), 1);
+
+        // So does switching to polling
+        let mut polled = request(&first_dir, false, Some(3));
+        polled.polling_interval = Some(50);
+        service.watch(vec![polled]).await.unwrap();
+        assert!(service.watchers_instances.read().await[&first].polling);
+        assert_eq!(service.get_stats().await["polling_watchers"], 1);
+
+        service.unwatch(&first).await.unwrap();
+        assert!(service.tasks.read().await.is_empty());
+        assert!(service.unwatch(&first).await.is_err());
+    }
+}
@@ -5,9 +5,9 @@
 
 use crate::services::watcher::types::FileChange;
 use std::collections::VecDeque;
-use std::sync::atomic::{AtomicUsize, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Notify};
 use tokio::time::{self, Duration};
 
 #[derive(Clone)]
@@ -17,101 +17,162 @@ pub struct ThrottlerConfig {
     pub throttle_delay: Duration,
 }
 
-pub struct EventThrottler {
+/// A chunk of changes handed out by the throttler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThrottledBatch {
+    pub changes: Vec<FileChange>,
+    /// Number of events dropped because the buffer was full since the previous batch
+    pub dropped: u64,
+}
+
+type LogSink = Option<Arc<dyn Fn(String) + Send + Sync>>;
+
+/// Shared between the throttler handle and its worker task
+struct ThrottlerState {
     buffer: Mutex<VecDeque<FileChange>>,
+    wakeup: Notify,
+    pending: AtomicUsize,
+    dropped: AtomicU64,
+    unreported_drops: AtomicU64,
+    throttled_batches: AtomicU64,
+    overflowing: AtomicBool,
+}
+
+/// Buffers incoming changes and hands them out in chunks of at most
+/// `_chunk_size`, waiting `throttle_delay` between chunks while a backlog
+/// remains. Once `max_buffered` events are waiting, new events are dropped
+/// and reported with the next batch.
+pub struct EventThrottler {
+    state: Arc<ThrottlerState>,
     max_buffered: usize,
-    _chunk_size: usize,
-    _throttle_delay: Duration,
-    log_sink: Option<Arc<dyn Fn(String) + Send + Sync>>,
-    pending: Arc<AtomicUsize>,
-    dropped: Arc<AtomicU64>,
-    throttled_batches: Arc<AtomicU64>,
-    output_tx: mpsc::Sender<Vec<FileChange>>,
+    log_sink: LogSink,
+    output_rx: tokio::sync::Mutex<mpsc::Receiver<ThrottledBatch>>,
     worker_handle: Option<tokio::task::JoinHandle<()>>,
 }
 
 impl EventThrottler {
-    pub fn new(config: ThrottlerConfig, log_sink: Option<Arc<dyn Fn(String) + Send + Sync>>) -> Self {
-        let (output_tx, _output_rx) = mpsc::channel(100); // buffered channel
+    pub fn new(config: ThrottlerConfig, log_sink: LogSink) -> Self {
+        let (output_tx, output_rx) = mpsc::channel(100); // buffered channel
 
-        let buffer = Mutex::new(VecDeque::new());
-        let pending = Arc::new(AtomicUsize::new(0));
-        let dropped = Arc::new(AtomicU64::new(0));
-        let throttled_batches = Arc::new(AtomicU64::new(0));
+        let state = Arc::new(ThrottlerState {
+            buffer: Mutex::new(VecDeque::new()),
+            wakeup: Notify::new(),
+            pending: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            unreported_drops: AtomicU64::new(0),
+            throttled_batches: AtomicU64::new(0),
+            overflowing: AtomicBool::new(false),
+        });
 
         let worker_handle = {
-            let pending = pending.clone();
+            let state = state.clone();
+            let config = config.clone();
             let log_sink = log_sink.clone();
             tokio::spawn(async move {
-                Self::throttling_worker_async(
-                    pending,
-                    log_sink,
-                ).await;
+                Self::throttling_worker_async(state, config, output_tx, log_sink).await;
             })
         };
 
         EventThrottler {
-            buffer,
+            state,
             max_buffered: config.max_buffered,
-            _chunk_size: config._chunk_size,
-            _throttle_delay: config.throttle_delay,
             log_sink,
-            pending,
-            dropped,
-            throttled_batches,
-            output_tx,
+            output_rx: tokio::sync::Mutex::new(output_rx),
             worker_handle: Some(worker_handle),
         }
     }
 
     pub fn send(&self, event: FileChange) -> Result<(), ()> {
-        let mut buffer = self.buffer.lock().unwrap();
-        if buffer.len() >= self.max_buffered {
-            self.dropped.fetch_add(1, Ordering::Relaxed);
-            if let Some(ref sink) = self.log_sink {
-                sink(format!("started ignoring events due to too many file changes (buffer: {}, max: {})", buffer.len(), self.max_buffered));
+        {
+            let mut buffer = self.state.buffer.lock().unwrap();
+            if buffer.len() >= self.max_buffered {
+                self.state.dropped.fetch_add(1, Ordering::Relaxed);
+                self.state.unreported_drops.fetch_add(1, Ordering::Relaxed);
+                // Only log when we start dropping, not for every dropped event
+                if !self.state.overflowing.swap(true, Ordering::Relaxed) {
+                    if let Some(ref sink) = self.log_sink {
+                        sink(format!("started ignoring events due to too many file changes (buffer: {}, max: {})", buffer.len(), self.max_buffered));
+                    }
+                }
+                return Err(());
             }
-            return Err(());
+            self.state.overflowing.store(false, Ordering::Relaxed);
+            buffer.push_back(event);
+            self.state.pending.fetch_add(1, Ordering::Relaxed);
         }
-        buffer.push_back(event);
-        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.state.wakeup.notify_one();
         Ok(())
     }
 
+    /// Receive the next batch, or `None` once the worker has stopped
+    pub async fn recv(&self) -> Option<ThrottledBatch> {
+        self.output_rx.lock().await.recv().await
+    }
+
     /// Receive a batch with timeout
-    pub fn recv_timeout(&self, _timeout: Duration) -> Option<Vec<FileChange>> {
-        // This would need to be implemented using the mpsc receiver
-        // For now, return None to indicate not implemented
-        None
+    pub async fn recv_timeout(&self, timeout: Duration) -> Option<ThrottledBatch> {
+        time::timeout(timeout, self.recv()).await.ok().flatten()
     }
 
     pub fn get_stats(&self) -> (usize, u64, u64) {
         (
-            self.pending.load(Ordering::Relaxed),
-            self.dropped.load(Ordering::Relaxed),
-            self.throttled_batches.load(Ordering::Relaxed),
+            self.state.pending.load(Ordering::Relaxed),
+            self.state.dropped.load(Ordering::Relaxed),
+            self.state.throttled_batches.load(Ordering::Relaxed),
         )
     }
 
+    /// Reset the drop and throttling counters. Pending events are unaffected.
     pub fn reset_stats(&self) {
-        self.pending.store(0, Ordering::Relaxed);
-        self.dropped.store(0, Ordering::Relaxed);
-        self.throttled_batches.store(0, Ordering::Relaxed);
+        self.state.dropped.store(0, Ordering::Relaxed);
+        self.state.throttled_batches.store(0, Ordering::Relaxed);
     }
 
     async fn throttling_worker_async(
-        pending: Arc<AtomicUsize>,
-        log_sink: Option<Arc<dyn Fn(String) + Send + Sync>>,
+        state: Arc<ThrottlerState>,
+        config: ThrottlerConfig,
+        output_tx: mpsc::Sender<ThrottledBatch>,
+        log_sink: LogSink,
     ) {
+        let chunk_size = config._chunk_size.max(1);
+        let mut throttling = false;
+
         loop {
-            // Check for throttling start logging
-            let current_pending = pending.load(Ordering::Relaxed);
-            if current_pending > 1000 {
-                if let Some(ref sink) = log_sink {
-                    sink(format!("started throttling events due to large amount of file changes (pending: {})", current_pending));
+            let (changes, remaining) = {
+                let mut buffer = state.buffer.lock().unwrap();
+                let count = buffer.len().min(chunk_size);
+                let changes: Vec<FileChange> = buffer.drain(..count).collect();
+                state.pending.fetch_sub(changes.len(), Ordering::Relaxed);
+                (changes, buffer.len())
+            };
+
+            if changes.is_empty() {
+                throttling = false;
+                state.wakeup.notified().await;
+                continue;
+            }
+
+            let batch = ThrottledBatch {
+                changes,
+                dropped: state.unreported_drops.swap(0, Ordering::Relaxed),
+            };
+            if output_tx.send(batch).await.is_err() {
+                return; // Receiver is gone
+            }
+
+            if remaining > 0 {
+                // More events than fit in one chunk: give the consumer a break
+                state.throttled_batches.fetch_add(1, Ordering::Relaxed);
+                if !throttling {
+                    throttling = true;
+                    if let Some(ref sink) = log_sink {
+                        sink(format!("started throttling events due to large amount of file changes (pending: {})", remaining));
+                    }
                 }
+                time::sleep(config.throttle_delay).await;
+            } else {
+                throttling = false;
             }
-            time::sleep(Duration::from_millis(10)).await;
         }
     }
 
@@ -136,21 +197,8 @@ impl EventThrottler {
 
 impl Drop for EventThrottler {
     fn drop(&mut self) {
-        // Flush remaining buffer
-        {
-            let mut buffer = self.buffer.lock().unwrap();
-            if !buffer.is_empty() {
-                // Create final chunk from remaining events
-                let mut chunk = Vec::new();
-                while let Some(event) = buffer.pop_front() {
-                    chunk.push(event);
-                }
-                // Try to send remaining events (fire and forget)
-                let _ = self.output_tx.try_send(chunk);
-            }
-        }
-
-        // Abort the worker task
+        // Abort the worker task; buffered events have nowhere to go once
+        // the receiver is dropped with us
         if let Some(handle) = self.worker_handle.take() {
             handle.abort();
         }
@@ -165,4 +213,75 @@ pub fn recursive_throttler(log_sink: Option<Arc<dyn Fn(String) + Send + Sync>>)
 /// Convenience function to create throttler for non-recursive watchers
 pub fn non_recursive_throttler(log_sink: Option<Arc<dyn Fn(String) + Send + Sync>>) -> EventThrottler {
     EventThrottler::new(EventThrottler::non_recursive_config(), log_sink)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::watcher::types::FileChangeType;
+
+    fn change(index: usize) -> FileChange {
+        FileChange {
+            resource: format!("file:///tmp/file{}.txt", index),
+            change_type: FileChangeType::Added,
+            correlation_id: None,
+            mtime: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_in_chunks() {
+        let config = ThrottlerConfig {
+            max_buffered: 100,
+            _chunk_size: 2,
+            throttle_delay: Duration::from_millis(20),
+        };
+        let throttler = EventThrottler::new(config, None);
+        for index in 0..5 {
+            throttler.send(change(index)).unwrap();
+        }
+
+        let mut received = Vec::new();
+        while received.len() < 5 {
+            let batch = throttler.recv_timeout(Duration::from_secs(5)).await.expect("batch");
+            assert!(batch.changes.len() <= 2);
+            received.extend(batch.changes);
+        }
+        assert_eq!(received, (0..5).map(change).collect::<Vec<_>>());
+        assert!(throttler.recv_timeout(Duration::from_millis(50)).await.is_none());
+
+        let (pending, dropped, throttled_batches) = throttler.get_stats();
+        assert_eq!((pending, dropped), (0, 0));
+        assert!(throttled_batches >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_drops_when_buffer_full() {
+        let config = ThrottlerConfig {
+            max_buffered: 3,
+            _chunk_size: 10,
+            throttle_delay: Duration::from_millis(20),
+        };
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let log_sink = {
+            let logs = logs.clone();
+            Some(Arc::new(move |message: String| logs.lock().unwrap().push(message)) as Arc<dyn Fn(String) + Send + Sync>)
+        };
+        let throttler = EventThrottler::new(config, log_sink);
+
+        // The worker only runs once we yield, so the buffer fills up first
+        let results: Vec<bool> = (0..5).map(|index| throttler.send(change(index)).is_ok()).collect();
+        assert_eq!(results, vec![true, true, true, false, false]);
+        assert_eq!(logs.lock().unwrap().len(), 1);
+
+        let batch = throttler.recv_timeout(Duration::from_secs(5)).await.expect("batch");
+        assert_eq!(batch.changes.len(), 3);
+        assert_eq!(batch.dropped, 2);
+        assert_eq!(throttler.get_stats().1, 2);
+
+        // Drops are reported once
+        throttler.send(change(5)).unwrap();
+        let batch = throttler.recv_timeout(Duration::from_secs(5)).await.expect("batch");
+        assert_eq!(batch.dropped, 0);
+    }
+}